@@ -1,13 +1,5 @@
-# IR生成
-echo "Generating LLVM IR..."
-cargo run
-
-# LLVM IRからアセンブリ言語へのコンパイル
-echo "Compiling LLVM IR to assembly..."
-llc -relocation-model=pic -filetype=asm output.ll -o output.s
-
-# アセンブリ言語から実行可能ファイルへのコンパイル
-echo "Compiling assembly to executable..."
-gcc -fPIE -pie output.s -o program
-
-echo "Build complete."
+# IR生成からリンクまで omochi が行う
+# 例: ./build.sh program.txt -o program
+#     ./build.sh main.omo helper.c -L./lib -lfoo
+echo "Building..."
+cargo run -- "$@" && echo "Build complete."
@@ -6,16 +6,26 @@ pub enum Token {
     If,                 // if文
     Else,               // Else
     Print,              // print文
+    Mod,                // `mod` モジュール宣言
+    Import,             // `import` 宣言
+    Pub,                // `pub` 公開指定
+    Extern,             // `extern` C関数の宣言
+    New,                // `new` ヒープ確保
     Identifier(String), // 識別子
     Integer(i64),       // 整数リテラル
+    Str(String),        // 文字列リテラル
     Plus,               // 加算演算子
     Minus,              // 減算演算子
     Equals,             // 等号
     DoubleEquals,       // 等値比較 `==`
     Asterisk,           // アスタリスク'*'
+    Slash,              // スラッシュ'/'
+    Ampersand,          // アンパサンド'&'
     Semicolon,          // セミコロン ;
     Colon,              // コロン :
+    DoubleColon,        // パス区切り `::`
     Comma,              // カンマ ,
+    Ellipsis,           // 可変長引数 `...`
     Then,               // then
     LeftParen,          // 左括弧 `(`
     RightParen,         // 右括弧 `)`
@@ -27,43 +37,209 @@ pub enum Token {
     EOF,                // 入力の終了
 }
 
+// ソースに書き戻すときの表記 (整数は10進数、文字列はエスケープし直す)
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Token::Identifier(name) => return write!(f, "{}", name),
+            Token::Integer(value) => return write!(f, "{}", value),
+            Token::Str(value) => {
+                write!(f, "\"")?;
+                for c in value.chars() {
+                    match c {
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\r' => write!(f, "\\r")?,
+                        '\0' => write!(f, "\\0")?,
+                        '\\' | '"' => write!(f, "\\{}", c)?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                return write!(f, "\"");
+            }
+            Token::Let => "let",
+            Token::Fn => "fn",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::Print => "print",
+            Token::Mod => "mod",
+            Token::Import => "import",
+            Token::Pub => "pub",
+            Token::Extern => "extern",
+            Token::New => "new",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Equals => "=",
+            Token::DoubleEquals => "==",
+            Token::Asterisk => "*",
+            Token::Slash => "/",
+            Token::Ampersand => "&",
+            Token::Semicolon => ";",
+            Token::Colon => ":",
+            Token::DoubleColon => "::",
+            Token::Comma => ",",
+            Token::Ellipsis => "...",
+            Token::Then => "then",
+            Token::LeftParen => "(",
+            Token::RightParen => ")",
+            Token::Arrow => "->",
+            Token::LeftBrace => "{",
+            Token::RightBrace => "}",
+            Token::MoreThan => ">",
+            Token::LessThan => "<",
+            Token::EOF => "",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+impl Token {
+    // 値の終わりになるトークンかどうか (直後の `-` や `*` は二項演算子になる)
+    pub fn ends_operand(&self) -> bool {
+        matches!(
+            self,
+            Token::Identifier(_) | Token::Integer(_) | Token::Str(_) | Token::RightParen
+        )
+    }
+}
+
+// ソース上の位置 (1始まりの行と列)
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub file: usize, // ソースファイルの番号 (0 はエントリファイル、子モジュールはローダーが読み込んだ順)
+}
+
+impl Span {
+    // エントリファイル上の位置
+    pub fn new(line: usize, column: usize) -> Self {
+        Span {
+            line,
+            column,
+            file: 0,
+        }
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
 // 式を表す列挙型
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expr {
     Integer(i64),
-    BinaryOp(Box<Expr>, Operator, Box<Expr>),
-    Variable(String),
+    Str(String),
+    BinaryOp(Box<Expr>, Operator, Box<Expr>, Span), // Span は演算子の位置
+    Variable(String, Span),
     Assign(String, Box<Expr>),
     If(Box<Expr>, Box<Statement>, Option<Box<Statement>>),
-    FunctionCall(String, Vec<Expr>),
+    FunctionCall(String, Vec<Expr>, Span),
+    AddressOf(String),      // `&x` 変数のアドレス
+    Deref(Box<Expr>, Span), // `*p` ポインタの参照先の読み出し (Span は `*` の位置)
+    New(Box<Expr>),         // `new(value)` malloc で確保した領域に値を格納する
 }
 
 // 文を表す列挙型
 #[derive(Debug, PartialEq, Clone)]
 pub enum Statement {
     Expression(Expr),
-    Declaration(String, Expr),
-    Assignment(String, Expr),
+    Declaration(String, Expr, Span), // Span は変数名の位置
+    Assignment(String, Expr, Span),
+    DerefAssignment(Expr, Expr, Span), // `*p = value;` (Span は `*` の位置)
     Print(Expr),
     Block(Vec<Statement>),
-    If(Box<Expr>, Box<Statement>, Option<Box<Statement>>),
+    If(Box<Expr>, Box<Statement>, Option<Box<Statement>>, Span), // Span は `if` の位置
     Function(Function),
+    Mod(String), // `mod name;` 同じディレクトリの name.omo を子モジュールとして読み込む
+    Import(Vec<String>), // `import a::b;` 関数やモジュールを修飾なしで使えるようにする
+    Extern(ExternFunction),
+}
+
+impl Statement {
+    // 文の位置 (位置を持たない文は None)
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Statement::Declaration(_, _, span)
+            | Statement::Assignment(_, _, span)
+            | Statement::If(_, _, _, span) => Some(*span),
+            Statement::Expression(expr) | Statement::Print(expr) => expr.span(),
+            Statement::DerefAssignment(_, _, span) => Some(*span),
+            _ => None,
+        }
+    }
+}
+
+impl Expr {
+    // 式の中で一番左にある位置
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Expr::BinaryOp(left, _, _, span) => left.span().or(Some(*span)),
+            Expr::Variable(_, span) | Expr::FunctionCall(_, _, span) => Some(*span),
+            Expr::Deref(_, span) => Some(*span),
+            Expr::Assign(_, value) | Expr::New(value) => value.span(),
+            Expr::If(condition, _, _) => condition.span(),
+            _ => None,
+        }
+    }
 }
 
 // 関数を表す構造体
 #[derive(Debug, PartialEq, Clone)]
 pub struct Function {
     pub name: String,
+    pub span: Span, // 関数名の位置
+    pub is_pub: bool,
     pub params: Vec<(String, Type)>,
+    pub param_spans: Vec<Span>, // 各引数の名前の位置
     pub return_type: Type,
     pub body: Vec<Statement>,
     pub return_expr: Expr,
 }
 
+// `extern fn` で宣言されたC関数を表す構造体
+#[derive(Debug, PartialEq, Clone)]
+pub struct ExternFunction {
+    pub name: String,
+    pub is_pub: bool,
+    pub params: Vec<(String, Type)>,
+    pub variadic: bool, // 最後の引数が `...`
+    pub return_type: Type,
+}
+
 // 型を表す列挙型
 #[derive(Debug, PartialEq, Clone)]
 pub enum Type {
-    Int,
+    Int,                  // C の int
+    Str,                  // C の char *
+    String,               // 参照カウント付きのヒープ文字列
+    Void,                 // 戻り値なし
+    Pointer(Box<Type>),   // `*T`
+    Reference(Box<Type>), // `&T` (`new` で確保した参照カウント付きの値)
+}
+
+// ソースコードと同じ表記で表示する
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Str => write!(f, "str"),
+            Type::String => write!(f, "string"),
+            Type::Void => write!(f, "void"),
+            Type::Pointer(inner) => write!(f, "*{}", inner),
+            Type::Reference(inner) => write!(f, "&{}", inner),
+        }
+    }
+}
+
+impl Type {
+    // 参照カウントで管理される型かどうか (`str` と `*T` の値は管理されない)
+    pub fn is_managed(&self) -> bool {
+        matches!(self, Type::String | Type::Reference(_))
+    }
 }
 
 // 演算子を表す列挙型
@@ -73,6 +249,22 @@ pub enum Operator {
     Minus,
     Equals,
     Multiply,
+    Divide,
     MoreThan,
     LessThan,
 }
+
+impl std::fmt::Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            Operator::Plus => "+",
+            Operator::Minus => "-",
+            Operator::Equals => "==",
+            Operator::Multiply => "*",
+            Operator::Divide => "/",
+            Operator::MoreThan => ">",
+            Operator::LessThan => "<",
+        };
+        write!(f, "{}", symbol)
+    }
+}
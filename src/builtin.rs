@@ -0,0 +1,63 @@
+use crate::ast::{Operator, Type};
+
+// 宣言しなくても呼び出せる組み込み関数 (`wrapping_add(a, b)` など)
+// 同じ名前の関数が定義されていればそちらが優先される
+#[derive(Debug, PartialEq, Clone)]
+pub enum Builtin {
+    Wrapping(Operator),   // 32bit の2の補数で折り返す
+    Saturating(Operator), // int の最大値・最小値で止める
+    Assert,               // `assert(condition)` 条件が 0 ならパニックする
+    Panic,                // `panic(message)` メッセージを表示してパニックする
+}
+
+pub fn lookup(name: &str) -> Option<Builtin> {
+    match name {
+        "assert" => return Some(Builtin::Assert),
+        "panic" => return Some(Builtin::Panic),
+        _ => {}
+    }
+    let (kind, operation) = name.split_once('_')?;
+    let op = match operation {
+        "add" => Operator::Plus,
+        "sub" => Operator::Minus,
+        "mul" => Operator::Multiply,
+        _ => return None,
+    };
+    match kind {
+        "wrapping" => Some(Builtin::Wrapping(op)),
+        "saturating" => Some(Builtin::Saturating(op)),
+        _ => None,
+    }
+}
+
+impl Builtin {
+    // 引数の型 (`types::check` が呼び出しを照合する)
+    pub fn params(&self) -> Vec<Type> {
+        match self {
+            Builtin::Wrapping(_) | Builtin::Saturating(_) => vec![Type::Int, Type::Int],
+            Builtin::Assert => vec![Type::Int],
+            Builtin::Panic => vec![Type::Str],
+        }
+    }
+
+    pub fn return_type(&self) -> Type {
+        match self {
+            Builtin::Wrapping(_) | Builtin::Saturating(_) => Type::Int,
+            Builtin::Assert | Builtin::Panic => Type::Void,
+        }
+    }
+
+    // 整数演算の組み込み関数の値 (インタプリタとバイトコード VM で使う)
+    pub fn evaluate(&self, left: i32, right: i32) -> Option<i32> {
+        let value = match self {
+            Builtin::Wrapping(Operator::Plus) => left.wrapping_add(right),
+            Builtin::Wrapping(Operator::Minus) => left.wrapping_sub(right),
+            Builtin::Wrapping(_) => left.wrapping_mul(right),
+            Builtin::Saturating(Operator::Plus) => left.saturating_add(right),
+            Builtin::Saturating(Operator::Minus) => left.saturating_sub(right),
+            Builtin::Saturating(_) => left.saturating_mul(right),
+            Builtin::Assert | Builtin::Panic => return None,
+        };
+        Some(value)
+    }
+}
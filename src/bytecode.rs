@@ -0,0 +1,420 @@
+mod compiler;
+mod vm;
+
+pub use compiler::{compile, CompileError};
+pub use vm::Vm;
+
+use crate::ast::{Operator, Span};
+use std::fmt;
+use std::io::{self, Read, Write};
+
+// `.omoc` ファイルの先頭に書かれる識別子とフォーマットのバージョン
+const MAGIC: &[u8; 4] = b"OMOC";
+pub const FORMAT_VERSION: u16 = 3;
+
+// 定数テーブルの値
+#[derive(Debug, PartialEq, Clone)]
+pub enum Constant {
+    Int(i32),
+    Str(String),
+}
+
+// スタックマシンの命令
+#[derive(Debug, PartialEq, Clone)]
+pub enum Instruction {
+    Const(u32),           // 定数をプッシュ
+    Load(u32),            // ローカル変数をプッシュ
+    Store(u32),           // ポップした値をローカル変数に格納
+    AddressOf(u32),       // ローカル変数へのポインタをプッシュ
+    LoadGlobal(u32),      // トップレベルの変数 (番号 0 の関数のローカル変数) をプッシュ
+    StoreGlobal(u32),     // ポップした値をトップレベルの変数に格納
+    AddressOfGlobal(u32), // トップレベルの変数へのポインタをプッシュ
+    Deref,                // ポインタをポップし、参照先の値をプッシュ
+    StoreDeref,           // 値、ポインタの順にポップし、参照先に格納
+    New,                  // 値をポップし、ヒープに確保したポインタをプッシュ
+    Binary(Operator),     // 2つポップして演算結果をプッシュ
+    Jump(u32),            // 指定した位置へジャンプ
+    JumpIfFalse(u32),     // ポップした値が 0 ならジャンプ
+    Call(u32, u32),       // 関数番号と引数の数
+    CallExtern(u32, u32), // 関数名の定数番号と引数の数
+    Return,               // ポップした値を戻り値として呼び出し元へ戻る
+    Print,                // ポップした値を出力
+    Pop,                  // スタックの先頭を捨てる
+    Dup,                  // スタックの先頭を複製
+}
+
+// 1つの関数 (トップレベルの文は番号 0 の関数になる)
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Chunk {
+    pub name: String,
+    pub arity: u32,  // 引数の数 (ローカル変数の先頭に入る)
+    pub locals: u32, // ローカル変数の数
+    pub constants: Vec<Constant>,
+    pub code: Vec<Instruction>,
+    pub spans: Vec<Span>, // 命令ごとのソース位置 (エラー表示用)
+}
+
+// コンパイル済みのプログラム
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Program {
+    pub chunks: Vec<Chunk>,
+    pub files: Vec<String>, // Span の file が指すソースファイルのパス (エラー表示用)
+}
+
+// `.omoc` ファイルの読み書きのエラーを表す列挙型
+#[derive(Debug)]
+pub enum BytecodeError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u16),
+    Corrupt(String),
+}
+
+impl fmt::Display for BytecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BytecodeError::Io(e) => write!(f, "{}", e),
+            BytecodeError::BadMagic => write!(f, "Not an omochi bytecode file"),
+            BytecodeError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported bytecode version {} (expected {})",
+                version, FORMAT_VERSION
+            ),
+            BytecodeError::Corrupt(reason) => write!(f, "Corrupt bytecode file: {}", reason),
+        }
+    }
+}
+
+impl From<io::Error> for BytecodeError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => {
+                BytecodeError::Corrupt(String::from("unexpected end of file"))
+            }
+            _ => BytecodeError::Io(e),
+        }
+    }
+}
+
+// 命令のオペコード
+const OP_CONST: u8 = 0;
+const OP_LOAD: u8 = 1;
+const OP_STORE: u8 = 2;
+const OP_ADDRESS_OF: u8 = 3;
+const OP_DEREF: u8 = 4;
+const OP_STORE_DEREF: u8 = 5;
+const OP_NEW: u8 = 6;
+const OP_BINARY: u8 = 7;
+const OP_JUMP: u8 = 8;
+const OP_JUMP_IF_FALSE: u8 = 9;
+const OP_CALL: u8 = 10;
+const OP_CALL_EXTERN: u8 = 11;
+const OP_RETURN: u8 = 12;
+const OP_PRINT: u8 = 13;
+const OP_POP: u8 = 14;
+const OP_DUP: u8 = 15;
+const OP_LOAD_GLOBAL: u8 = 16;
+const OP_STORE_GLOBAL: u8 = 17;
+const OP_ADDRESS_OF_GLOBAL: u8 = 18;
+
+const OPERATORS: [Operator; 7] = [
+    Operator::Plus,
+    Operator::Minus,
+    Operator::Equals,
+    Operator::Multiply,
+    Operator::Divide,
+    Operator::MoreThan,
+    Operator::LessThan,
+];
+
+impl Program {
+    // `.omoc` 形式で書き出す (整数はすべてリトルエンディアン)
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        write_u32(writer, self.chunks.len() as u32)?;
+        for chunk in &self.chunks {
+            write_str(writer, &chunk.name)?;
+            write_u32(writer, chunk.arity)?;
+            write_u32(writer, chunk.locals)?;
+            write_u32(writer, chunk.constants.len() as u32)?;
+            for constant in &chunk.constants {
+                match constant {
+                    Constant::Int(value) => {
+                        writer.write_all(&[0])?;
+                        writer.write_all(&value.to_le_bytes())?;
+                    }
+                    Constant::Str(value) => {
+                        writer.write_all(&[1])?;
+                        write_str(writer, value)?;
+                    }
+                }
+            }
+            write_u32(writer, chunk.code.len() as u32)?;
+            for (instruction, span) in chunk.code.iter().zip(&chunk.spans) {
+                write_instruction(writer, instruction)?;
+                write_u32(writer, span.line as u32)?;
+                write_u32(writer, span.column as u32)?;
+                write_u32(writer, span.file as u32)?;
+            }
+        }
+        write_u32(writer, self.files.len() as u32)?;
+        for file in &self.files {
+            write_str(writer, file)?;
+        }
+        Ok(())
+    }
+
+    // `.omoc` 形式から読み込む
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Program, BytecodeError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(BytecodeError::BadMagic);
+        }
+        let mut version = [0; 2];
+        reader.read_exact(&mut version)?;
+        let version = u16::from_le_bytes(version);
+        if version != FORMAT_VERSION {
+            return Err(BytecodeError::UnsupportedVersion(version));
+        }
+
+        let mut program = Program::default();
+        for _ in 0..read_u32(reader)? {
+            let mut chunk = Chunk {
+                name: read_str(reader)?,
+                arity: read_u32(reader)?,
+                locals: read_u32(reader)?,
+                ..Chunk::default()
+            };
+            for _ in 0..read_u32(reader)? {
+                let constant = match read_u8(reader)? {
+                    0 => Constant::Int(read_u32(reader)? as i32),
+                    1 => Constant::Str(read_str(reader)?),
+                    tag => return Err(BytecodeError::Corrupt(format!("constant tag {}", tag))),
+                };
+                chunk.constants.push(constant);
+            }
+            for _ in 0..read_u32(reader)? {
+                chunk.code.push(read_instruction(reader)?);
+                let line = read_u32(reader)? as usize;
+                let column = read_u32(reader)? as usize;
+                let file = read_u32(reader)? as usize;
+                chunk.spans.push(Span { line, column, file });
+            }
+            program.chunks.push(chunk);
+        }
+        for _ in 0..read_u32(reader)? {
+            program.files.push(read_str(reader)?);
+        }
+        program.validate()?;
+        Ok(program)
+    }
+
+    // 読み込んだ命令の参照先が範囲内かどうかを確認する
+    fn validate(&self) -> Result<(), BytecodeError> {
+        if self.chunks.is_empty() {
+            return Err(BytecodeError::Corrupt(String::from("no entry chunk")));
+        }
+        for chunk in &self.chunks {
+            let corrupt = |what: &str| {
+                BytecodeError::Corrupt(format!("{} out of range in `{}`", what, chunk.name))
+            };
+            if chunk.arity > chunk.locals {
+                return Err(corrupt("arity"));
+            }
+            for instruction in &chunk.code {
+                match *instruction {
+                    Instruction::Const(index) if index as usize >= chunk.constants.len() => {
+                        return Err(corrupt("constant"))
+                    }
+                    Instruction::Load(slot)
+                    | Instruction::Store(slot)
+                    | Instruction::AddressOf(slot)
+                        if slot >= chunk.locals =>
+                    {
+                        return Err(corrupt("local"))
+                    }
+                    Instruction::LoadGlobal(slot)
+                    | Instruction::StoreGlobal(slot)
+                    | Instruction::AddressOfGlobal(slot)
+                        if slot >= self.chunks[0].locals =>
+                    {
+                        return Err(corrupt("global"))
+                    }
+                    Instruction::Jump(target) | Instruction::JumpIfFalse(target)
+                        if target as usize > chunk.code.len() =>
+                    {
+                        return Err(corrupt("jump target"))
+                    }
+                    Instruction::Call(index, _) if index as usize >= self.chunks.len() => {
+                        return Err(corrupt("function"))
+                    }
+                    Instruction::CallExtern(index, _)
+                        if !matches!(
+                            chunk.constants.get(index as usize),
+                            Some(Constant::Str(_))
+                        ) =>
+                    {
+                        return Err(corrupt("extern name"))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_str<W: Write>(writer: &mut W, value: &str) -> io::Result<()> {
+    write_u32(writer, value.len() as u32)?;
+    writer.write_all(value.as_bytes())
+}
+
+fn write_instruction<W: Write>(writer: &mut W, instruction: &Instruction) -> io::Result<()> {
+    let (opcode, operands): (u8, &[u32]) = match *instruction {
+        Instruction::Const(index) => (OP_CONST, &[index]),
+        Instruction::Load(slot) => (OP_LOAD, &[slot]),
+        Instruction::Store(slot) => (OP_STORE, &[slot]),
+        Instruction::AddressOf(slot) => (OP_ADDRESS_OF, &[slot]),
+        Instruction::LoadGlobal(slot) => (OP_LOAD_GLOBAL, &[slot]),
+        Instruction::StoreGlobal(slot) => (OP_STORE_GLOBAL, &[slot]),
+        Instruction::AddressOfGlobal(slot) => (OP_ADDRESS_OF_GLOBAL, &[slot]),
+        Instruction::Deref => (OP_DEREF, &[]),
+        Instruction::StoreDeref => (OP_STORE_DEREF, &[]),
+        Instruction::New => (OP_NEW, &[]),
+        Instruction::Binary(ref op) => {
+            let index = OPERATORS.iter().position(|o| o == op).unwrap() as u32;
+            (OP_BINARY, &[index])
+        }
+        Instruction::Jump(target) => (OP_JUMP, &[target]),
+        Instruction::JumpIfFalse(target) => (OP_JUMP_IF_FALSE, &[target]),
+        Instruction::Call(index, argc) => (OP_CALL, &[index, argc]),
+        Instruction::CallExtern(index, argc) => (OP_CALL_EXTERN, &[index, argc]),
+        Instruction::Return => (OP_RETURN, &[]),
+        Instruction::Print => (OP_PRINT, &[]),
+        Instruction::Pop => (OP_POP, &[]),
+        Instruction::Dup => (OP_DUP, &[]),
+    };
+    writer.write_all(&[opcode])?;
+    for operand in operands {
+        write_u32(writer, *operand)?;
+    }
+    Ok(())
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut buffer = [0; 1];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer[0])
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buffer = [0; 4];
+    reader.read_exact(&mut buffer)?;
+    Ok(u32::from_le_bytes(buffer))
+}
+
+// 長さは信頼できないので、先に確保せず実際に読めたバイト数と照合する
+fn read_str<R: Read>(reader: &mut R) -> Result<String, BytecodeError> {
+    let length = read_u32(reader)? as u64;
+    let mut buffer = Vec::new();
+    reader.take(length).read_to_end(&mut buffer)?;
+    if buffer.len() as u64 != length {
+        return Err(BytecodeError::Corrupt(String::from("unexpected end of file")));
+    }
+    String::from_utf8(buffer).map_err(|_| BytecodeError::Corrupt(String::from("invalid UTF-8")))
+}
+
+fn read_instruction<R: Read>(reader: &mut R) -> Result<Instruction, BytecodeError> {
+    let instruction = match read_u8(reader)? {
+        OP_CONST => Instruction::Const(read_u32(reader)?),
+        OP_LOAD => Instruction::Load(read_u32(reader)?),
+        OP_STORE => Instruction::Store(read_u32(reader)?),
+        OP_ADDRESS_OF => Instruction::AddressOf(read_u32(reader)?),
+        OP_LOAD_GLOBAL => Instruction::LoadGlobal(read_u32(reader)?),
+        OP_STORE_GLOBAL => Instruction::StoreGlobal(read_u32(reader)?),
+        OP_ADDRESS_OF_GLOBAL => Instruction::AddressOfGlobal(read_u32(reader)?),
+        OP_DEREF => Instruction::Deref,
+        OP_STORE_DEREF => Instruction::StoreDeref,
+        OP_NEW => Instruction::New,
+        OP_BINARY => {
+            let index = read_u32(reader)? as usize;
+            let op = OPERATORS
+                .get(index)
+                .ok_or_else(|| BytecodeError::Corrupt(format!("operator {}", index)))?;
+            Instruction::Binary(op.clone())
+        }
+        OP_JUMP => Instruction::Jump(read_u32(reader)?),
+        OP_JUMP_IF_FALSE => Instruction::JumpIfFalse(read_u32(reader)?),
+        OP_CALL => Instruction::Call(read_u32(reader)?, read_u32(reader)?),
+        OP_CALL_EXTERN => Instruction::CallExtern(read_u32(reader)?, read_u32(reader)?),
+        OP_RETURN => Instruction::Return,
+        OP_PRINT => Instruction::Print,
+        OP_POP => Instruction::Pop,
+        OP_DUP => Instruction::Dup,
+        opcode => return Err(BytecodeError::Corrupt(format!("opcode {}", opcode))),
+    };
+    Ok(instruction)
+}
+
+// 逆アセンブル結果
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            writeln!(
+                f,
+                "== #{} {} (params: {}, locals: {}) ==",
+                index, chunk.name, chunk.arity, chunk.locals
+            )?;
+            for (offset, (instruction, span)) in chunk.code.iter().zip(&chunk.spans).enumerate() {
+                write!(f, "{:04} {:>8}  ", offset, span.to_string())?;
+                match *instruction {
+                    Instruction::Const(index) => {
+                        write!(f, "CONST {}", index)?;
+                        match &chunk.constants[index as usize] {
+                            Constant::Int(value) => write!(f, " ({})", value)?,
+                            Constant::Str(value) => write!(f, " ({:?})", value)?,
+                        }
+                    }
+                    Instruction::Load(slot) => write!(f, "LOAD {}", slot)?,
+                    Instruction::Store(slot) => write!(f, "STORE {}", slot)?,
+                    Instruction::AddressOf(slot) => write!(f, "ADDRESS_OF {}", slot)?,
+                    Instruction::LoadGlobal(slot) => write!(f, "LOAD_GLOBAL {}", slot)?,
+                    Instruction::StoreGlobal(slot) => write!(f, "STORE_GLOBAL {}", slot)?,
+                    Instruction::AddressOfGlobal(slot) => write!(f, "ADDRESS_OF_GLOBAL {}", slot)?,
+                    Instruction::Deref => write!(f, "DEREF")?,
+                    Instruction::StoreDeref => write!(f, "STORE_DEREF")?,
+                    Instruction::New => write!(f, "NEW")?,
+                    Instruction::Binary(ref op) => {
+                        write!(f, "{}", format!("{:?}", op).to_uppercase())?
+                    }
+                    Instruction::Jump(target) => write!(f, "JUMP {:04}", target)?,
+                    Instruction::JumpIfFalse(target) => write!(f, "JUMP_IF_FALSE {:04}", target)?,
+                    Instruction::Call(index, argc) => {
+                        let name = self.chunks.get(index as usize).map_or("?", |c| &c.name);
+                        write!(f, "CALL #{} {} ({} args)", index, name, argc)?
+                    }
+                    Instruction::CallExtern(index, argc) => {
+                        match &chunk.constants[index as usize] {
+                            Constant::Str(name) => {
+                                write!(f, "CALL_EXTERN {} ({} args)", name, argc)?
+                            }
+                            Constant::Int(_) => write!(f, "CALL_EXTERN ? ({} args)", argc)?,
+                        }
+                    }
+                    Instruction::Return => write!(f, "RETURN")?,
+                    Instruction::Print => write!(f, "PRINT")?,
+                    Instruction::Pop => write!(f, "POP")?,
+                    Instruction::Dup => write!(f, "DUP")?,
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,376 @@
+use super::{Chunk, Constant, Instruction, Program};
+use crate::ast::{Expr, Function, Span, Statement, Type};
+use crate::builtin;
+use crate::entry;
+use std::collections::HashMap;
+use std::fmt;
+
+// バイトコードへのコンパイルエラーを表す列挙型
+#[derive(Debug)]
+pub enum CompileError {
+    UndefinedVariable(String, Span),
+    UndefinedFunction(String, Span),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::UndefinedVariable(name, span) => {
+                write!(f, "{}: Undefined variable `{}`", span, name)
+            }
+            CompileError::UndefinedFunction(name, span) => {
+                write!(f, "{}: Undefined function `{}`", span, name)
+            }
+        }
+    }
+}
+
+// 呼び出し先の関数
+enum Callee {
+    User(u32),
+    Extern,
+}
+
+// 変数の格納場所
+enum Slot {
+    Local(u32),
+    Global(u32), // トップレベルの変数 (番号 0 の関数のローカル変数)
+}
+
+// 関数1つ分のコンパイル状態
+struct FunctionCompiler {
+    chunk: Chunk,
+    scopes: Vec<HashMap<String, u32>>, // 変数名からローカル変数の番号
+    span: Span,                        // 次に追加する命令の位置
+    is_main: bool,                     // トップレベルの文をまとめた関数
+}
+
+impl FunctionCompiler {
+    fn new(name: &str) -> Self {
+        FunctionCompiler {
+            chunk: Chunk {
+                name: name.to_string(),
+                ..Chunk::default()
+            },
+            scopes: vec![HashMap::new()],
+            span: Span::default(),
+            is_main: false,
+        }
+    }
+
+    // トップレベルのスコープにいるかどうか (ブロックの中の変数はグローバル変数にしない)
+    fn is_top_level(&self) -> bool {
+        self.is_main && self.scopes.len() == 1
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.chunk.code.push(instruction);
+        self.chunk.spans.push(self.span);
+        self.chunk.code.len() - 1
+    }
+
+    // ジャンプ先を現在の位置に書き換える
+    fn patch_jump(&mut self, at: usize) {
+        let target = self.chunk.code.len() as u32;
+        match &mut self.chunk.code[at] {
+            Instruction::Jump(to) | Instruction::JumpIfFalse(to) => *to = target,
+            _ => unreachable!("Not a jump instruction"),
+        }
+    }
+
+    fn constant(&mut self, constant: Constant) -> u32 {
+        let index = match self.chunk.constants.iter().position(|c| *c == constant) {
+            Some(index) => index,
+            None => {
+                self.chunk.constants.push(constant);
+                self.chunk.constants.len() - 1
+            }
+        };
+        index as u32
+    }
+
+    // 新しいローカル変数を現在のスコープに定義する
+    fn define(&mut self, name: &str) -> u32 {
+        let slot = self.chunk.locals;
+        self.chunk.locals += 1;
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), slot);
+        slot
+    }
+
+    fn resolve(&self, name: &str) -> Option<u32> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+}
+
+// AST をバイトコードにコンパイルする
+struct Compiler {
+    functions: HashMap<String, Callee>,
+    chunks: Vec<Chunk>,
+    globals: HashMap<String, u32>, // トップレベルの変数の番号 (関数の中からも見える)
+}
+
+// プログラム全体をコンパイルする (トップレベルの文は番号 0 の関数になる)
+pub fn compile(statements: &[Statement]) -> Result<Program, CompileError> {
+    let mut compiler = Compiler {
+        functions: HashMap::new(),
+        chunks: vec![Chunk::default()],
+        globals: HashMap::new(),
+    };
+    // 定義より前の呼び出しも解決できるように、先に関数に番号を振る
+    compiler.declare_functions(statements);
+
+    let mut main = FunctionCompiler::new("<main>");
+    main.is_main = true;
+    // 関数の中から参照できるように、トップレベルの変数に先に番号を振る (同じ名前の再宣言は同じ番号を使う)
+    for statement in statements {
+        if let Statement::Declaration(name, _, _) = statement {
+            if !compiler.globals.contains_key(name) {
+                compiler.globals.insert(name.clone(), main.chunk.locals);
+                main.chunk.locals += 1;
+            }
+        }
+    }
+    for statement in statements {
+        compiler.statement(&mut main, statement)?;
+    }
+    // `fn main` があれば最後に呼び出し、int を返すならその戻り値を終了コードにする
+    let mut returns_code = false;
+    if let (Some(func), Some(&Callee::User(index))) = (
+        entry::find_main(statements),
+        compiler.functions.get(entry::MAIN),
+    ) {
+        main.span = func.span;
+        main.emit(Instruction::Call(index, 0));
+        returns_code = func.return_type == Type::Int;
+        if !returns_code {
+            main.emit(Instruction::Pop);
+        }
+    }
+    if !returns_code {
+        let zero = main.constant(Constant::Int(0));
+        main.emit(Instruction::Const(zero));
+    }
+    main.emit(Instruction::Return);
+    compiler.chunks[0] = main.chunk;
+    // ファイル名はドライバーがローダーの結果から設定する
+    Ok(Program {
+        chunks: compiler.chunks,
+        files: Vec::new(),
+    })
+}
+
+impl Compiler {
+    // 変数名を解決する (関数の中ではローカル変数の次にトップレベルの変数を探す)
+    fn resolve(&self, fc: &FunctionCompiler, name: &str) -> Result<Slot, CompileError> {
+        if let Some(slot) = fc.resolve(name) {
+            return Ok(Slot::Local(slot));
+        }
+        match self.globals.get(name) {
+            Some(&slot) if !fc.is_main => Ok(Slot::Global(slot)),
+            _ => Err(CompileError::UndefinedVariable(name.to_string(), fc.span)),
+        }
+    }
+
+    fn store(&self, fc: &mut FunctionCompiler, name: &str) -> Result<(), CompileError> {
+        match self.resolve(fc, name)? {
+            Slot::Local(slot) => fc.emit(Instruction::Store(slot)),
+            Slot::Global(slot) => fc.emit(Instruction::StoreGlobal(slot)),
+        };
+        Ok(())
+    }
+
+    fn declare_functions(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Function(func) => {
+                    self.functions
+                        .insert(func.name.clone(), Callee::User(self.chunks.len() as u32));
+                    self.chunks.push(Chunk::default());
+                    self.declare_functions(&func.body);
+                }
+                Statement::Extern(func) => {
+                    self.functions.insert(func.name.clone(), Callee::Extern);
+                }
+                Statement::Block(body) => self.declare_functions(body),
+                Statement::If(_, then_branch, else_branch, _) => {
+                    self.declare_functions(std::slice::from_ref(then_branch));
+                    if let Some(else_branch) = else_branch {
+                        self.declare_functions(std::slice::from_ref(else_branch));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn function(&mut self, func: &Function) -> Result<(), CompileError> {
+        let index = match self.functions.get(&func.name) {
+            Some(Callee::User(index)) => *index as usize,
+            _ => unreachable!("Function `{}` was not declared", func.name),
+        };
+        let mut compiler = FunctionCompiler::new(&func.name);
+        for (param, _) in &func.params {
+            compiler.define(param);
+        }
+        compiler.chunk.arity = func.params.len() as u32;
+        for statement in &func.body {
+            self.statement(&mut compiler, statement)?;
+        }
+        self.expr(&mut compiler, &func.return_expr)?;
+        compiler.emit(Instruction::Return);
+        self.chunks[index] = compiler.chunk;
+        Ok(())
+    }
+
+    fn statement(
+        &mut self,
+        fc: &mut FunctionCompiler,
+        statement: &Statement,
+    ) -> Result<(), CompileError> {
+        match statement {
+            Statement::Expression(expr) => {
+                self.expr(fc, expr)?;
+                fc.emit(Instruction::Pop);
+            }
+            Statement::Declaration(name, expr, span) => {
+                // `let x = x + 1;` の右辺は外側の x を参照する
+                self.expr(fc, expr)?;
+                fc.span = *span;
+                let slot = match self.globals.get(name) {
+                    Some(&slot) if fc.is_top_level() => {
+                        fc.scopes[0].insert(name.clone(), slot);
+                        slot
+                    }
+                    _ => fc.define(name),
+                };
+                fc.emit(Instruction::Store(slot));
+            }
+            Statement::Assignment(name, expr, span) => {
+                self.expr(fc, expr)?;
+                fc.span = *span;
+                self.store(fc, name)?;
+            }
+            Statement::DerefAssignment(pointer, expr, _) => {
+                self.expr(fc, expr)?;
+                self.expr(fc, pointer)?;
+                fc.emit(Instruction::StoreDeref);
+            }
+            Statement::Print(expr) => {
+                self.expr(fc, expr)?;
+                fc.emit(Instruction::Print);
+            }
+            Statement::Block(statements) => {
+                fc.scopes.push(HashMap::new());
+                for statement in statements {
+                    self.statement(fc, statement)?;
+                }
+                fc.scopes.pop();
+            }
+            Statement::If(condition, then_branch, else_branch, span) => {
+                fc.span = *span;
+                self.if_statement(fc, condition, then_branch, else_branch.as_deref())?;
+            }
+            Statement::Function(func) => self.function(func)?,
+            Statement::Extern(_) | Statement::Mod(_) | Statement::Import(_) => {}
+        }
+        Ok(())
+    }
+
+    fn if_statement(
+        &mut self,
+        fc: &mut FunctionCompiler,
+        condition: &Expr,
+        then_branch: &Statement,
+        else_branch: Option<&Statement>,
+    ) -> Result<(), CompileError> {
+        self.expr(fc, condition)?;
+        let to_else = fc.emit(Instruction::JumpIfFalse(0));
+        self.statement(fc, then_branch)?;
+        match else_branch {
+            Some(else_branch) => {
+                let to_end = fc.emit(Instruction::Jump(0));
+                fc.patch_jump(to_else);
+                self.statement(fc, else_branch)?;
+                fc.patch_jump(to_end);
+            }
+            None => fc.patch_jump(to_else),
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self, fc: &mut FunctionCompiler, expr: &Expr) -> Result<(), CompileError> {
+        match expr {
+            Expr::Integer(value) => {
+                let index = fc.constant(Constant::Int(*value as i32));
+                fc.emit(Instruction::Const(index));
+            }
+            Expr::Str(value) => {
+                let index = fc.constant(Constant::Str(value.clone()));
+                fc.emit(Instruction::Const(index));
+            }
+            Expr::Variable(name, span) => {
+                fc.span = *span;
+                match self.resolve(fc, name)? {
+                    Slot::Local(slot) => fc.emit(Instruction::Load(slot)),
+                    Slot::Global(slot) => fc.emit(Instruction::LoadGlobal(slot)),
+                };
+            }
+            Expr::Assign(name, value) => {
+                self.expr(fc, value)?;
+                fc.emit(Instruction::Dup);
+                self.store(fc, name)?;
+            }
+            Expr::BinaryOp(left, op, right, span) => {
+                self.expr(fc, left)?;
+                self.expr(fc, right)?;
+                fc.span = *span;
+                fc.emit(Instruction::Binary(op.clone()));
+            }
+            Expr::If(condition, then_branch, else_branch) => {
+                // if 式の値はインタプリタと同じく 0
+                self.if_statement(fc, condition, then_branch, else_branch.as_deref())?;
+                let zero = fc.constant(Constant::Int(0));
+                fc.emit(Instruction::Const(zero));
+            }
+            Expr::AddressOf(name) => {
+                match self.resolve(fc, name)? {
+                    Slot::Local(slot) => fc.emit(Instruction::AddressOf(slot)),
+                    Slot::Global(slot) => fc.emit(Instruction::AddressOfGlobal(slot)),
+                };
+            }
+            Expr::Deref(pointer, _) => {
+                self.expr(fc, pointer)?;
+                fc.emit(Instruction::Deref);
+            }
+            Expr::New(value) => {
+                self.expr(fc, value)?;
+                fc.emit(Instruction::New);
+            }
+            Expr::FunctionCall(name, args, span) => {
+                for arg in args {
+                    self.expr(fc, arg)?;
+                }
+                fc.span = *span;
+                let argc = args.len() as u32;
+                match self.functions.get(name) {
+                    Some(Callee::User(index)) => fc.emit(Instruction::Call(*index, argc)),
+                    None if builtin::lookup(name).is_none() => {
+                        return Err(CompileError::UndefinedFunction(name.clone(), *span))
+                    }
+                    // C関数と組み込み関数は VM が名前で呼び出す
+                    Some(Callee::Extern) | None => {
+                        let name = fc.constant(Constant::Str(name.clone()));
+                        fc.emit(Instruction::CallExtern(name, argc))
+                    }
+                };
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+use super::{Constant, Instruction, Program};
+use crate::ast::Span;
+use crate::interpreter::{binary_op, call_intrinsic, RuntimeError, Value};
+use crate::panic::{PanicKind, MAX_FRAMES};
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+// 関数呼び出し1回分の状態
+struct Frame {
+    chunk: usize,
+    ip: usize,
+    locals: Vec<Rc<RefCell<Value>>>,
+}
+
+// バイトコードを実行するスタックマシン
+pub struct Vm<'p> {
+    program: &'p Program,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    output: Box<dyn Write>,
+    overflow_checks: bool, // `+` `-` `*` `/` のオーバーフローでパニックする
+}
+
+impl<'p> Vm<'p> {
+    pub fn new(program: &'p Program) -> Self {
+        Vm::with_output(program, Box::new(io::stdout()))
+    }
+
+    // print の出力先を指定して作成
+    pub fn with_output(program: &'p Program, output: Box<dyn Write>) -> Self {
+        Vm {
+            program,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            output,
+            overflow_checks: true,
+        }
+    }
+
+    // 整数演算のオーバーフローの検査を切り替える (無効にすると 32bit で折り返す)
+    pub fn set_overflow_checks(&mut self, enabled: bool) {
+        self.overflow_checks = enabled;
+    }
+
+    // 番号 0 の関数から実行し、その戻り値を返す
+    pub fn run(&mut self) -> Result<Value, RuntimeError> {
+        self.push_frame(0, Vec::new());
+        let result = self.execute();
+        self.output
+            .flush()
+            .map_err(|e| RuntimeError::new(e.to_string(), None))?;
+        result
+    }
+
+    fn push_frame(&mut self, chunk: usize, args: Vec<Value>) {
+        let locals = self.program.chunks[chunk].locals as usize;
+        let mut cells: Vec<_> = args
+            .into_iter()
+            .map(|value| Rc::new(RefCell::new(value)))
+            .collect();
+        cells.resize_with(locals, || Rc::new(RefCell::new(Value::Unit)));
+        self.frames.push(Frame {
+            chunk,
+            ip: 0,
+            locals: cells,
+        });
+    }
+
+    // 壊れたバイトコードは空のスタックからポップすることがある
+    fn pop(&mut self, span: Span) -> Result<Value, RuntimeError> {
+        self.stack.pop().ok_or_else(|| underflow(span))
+    }
+
+    fn pop_args(&mut self, argc: u32, span: Span) -> Result<Vec<Value>, RuntimeError> {
+        let at = self
+            .stack
+            .len()
+            .checked_sub(argc as usize)
+            .ok_or_else(|| underflow(span))?;
+        Ok(self.stack.split_off(at))
+    }
+
+    fn execute(&mut self) -> Result<Value, RuntimeError> {
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            let chunk = &self.program.chunks[frame.chunk];
+            let (instruction, span) = match chunk.code.get(frame.ip) {
+                Some(instruction) => (instruction, chunk.spans[frame.ip]),
+                None => {
+                    return Err(RuntimeError::new(
+                        format!("Reached the end of `{}` without returning", chunk.name),
+                        None,
+                    ))
+                }
+            };
+            frame.ip += 1;
+            let error = |message: String| RuntimeError::new(message, Some(span));
+
+            match instruction {
+                Instruction::Const(index) => {
+                    let value = match &chunk.constants[*index as usize] {
+                        Constant::Int(value) => Value::Int(*value),
+                        Constant::Str(value) => Value::Str(Rc::from(value.as_str())),
+                    };
+                    self.stack.push(value);
+                }
+                Instruction::Load(slot) => {
+                    let value = frame.locals[*slot as usize].borrow().clone();
+                    self.stack.push(value);
+                }
+                Instruction::Store(slot) => {
+                    let cell = Rc::clone(&frame.locals[*slot as usize]);
+                    *cell.borrow_mut() = self.pop(span)?;
+                }
+                Instruction::AddressOf(slot) => {
+                    let cell = Rc::clone(&frame.locals[*slot as usize]);
+                    self.stack.push(Value::Pointer(cell));
+                }
+                Instruction::LoadGlobal(slot) => {
+                    let value = self.frames[0].locals[*slot as usize].borrow().clone();
+                    self.stack.push(value);
+                }
+                Instruction::StoreGlobal(slot) => {
+                    let value = self.pop(span)?;
+                    *self.frames[0].locals[*slot as usize].borrow_mut() = value;
+                }
+                Instruction::AddressOfGlobal(slot) => {
+                    let cell = Rc::clone(&self.frames[0].locals[*slot as usize]);
+                    self.stack.push(Value::Pointer(cell));
+                }
+                Instruction::Deref => match self.pop(span)? {
+                    Value::Pointer(cell) => self.stack.push(cell.borrow().clone()),
+                    other => return Err(error(format!("Cannot dereference `{}`", other))),
+                },
+                Instruction::StoreDeref => {
+                    let pointer = self.pop(span)?;
+                    let value = self.pop(span)?;
+                    match pointer {
+                        Value::Pointer(cell) => *cell.borrow_mut() = value,
+                        other => return Err(error(format!("Cannot dereference `{}`", other))),
+                    }
+                }
+                Instruction::New => {
+                    let value = self.pop(span)?;
+                    self.stack
+                        .push(Value::Pointer(Rc::new(RefCell::new(value))));
+                }
+                Instruction::Binary(op) => {
+                    let right = self.pop(span)?;
+                    let left = self.pop(span)?;
+                    let value = binary_op(left, op, right, span, self.overflow_checks)?;
+                    self.stack.push(value);
+                }
+                Instruction::Jump(target) => frame.ip = *target as usize,
+                Instruction::JumpIfFalse(target) => {
+                    let target = *target as usize;
+                    match self.pop(span)? {
+                        Value::Int(0) => self.frames.last_mut().unwrap().ip = target,
+                        Value::Int(_) => {}
+                        other => {
+                            return Err(error(format!(
+                                "Condition must be an integer, found `{}`",
+                                other
+                            )))
+                        }
+                    }
+                }
+                Instruction::Call(index, argc) => {
+                    let callee = &self.program.chunks[*index as usize];
+                    if callee.arity != *argc {
+                        return Err(error(format!(
+                            "`{}` takes {} argument(s) but {} were given",
+                            callee.name, callee.arity, argc
+                        )));
+                    }
+                    if self.frames.len() >= MAX_FRAMES {
+                        return Err(RuntimeError::panic(
+                            PanicKind::StackOverflow,
+                            "Stack overflow",
+                            span,
+                        ));
+                    }
+                    let args = self.pop_args(*argc, span)?;
+                    self.push_frame(*index as usize, args);
+                }
+                Instruction::CallExtern(name, argc) => {
+                    let name = match &chunk.constants[*name as usize] {
+                        Constant::Str(name) => name,
+                        Constant::Int(_) => unreachable!("Extern name must be a string"),
+                    };
+                    let args = self.pop_args(*argc, span)?;
+                    let value = call_intrinsic(name, &args, &mut self.output, span)?;
+                    self.stack.push(value);
+                }
+                Instruction::Return => {
+                    let value = self.pop(span)?;
+                    self.frames.pop();
+                    if self.frames.is_empty() {
+                        return Ok(value);
+                    }
+                    self.stack.push(value);
+                }
+                Instruction::Print => {
+                    let value = self.pop(span)?;
+                    writeln!(self.output, "{}", value).map_err(|e| error(e.to_string()))?;
+                }
+                Instruction::Pop => {
+                    self.pop(span)?;
+                }
+                Instruction::Dup => {
+                    let value = self.stack.last().ok_or_else(|| underflow(span))?.clone();
+                    self.stack.push(value);
+                }
+            }
+        }
+    }
+}
+
+fn underflow(span: Span) -> RuntimeError {
+    RuntimeError::new(String::from("Bytecode stack underflow"), Some(span))
+}
@@ -0,0 +1,264 @@
+use crate::driver::{Backend, CompileOptions};
+use crate::lint::{Level, LintConfig};
+use std::fmt;
+use std::path::PathBuf;
+
+// サブコマンド
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Command {
+    Build,  // 実行ファイルを作成する (省略時)
+    Run,    // プログラムをその場で実行する
+    Disasm, // バイトコードを逆アセンブルして表示する
+    Repl,   // 対話環境を起動する
+    Fmt,    // ソースファイルを整形する
+    Lsp,    // 標準入出力で言語サーバを起動する
+}
+
+// 最適化レベル
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum OptLevel {
+    #[default]
+    O0,
+    O1,
+    O2,
+    O3,
+    Os, // サイズ優先
+}
+
+// `--emit` で出力するフロントエンドの結果
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Emit {
+    TokensJson, // トークン列 (JSON)
+    AstJson,    // 構文木 (JSON)
+    AstSexp,    // 構文木 (S 式)
+}
+
+// コマンドライン引数の解析結果
+#[derive(Debug)]
+pub struct Options {
+    pub command: Command,
+    pub interpret: bool,             // `--interp` で LLVM を使わずに実行する
+    pub vm: bool,                    // `--vm` でバイトコードにコンパイルして実行する
+    pub bytecode: bool,              // `--bytecode` で実行ファイルの代わりに .omoc を出力する
+    pub check: bool,                 // `fmt --check` で書き換えずに整形済みかどうかだけを調べる
+    pub emit: Option<Emit>,          // `--emit` でコンパイルせずにトークン列や構文木を出力する
+    pub input: PathBuf,              // エントリファイル
+    pub output: PathBuf,             // 出力する実行ファイル
+    pub libraries: Vec<String>,      // `-l` でリンクするライブラリ
+    pub library_paths: Vec<PathBuf>, // `-L` で追加するライブラリの検索パス
+    pub link_inputs: Vec<PathBuf>,   // 一緒にリンクする .o / .a / .c ファイル
+    pub target: Option<String>,      // `--target` で指定したターゲットトリプル (省略時はこのマシン)
+    pub cpu: Option<String>,         // `--cpu` で指定した CPU 名
+    pub features: Option<String>,    // `--features` で指定した CPU 機能 (`+neon,-fp-armv8` など)
+    pub opt_level: OptLevel,         // `-O0` から `-O3`、`-Os`
+    pub print_after_all: bool,       // `--print-after-all` で各パスの後の IR を表示する
+    pub keep_temps: bool,            // `--keep-temps` で中間ファイル (.s / .o) を残す
+    pub leak_check: bool,            // 終了時に解放されていないヒープ値を報告する
+    pub debug_info: bool,            // `-g` で gdb などのためのデバッグ情報 (DWARF) を出力する
+    pub overflow_checks: bool,       // 演算のオーバーフローを検査する (`-O0` では既定で有効)
+    pub lints: LintConfig,           // `-W` / `-A` / `-D` で変更したリントの報告レベル
+}
+
+impl Options {
+    // コンパイラに渡す設定
+    pub fn compile_options(&self, backend: Backend) -> CompileOptions {
+        CompileOptions {
+            path: self.input.clone(),
+            backend,
+            lints: self.lints.clone(),
+            opt_level: self.opt_level,
+            overflow_checks: self.overflow_checks,
+            debug_info: self.debug_info,
+            leak_check: self.leak_check,
+            print_after_all: self.print_after_all,
+        }
+    }
+}
+
+// コマンドライン引数のエラーを表す列挙型
+#[derive(Debug)]
+pub enum CliError {
+    MissingValue(String),
+    UnknownOption(String),
+    MultipleInputs(PathBuf, PathBuf),
+    UnknownLint(String),
+    InvalidValue(String, String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::MissingValue(option) => write!(f, "Missing value for `{}`", option),
+            CliError::UnknownOption(option) => write!(f, "Unknown option: {}", option),
+            CliError::MultipleInputs(first, second) => write!(
+                f,
+                "Multiple source files given: {} and {}",
+                first.display(),
+                second.display()
+            ),
+            CliError::UnknownLint(name) => write!(f, "Unknown lint: `{}`", name),
+            CliError::InvalidValue(option, value) => {
+                write!(f, "Invalid value for `{}`: {}", option, value)
+            }
+        }
+    }
+}
+
+// リンカにそのまま渡す入力ファイルの拡張子
+const LINK_INPUT_EXTENSIONS: [&str; 4] = ["o", "a", "so", "c"];
+
+// コマンドライン引数を解析する (先頭のプログラム名は含めない)
+// `-lfoo` と `-l foo`、`--target=x` と `--target x` のどちらの形式も受け付ける
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, CliError> {
+    let mut args = args.into_iter().peekable();
+    // サブコマンドを省略した場合は build として扱う
+    let command = match args
+        .next_if(|arg| {
+            matches!(
+                arg.as_str(),
+                "build" | "run" | "disasm" | "repl" | "fmt" | "lsp"
+            )
+        })
+        .as_deref()
+    {
+        Some("run") => Command::Run,
+        Some("disasm") => Command::Disasm,
+        Some("repl") => Command::Repl,
+        Some("fmt") => Command::Fmt,
+        Some("lsp") => Command::Lsp,
+        _ => Command::Build,
+    };
+
+    let mut input: Option<PathBuf> = None;
+    let mut overflow_checks: Option<bool> = None;
+    let mut options = Options {
+        command,
+        interpret: false,
+        vm: false,
+        bytecode: false,
+        check: false,
+        emit: None,
+        input: PathBuf::from("program.txt"),
+        output: PathBuf::from("program"),
+        libraries: Vec::new(),
+        library_paths: Vec::new(),
+        link_inputs: Vec::new(),
+        leak_check: false,
+        debug_info: false,
+        overflow_checks: false,
+        opt_level: OptLevel::O0,
+        print_after_all: false,
+        keep_temps: false,
+        target: None,
+        cpu: None,
+        features: None,
+        lints: LintConfig::new(),
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value_of = |flag: &str| -> Result<String, CliError> {
+            match &arg[flag.len()..] {
+                "" => args.next().ok_or(CliError::MissingValue(flag.to_string())),
+                value => Ok(value.strip_prefix('=').unwrap_or(value).to_string()),
+            }
+        };
+        if arg == "--leak-check" {
+            options.leak_check = true;
+        } else if arg == "--overflow-checks" {
+            overflow_checks = Some(true);
+        } else if arg == "--no-overflow-checks" {
+            overflow_checks = Some(false);
+        } else if arg == "-g" {
+            options.debug_info = true;
+        } else if arg == "--keep-temps" {
+            options.keep_temps = true;
+        } else if arg == "--print-after-all" {
+            options.print_after_all = true;
+        } else if let Some(level) = opt_level(&arg) {
+            options.opt_level = level;
+        } else if arg == "--interp" {
+            options.interpret = true;
+        } else if arg == "--vm" {
+            options.vm = true;
+        } else if arg == "--bytecode" {
+            options.bytecode = true;
+        } else if arg == "--check" {
+            options.check = true;
+        } else if is_long_option(&arg, "--emit") {
+            let value = value_of("--emit")?;
+            options.emit = Some(match value.as_str() {
+                "tokens-json" => Emit::TokensJson,
+                "ast-json" => Emit::AstJson,
+                "ast-sexp" => Emit::AstSexp,
+                _ => return Err(CliError::InvalidValue(String::from("--emit"), value)),
+            });
+        } else if is_long_option(&arg, "--target") {
+            options.target = Some(value_of("--target")?);
+        } else if is_long_option(&arg, "--cpu") {
+            options.cpu = Some(value_of("--cpu")?);
+        } else if is_long_option(&arg, "--features") {
+            options.features = Some(value_of("--features")?);
+        } else if let Some((flag, level)) = lint_level(&arg) {
+            let name = value_of(flag)?;
+            options
+                .lints
+                .set(&name, level)
+                .map_err(CliError::UnknownLint)?;
+        } else if arg.starts_with("-l") {
+            options.libraries.push(value_of("-l")?);
+        } else if arg.starts_with("-L") {
+            options.library_paths.push(PathBuf::from(value_of("-L")?));
+        } else if arg.starts_with("-o") {
+            options.output = PathBuf::from(value_of("-o")?);
+        } else if arg.starts_with('-') {
+            return Err(CliError::UnknownOption(arg));
+        } else {
+            let path = PathBuf::from(&arg);
+            let is_link_input = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| LINK_INPUT_EXTENSIONS.contains(&ext));
+            if is_link_input {
+                options.link_inputs.push(path);
+            } else if let Some(first) = input.take() {
+                return Err(CliError::MultipleInputs(first, path));
+            } else {
+                input = Some(path);
+            }
+        }
+    }
+
+    if let Some(input) = input {
+        options.input = input;
+    }
+    options.overflow_checks = overflow_checks.unwrap_or(options.opt_level == OptLevel::O0);
+    Ok(options)
+}
+
+fn opt_level(arg: &str) -> Option<OptLevel> {
+    match arg {
+        "-O0" => Some(OptLevel::O0),
+        "-O1" => Some(OptLevel::O1),
+        "-O2" => Some(OptLevel::O2),
+        "-O3" => Some(OptLevel::O3),
+        "-Os" => Some(OptLevel::Os),
+        _ => None,
+    }
+}
+
+// `-W` (警告)、`-A` (許可)、`-D` (エラー) のリント指定
+fn lint_level(arg: &str) -> Option<(&'static str, Level)> {
+    [
+        ("-W", Level::Warn),
+        ("-A", Level::Allow),
+        ("-D", Level::Deny),
+    ]
+    .into_iter()
+    .find(|(flag, _)| arg.starts_with(flag))
+}
+
+// `--name` または `--name=value` の形式かどうか
+fn is_long_option(arg: &str, name: &str) -> bool {
+    arg.strip_prefix(name)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('='))
+}
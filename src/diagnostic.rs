@@ -0,0 +1,173 @@
+use crate::ast::Span;
+use crate::bytecode::CompileError;
+use crate::entry::EntryError;
+use crate::fold::FoldError;
+use crate::lexer::LexerError;
+use crate::lint::{self, Level};
+use crate::loader::LoaderError;
+use crate::parser::ParserError;
+use crate::resolve::ResolveError;
+use crate::types::TypeError;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// コンパイルの各段階で見つかった問題
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub path: Option<PathBuf>, // 問題のあるファイル (省略時はコンパイルしたソース)
+    pub span: Option<Span>,
+    pub message: String,
+    pub code: Option<&'static str>, // リント名
+}
+
+impl Diagnostic {
+    pub fn error(span: Option<Span>, message: String) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            path: None,
+            span,
+            message,
+            code: None,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    // 構文エラーは構文解析器が最後に読んだトークンの位置で報告する
+    pub fn parser(e: ParserError, span: Span) -> Self {
+        Diagnostic::error(Some(span), e.to_string())
+    }
+}
+
+// `file:line:col: message` の形式 (リントは `file:line:col: warning: message [name]`)
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+        }
+        if let Some(span) = self.span {
+            write!(f, "{}:", span)?;
+        }
+        if self.path.is_some() || self.span.is_some() {
+            write!(f, " ")?;
+        }
+        match self.code {
+            Some(code) => {
+                let level = match self.severity {
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                };
+                write!(f, "{}: {} [{}]", level, self.message, code)
+            }
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl From<LexerError> for Diagnostic {
+    fn from(e: LexerError) -> Self {
+        let span = e.span();
+        Diagnostic::error(Some(span), strip_span(e.to_string(), span))
+    }
+}
+
+impl From<FoldError> for Diagnostic {
+    fn from(e: FoldError) -> Self {
+        let FoldError::DivisionByZero(span) = e;
+        Diagnostic::error(Some(span), strip_span(e.to_string(), span))
+    }
+}
+
+impl From<EntryError> for Diagnostic {
+    fn from(e: EntryError) -> Self {
+        let span = e.span();
+        Diagnostic::error(Some(span), strip_span(e.to_string(), span))
+    }
+}
+
+impl From<ResolveError> for Diagnostic {
+    fn from(e: ResolveError) -> Self {
+        let span = e.span();
+        Diagnostic::error(Some(span), strip_span(e.to_string(), span))
+    }
+}
+
+impl From<TypeError> for Diagnostic {
+    fn from(e: TypeError) -> Self {
+        let span = e.span();
+        Diagnostic::error(Some(span), strip_span(e.to_string(), span))
+    }
+}
+
+impl From<CompileError> for Diagnostic {
+    fn from(e: CompileError) -> Self {
+        let span = match &e {
+            CompileError::UndefinedVariable(_, span) | CompileError::UndefinedFunction(_, span) => {
+                *span
+            }
+        };
+        Diagnostic::error(Some(span), strip_span(e.to_string(), span))
+    }
+}
+
+impl From<lint::Diagnostic> for Diagnostic {
+    fn from(d: lint::Diagnostic) -> Self {
+        Diagnostic {
+            severity: match d.level {
+                Level::Deny => Severity::Error,
+                _ => Severity::Warning,
+            },
+            path: None,
+            span: Some(d.span),
+            message: d.message,
+            code: Some(d.lint),
+        }
+    }
+}
+
+// 読み込みのエラーは問題のあったファイルを指す
+impl From<LoaderError> for Diagnostic {
+    fn from(e: LoaderError) -> Self {
+        let path = e.path().map(Path::to_path_buf);
+        let mut diagnostic = match e {
+            LoaderError::Lexer(_, e) => Diagnostic::from(e),
+            LoaderError::Parser(_, e, span) => Diagnostic::parser(e, span),
+            e => {
+                let span = e.span();
+                let message = e.to_string();
+                let message = match &path {
+                    Some(path) => message
+                        .strip_prefix(&format!("{}", path.display()))
+                        .and_then(|rest| rest.strip_prefix(':'))
+                        .map(|rest| rest.trim_start().to_string())
+                        .unwrap_or(message),
+                    None => message,
+                };
+                let message = match span {
+                    Some(span) => strip_span(message, span),
+                    None => message,
+                };
+                Diagnostic::error(span, message)
+            }
+        };
+        diagnostic.path = path;
+        diagnostic
+    }
+}
+
+// エラーメッセージの先頭の `line:col: ` を取り除く (位置は別に持つ)
+pub(crate) fn strip_span(message: String, span: Span) -> String {
+    match message.strip_prefix(&format!("{}: ", span)) {
+        Some(rest) => rest.to_string(),
+        None => message,
+    }
+}
@@ -0,0 +1,202 @@
+use crate::ast::{Span, Statement, Token};
+use crate::bytecode::{self, Program};
+use crate::cli::OptLevel;
+use crate::diagnostic::Diagnostic;
+use crate::entry;
+use crate::fold;
+use crate::lexer::Lexer;
+use crate::lint::{self, LintConfig};
+use crate::loader::Loader;
+use crate::parser::Parser;
+use crate::resolve;
+use crate::types;
+use std::path::{Path, PathBuf};
+
+// コンパイル結果の形式
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Backend {
+    Ast,      // 検査と定数畳み込みを終えた構文木 (インタプリタで実行する)
+    Bytecode, // VM で実行するバイトコード
+    #[cfg(feature = "llvm")]
+    LlvmIr, // LLVM IR のテキスト
+}
+
+// コンパイル結果
+#[derive(Debug)]
+pub enum Output {
+    Ast(Vec<Statement>),
+    Bytecode(Program),
+    #[cfg(feature = "llvm")]
+    LlvmIr(String),
+}
+
+// 成功したコンパイルの成果物と、それまでに出た警告
+#[derive(Debug)]
+pub struct Artifact {
+    pub output: Output,
+    pub warnings: Vec<Diagnostic>,
+    pub files: Vec<PathBuf>, // Span の file が指すソースファイル (0 は options.path)
+}
+
+// コンパイルの設定 (コマンドライン引数の `Options` からコンパイラに関わるものだけを取り出したもの)
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    pub path: PathBuf, // 診断に表示するパス (`mod` で読み込むファイルはここからの相対パスで探す)
+    pub backend: Backend,
+    pub lints: LintConfig,
+    pub opt_level: OptLevel,
+    pub overflow_checks: bool, // 演算のオーバーフローを検査する
+    pub debug_info: bool,      // DWARF のデバッグ情報を出力する
+    pub leak_check: bool,      // 終了時に解放されていないヒープ値を報告する
+    pub print_after_all: bool, // 各最適化パスの後の IR を表示する
+}
+
+impl CompileOptions {
+    // コマンドラインの既定値と同じ設定
+    pub fn new(path: impl Into<PathBuf>, backend: Backend) -> Self {
+        CompileOptions {
+            path: path.into(),
+            backend,
+            lints: LintConfig::new(),
+            opt_level: OptLevel::O0,
+            overflow_checks: true,
+            debug_info: false,
+            leak_check: false,
+            print_after_all: false,
+        }
+    }
+}
+
+// ソースコードを読み込み・検査・定数畳み込みして options.backend の形式にコンパイルする
+// 失敗した場合はそれまでの警告も含めてすべての診断を返す
+pub fn compile(source: &str, options: &CompileOptions) -> Result<Artifact, Vec<Diagnostic>> {
+    // 診断のパスは位置が指すファイル (位置のない診断はエントリファイル)
+    let with_path = |files: &[PathBuf], mut diagnostics: Vec<Diagnostic>| {
+        for diagnostic in &mut diagnostics {
+            let file = diagnostic.span.and_then(|span| files.get(span.file));
+            diagnostic
+                .path
+                .get_or_insert_with(|| file.unwrap_or(&options.path).clone());
+        }
+        diagnostics
+    };
+
+    let (statements, files) = load(source, &options.path).map_err(|e| with_path(&[], vec![e]))?;
+    // リントは畳み込み前の AST で行う (畳み込むと定数条件の if などが消えてしまう)
+    let mut diagnostics = check(&statements, &options.lints);
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        return Err(with_path(&files, diagnostics));
+    }
+    let result = fold_constants(statements).and_then(|statements| {
+        Ok(match options.backend {
+            Backend::Ast => Output::Ast(statements),
+            Backend::Bytecode => {
+                let mut program = compile_bytecode(&statements)?;
+                program.files = files.iter().map(|file| file.display().to_string()).collect();
+                Output::Bytecode(program)
+            }
+            #[cfg(feature = "llvm")]
+            Backend::LlvmIr => Output::LlvmIr(emit_llvm_ir(statements, &files, options)),
+        })
+    });
+    match result {
+        Ok(output) => Ok(Artifact {
+            output,
+            warnings: with_path(&files, diagnostics),
+            files,
+        }),
+        Err(e) => {
+            diagnostics.push(e);
+            Err(with_path(&files, diagnostics))
+        }
+    }
+}
+
+// 字句解析 (各トークンの開始位置も返す)
+pub fn tokenize(source: &str) -> Result<(Vec<Token>, Vec<Span>), Diagnostic> {
+    Ok(Lexer::new(source).lex_with_spans()?)
+}
+
+// 1つのファイルの構文解析 (`mod` や `import` は解決しない)
+pub fn parse(source: &str) -> Result<Vec<Statement>, Diagnostic> {
+    let (tokens, spans) = tokenize(source)?;
+    let mut parser = Parser::with_spans(tokens, spans);
+    parser.parse_statements().map_err(|e| {
+        let span = e.span().unwrap_or_else(|| parser.error_span());
+        Diagnostic::parser(e, span)
+    })
+}
+
+// `mod` で宣言したファイルも読み込み、名前解決した1つのプログラムにする
+// 読み込んだファイルのパスも返す (Span の file はこの添字で、0 のエントリファイルは path のまま)
+pub fn load(source: &str, path: &Path) -> Result<(Vec<Statement>, Vec<PathBuf>), Diagnostic> {
+    let (statements, mut files) = Loader::new().load_source(path, source)?;
+    files[0] = path.to_path_buf();
+    Ok((statements, files))
+}
+
+// `fn main` の検査、未定義の変数と関数の検出、型検査とリント (`-D` で拒否したものはエラーになる)
+pub fn check(statements: &[Statement], config: &LintConfig) -> Vec<Diagnostic> {
+    let entry_errors = entry::check(statements).into_iter().map(Diagnostic::from);
+    let resolve_errors = resolve::check(statements).into_iter().map(Diagnostic::from);
+    let type_errors = types::check(statements).into_iter().map(Diagnostic::from);
+    let lints = lint::check(statements, config).into_iter().map(Diagnostic::from);
+    entry_errors
+        .chain(resolve_errors)
+        .chain(type_errors)
+        .chain(lints)
+        .collect()
+}
+
+// 定数畳み込み
+pub fn fold_constants(statements: Vec<Statement>) -> Result<Vec<Statement>, Diagnostic> {
+    Ok(fold::fold_program(statements)?)
+}
+
+// バイトコードへのコンパイル
+pub fn compile_bytecode(statements: &[Statement]) -> Result<Program, Diagnostic> {
+    Ok(bytecode::compile(statements)?)
+}
+
+// プログラム全体の LLVM IR を生成して最適化する
+#[cfg(feature = "llvm")]
+pub fn generate_ir<'ctx>(
+    context: &'ctx inkwell::context::Context,
+    statements: Vec<Statement>,
+    files: &[PathBuf],
+    options: &CompileOptions,
+    machine: Option<&inkwell::targets::TargetMachine>,
+) -> crate::irgenerator::IRGenerator<'ctx> {
+    // IRジェネレータのインスタンス化
+    let mut ir_generator = crate::irgenerator::IRGenerator::new(context);
+    ir_generator.set_leak_check(options.leak_check);
+    ir_generator.set_overflow_checks(options.overflow_checks);
+    ir_generator.set_source_files(files);
+    if let Some(machine) = machine {
+        ir_generator.set_target(machine);
+    }
+    if options.debug_info {
+        ir_generator.enable_debug_info(&options.path, options.opt_level != OptLevel::O0);
+    }
+    // 解析されたプログラム (AST) から LLVM IR を生成
+    // トップレベルの文は初期化関数にまとめ、main から呼び出す
+    ir_generator.generate_program(&statements);
+    crate::optimize::optimize_module(
+        &ir_generator.module,
+        options.opt_level,
+        options.print_after_all,
+    );
+    ir_generator
+}
+
+// LLVM IR をテキストで返す
+#[cfg(feature = "llvm")]
+pub fn emit_llvm_ir(
+    statements: Vec<Statement>,
+    files: &[PathBuf],
+    options: &CompileOptions,
+) -> String {
+    let context = inkwell::context::Context::create();
+    let ir_generator = generate_ir(&context, statements, files, options, None);
+    ir_generator.module.print_to_string().to_string()
+}
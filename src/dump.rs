@@ -0,0 +1,398 @@
+use crate::ast::{Expr, ExternFunction, Function, Span, Statement, Token, Type};
+use crate::json::Json;
+
+// S 式で1行に収める最大の幅
+const SEXP_WIDTH: usize = 80;
+
+// JSON と S 式に共通の中間表現
+enum Node {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Str(String),  // 文字列リテラル (S 式では引用符で囲む)
+    Name(String), // 識別子や型 (S 式ではそのまま書く)
+    Span(Span),
+    List(Vec<Node>),
+    Object(&'static str, Vec<(&'static str, Node)>), // 種類とフィールド
+}
+
+// トークン列を JSON で出力する
+pub fn tokens_json(tokens: &[Token], spans: &[Span]) -> String {
+    let tokens = tokens
+        .iter()
+        .zip(spans)
+        .map(|(token, span)| {
+            let mut fields = Vec::new();
+            match token {
+                Token::Identifier(name) => fields.push(("value", Node::Name(name.clone()))),
+                Token::Integer(value) => fields.push(("value", Node::Int(*value))),
+                Token::Str(value) => fields.push(("value", Node::Str(value.clone()))),
+                _ => {}
+            }
+            fields.push(("text", Node::Str(token.to_string())));
+            fields.push(("span", Node::Span(*span)));
+            Node::Object(token_kind(token), fields)
+        })
+        .collect();
+    let mut out = String::new();
+    write_json(&Node::List(tokens), 0, &mut out);
+    out.push('\n');
+    out
+}
+
+// AST を JSON で出力する
+// span は AST が位置を持つ節点だけに付く (整数・文字列リテラル, 式の中の代入, `&x`, `new`,
+// print, 式文, extern, mod, import には位置がないので出力しない)
+pub fn ast_json(statements: &[Statement]) -> String {
+    let mut out = String::new();
+    write_json(&statements_node(statements), 0, &mut out);
+    out.push('\n');
+    out
+}
+
+// AST を S 式で出力する
+pub fn ast_sexp(statements: &[Statement]) -> String {
+    let mut out = String::from("(program");
+    for statement in statements {
+        out.push_str("\n  ");
+        write_sexp(&statement_node(statement), 1, &mut out);
+    }
+    out.push_str(")\n");
+    out
+}
+
+// トークンの種類名 (列挙子の名前)
+fn token_kind(token: &Token) -> &'static str {
+    match token {
+        Token::Identifier(_) => "Identifier",
+        Token::Integer(_) => "Integer",
+        Token::Str(_) => "Str",
+        Token::Let => "Let",
+        Token::Fn => "Fn",
+        Token::If => "If",
+        Token::Else => "Else",
+        Token::Print => "Print",
+        Token::Mod => "Mod",
+        Token::Import => "Import",
+        Token::Pub => "Pub",
+        Token::Extern => "Extern",
+        Token::New => "New",
+        Token::Plus => "Plus",
+        Token::Minus => "Minus",
+        Token::Equals => "Equals",
+        Token::DoubleEquals => "DoubleEquals",
+        Token::Asterisk => "Asterisk",
+        Token::Slash => "Slash",
+        Token::Ampersand => "Ampersand",
+        Token::Semicolon => "Semicolon",
+        Token::Colon => "Colon",
+        Token::DoubleColon => "DoubleColon",
+        Token::Comma => "Comma",
+        Token::Ellipsis => "Ellipsis",
+        Token::Then => "Then",
+        Token::LeftParen => "LeftParen",
+        Token::RightParen => "RightParen",
+        Token::Arrow => "Arrow",
+        Token::LeftBrace => "LeftBrace",
+        Token::RightBrace => "RightBrace",
+        Token::MoreThan => "MoreThan",
+        Token::LessThan => "LessThan",
+        Token::EOF => "EOF",
+    }
+}
+
+fn statements_node(statements: &[Statement]) -> Node {
+    Node::List(statements.iter().map(statement_node).collect())
+}
+
+fn statement_node(statement: &Statement) -> Node {
+    match statement {
+        Statement::Expression(expr) => Node::Object("Expression", vec![("expr", expr_node(expr))]),
+        Statement::Declaration(name, value, span) => Node::Object(
+            "Declaration",
+            vec![
+                ("name", Node::Name(name.clone())),
+                ("value", expr_node(value)),
+                ("span", Node::Span(*span)),
+            ],
+        ),
+        Statement::Assignment(name, value, span) => Node::Object(
+            "Assignment",
+            vec![
+                ("name", Node::Name(name.clone())),
+                ("value", expr_node(value)),
+                ("span", Node::Span(*span)),
+            ],
+        ),
+        Statement::DerefAssignment(pointer, value, span) => Node::Object(
+            "DerefAssignment",
+            vec![
+                ("pointer", expr_node(pointer)),
+                ("value", expr_node(value)),
+                ("span", Node::Span(*span)),
+            ],
+        ),
+        Statement::Print(value) => Node::Object("Print", vec![("value", expr_node(value))]),
+        Statement::Block(statements) => {
+            Node::Object("Block", vec![("statements", statements_node(statements))])
+        }
+        Statement::If(condition, then_branch, else_branch, span) => Node::Object(
+            "If",
+            vec![
+                ("condition", expr_node(condition)),
+                ("then", statement_node(then_branch)),
+                (
+                    "else",
+                    else_branch.as_deref().map_or(Node::Null, statement_node),
+                ),
+                ("span", Node::Span(*span)),
+            ],
+        ),
+        Statement::Function(func) => function_node(func),
+        Statement::Mod(name) => Node::Object("Mod", vec![("name", Node::Name(name.clone()))]),
+        Statement::Import(path) => Node::Object(
+            "Import",
+            vec![(
+                "path",
+                Node::List(path.iter().cloned().map(Node::Name).collect()),
+            )],
+        ),
+        Statement::Extern(func) => extern_node(func),
+    }
+}
+
+fn function_node(func: &Function) -> Node {
+    Node::Object(
+        "Function",
+        vec![
+            ("name", Node::Name(func.name.clone())),
+            ("is_pub", Node::Bool(func.is_pub)),
+            ("params", params_node(&func.params)),
+            ("return_type", type_node(&func.return_type)),
+            ("body", statements_node(&func.body)),
+            ("return_expr", expr_node(&func.return_expr)),
+            ("span", Node::Span(func.span)),
+        ],
+    )
+}
+
+fn extern_node(func: &ExternFunction) -> Node {
+    Node::Object(
+        "Extern",
+        vec![
+            ("name", Node::Name(func.name.clone())),
+            ("is_pub", Node::Bool(func.is_pub)),
+            ("params", params_node(&func.params)),
+            ("variadic", Node::Bool(func.variadic)),
+            ("return_type", type_node(&func.return_type)),
+        ],
+    )
+}
+
+fn params_node(params: &[(String, Type)]) -> Node {
+    Node::List(
+        params
+            .iter()
+            .map(|(name, ty)| {
+                Node::Object(
+                    "Param",
+                    vec![("name", Node::Name(name.clone())), ("type", type_node(ty))],
+                )
+            })
+            .collect(),
+    )
+}
+
+fn type_node(ty: &Type) -> Node {
+    Node::Name(ty.to_string())
+}
+
+fn expr_node(expr: &Expr) -> Node {
+    match expr {
+        Expr::Integer(value) => Node::Object("Integer", vec![("value", Node::Int(*value))]),
+        Expr::Str(value) => Node::Object("Str", vec![("value", Node::Str(value.clone()))]),
+        Expr::BinaryOp(left, op, right, span) => Node::Object(
+            "BinaryOp",
+            vec![
+                ("op", Node::Name(op.to_string())),
+                ("left", expr_node(left)),
+                ("right", expr_node(right)),
+                ("span", Node::Span(*span)),
+            ],
+        ),
+        Expr::Variable(name, span) => Node::Object(
+            "Variable",
+            vec![
+                ("name", Node::Name(name.clone())),
+                ("span", Node::Span(*span)),
+            ],
+        ),
+        Expr::Assign(name, value) => Node::Object(
+            "Assign",
+            vec![
+                ("name", Node::Name(name.clone())),
+                ("value", expr_node(value)),
+            ],
+        ),
+        Expr::If(condition, then_branch, else_branch) => Node::Object(
+            "IfExpr",
+            vec![
+                ("condition", expr_node(condition)),
+                ("then", statement_node(then_branch)),
+                (
+                    "else",
+                    else_branch.as_deref().map_or(Node::Null, statement_node),
+                ),
+            ],
+        ),
+        Expr::FunctionCall(name, args, span) => Node::Object(
+            "FunctionCall",
+            vec![
+                ("name", Node::Name(name.clone())),
+                ("args", Node::List(args.iter().map(expr_node).collect())),
+                ("span", Node::Span(*span)),
+            ],
+        ),
+        Expr::AddressOf(name) => {
+            Node::Object("AddressOf", vec![("name", Node::Name(name.clone()))])
+        }
+        Expr::Deref(pointer, span) => Node::Object(
+            "Deref",
+            vec![("pointer", expr_node(pointer)), ("span", Node::Span(*span))],
+        ),
+        Expr::New(value) => Node::Object("New", vec![("value", expr_node(value))]),
+    }
+}
+
+// 入れ子のリストやオブジェクトを含まないものは1行に書く
+fn is_flat(node: &Node) -> bool {
+    match node {
+        Node::List(items) => items.is_empty(),
+        Node::Object(_, fields) => fields
+            .iter()
+            .all(|(_, value)| !matches!(value, Node::List(_) | Node::Object(..))),
+        _ => true,
+    }
+}
+
+fn write_json(node: &Node, indent: usize, out: &mut String) {
+    // 1行に書くときは ", " で区切り、そうでなければ要素ごとに改行する
+    let (start, separator, end) = if is_flat(node) {
+        (String::new(), String::from(", "), String::new())
+    } else {
+        let inner = format!("\n{}", "  ".repeat(indent + 1));
+        (
+            inner.clone(),
+            format!(",{}", inner),
+            format!("\n{}", "  ".repeat(indent)),
+        )
+    };
+    match node {
+        Node::Null => out.push_str("null"),
+        Node::Bool(value) => out.push_str(&value.to_string()),
+        Node::Int(value) => out.push_str(&value.to_string()),
+        Node::Str(value) | Node::Name(value) => write_json_string(value, out),
+        Node::Span(span) => out.push_str(&format!(
+            "{{\"line\": {}, \"column\": {}}}",
+            span.line, span.column
+        )),
+        Node::List(items) => {
+            out.push('[');
+            out.push_str(&start);
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(&separator);
+                }
+                write_json(item, indent + 1, out);
+            }
+            out.push_str(&end);
+            out.push(']');
+        }
+        Node::Object(kind, fields) => {
+            out.push('{');
+            out.push_str(&start);
+            out.push_str("\"kind\": ");
+            write_json_string(kind, out);
+            for (name, value) in fields {
+                out.push_str(&separator);
+                out.push_str(&format!("\"{}\": ", name));
+                write_json(value, indent + 1, out);
+            }
+            out.push_str(&end);
+            out.push('}');
+        }
+    }
+}
+
+fn write_json_string(value: &str, out: &mut String) {
+    out.push_str(&Json::String(value.to_string()).to_string());
+}
+
+// 1行に収まらない場合は子要素を1つずつ字下げした行に書く
+fn write_sexp(node: &Node, indent: usize, out: &mut String) {
+    let flat = sexp(node);
+    let children = match node {
+        Node::List(items) => items.iter().collect::<Vec<_>>(),
+        Node::Object(_, fields) => fields.iter().map(|(_, value)| value).collect(),
+        _ => Vec::new(),
+    };
+    if indent * 2 + flat.len() <= SEXP_WIDTH || children.is_empty() {
+        out.push_str(&flat);
+        return;
+    }
+    out.push('(');
+    if let Node::Object(kind, _) = node {
+        out.push_str(&snake_case(kind));
+    }
+    for (i, child) in children.into_iter().enumerate() {
+        if i == 0 && matches!(node, Node::List(_)) {
+            write_sexp(child, indent + 1, out);
+            continue;
+        }
+        out.push('\n');
+        out.push_str(&"  ".repeat(indent + 1));
+        write_sexp(child, indent + 1, out);
+    }
+    out.push(')');
+}
+
+// `(binary_op + (variable a @1:9) (integer 1) @1:11)` の形式
+fn sexp(node: &Node) -> String {
+    match node {
+        Node::Null => String::from("nil"),
+        Node::Bool(value) => value.to_string(),
+        Node::Int(value) => value.to_string(),
+        Node::Str(value) => Token::Str(value.clone()).to_string(),
+        Node::Name(name) => name.clone(),
+        Node::Span(span) => format!("@{}", span),
+        Node::List(items) => {
+            let items: Vec<String> = items.iter().map(sexp).collect();
+            format!("({})", items.join(" "))
+        }
+        Node::Object(kind, fields) => {
+            let mut text = format!("({}", snake_case(kind));
+            for (_, value) in fields {
+                text.push(' ');
+                text.push_str(&sexp(value));
+            }
+            text.push(')');
+            text
+        }
+    }
+}
+
+// `BinaryOp` -> `binary_op`
+fn snake_case(kind: &str) -> String {
+    let mut name = String::new();
+    for (i, c) in kind.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 {
+                name.push('_');
+            }
+            name.push(c.to_ascii_lowercase());
+        } else {
+            name.push(c);
+        }
+    }
+    name
+}
@@ -0,0 +1,74 @@
+use crate::ast::{Function, Span, Statement, Type};
+use std::fmt;
+
+// 入口にするユーザー定義の関数の名前
+pub const MAIN: &str = "main";
+
+// `fn main` の使い方の誤り
+#[derive(Debug, PartialEq, Clone)]
+pub enum EntryError {
+    InvalidSignature(Span),  // main の位置
+    MixedStyles(Span, Span), // トップレベルの文の位置, main の位置
+}
+
+impl EntryError {
+    pub fn span(&self) -> Span {
+        match self {
+            EntryError::InvalidSignature(span) | EntryError::MixedStyles(span, _) => *span,
+        }
+    }
+}
+
+impl fmt::Display for EntryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntryError::InvalidSignature(span) => write!(
+                f,
+                "{}: `main` must take no arguments and return `int` or `void`",
+                span
+            ),
+            EntryError::MixedStyles(span, main) => write!(
+                f,
+                "{}: Top-level statements cannot be mixed with `fn main` (defined at {}); move them into `main`",
+                span, main
+            ),
+        }
+    }
+}
+
+// プログラムの入口になる `fn main` (なければトップレベルの文を順に実行するスクリプト)
+pub fn find_main(statements: &[Statement]) -> Option<&Function> {
+    statements.iter().find_map(|statement| match statement {
+        Statement::Function(func) if func.name == MAIN => Some(func),
+        _ => None,
+    })
+}
+
+// `fn main` があるときは、トップレベルには関数・変数の宣言と `mod` `import` だけを書ける
+pub fn check(statements: &[Statement]) -> Vec<EntryError> {
+    let Some(main) = find_main(statements) else {
+        return Vec::new();
+    };
+    let mut errors = Vec::new();
+    if !main.params.is_empty() || !matches!(main.return_type, Type::Int | Type::Void) {
+        errors.push(EntryError::InvalidSignature(main.span));
+    }
+    // 最初の1つだけ報告する
+    let script = statements.iter().find(|statement| !is_item(statement));
+    if let Some(statement) = script {
+        let span = statement.span().unwrap_or(main.span);
+        errors.push(EntryError::MixedStyles(span, main.span));
+    }
+    errors
+}
+
+fn is_item(statement: &Statement) -> bool {
+    matches!(
+        statement,
+        Statement::Function(_)
+            | Statement::Extern(_)
+            | Statement::Declaration(..)
+            | Statement::Mod(_)
+            | Statement::Import(_)
+    )
+}
@@ -0,0 +1,144 @@
+use crate::ast::{Expr, Function, Operator, Span, Statement};
+use std::fmt;
+
+// 定数畳み込みで見つかったエラー
+#[derive(Debug)]
+pub enum FoldError {
+    DivisionByZero(Span),
+}
+
+impl fmt::Display for FoldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FoldError::DivisionByZero(span) => write!(f, "{}: Division by zero", span),
+        }
+    }
+}
+
+// プログラム全体の定数式を畳み込み、簡単な代数的な簡約を行う
+pub fn fold_program(statements: Vec<Statement>) -> Result<Vec<Statement>, FoldError> {
+    statements.into_iter().map(fold_statement).collect()
+}
+
+fn fold_statement(statement: Statement) -> Result<Statement, FoldError> {
+    let statement = match statement {
+        Statement::Expression(expr) => Statement::Expression(fold_expr(expr)?),
+        Statement::Declaration(name, expr, span) => {
+            Statement::Declaration(name, fold_expr(expr)?, span)
+        }
+        Statement::Assignment(name, expr, span) => {
+            Statement::Assignment(name, fold_expr(expr)?, span)
+        }
+        Statement::DerefAssignment(pointer, expr, span) => {
+            Statement::DerefAssignment(fold_expr(pointer)?, fold_expr(expr)?, span)
+        }
+        Statement::Print(expr) => Statement::Print(fold_expr(expr)?),
+        Statement::Block(statements) => Statement::Block(fold_program(statements)?),
+        Statement::If(condition, then_branch, else_branch, span) => {
+            let condition = fold_expr(*condition)?;
+            let then_branch = fold_statement(*then_branch)?;
+            let else_branch = else_branch.map(|e| fold_statement(*e)).transpose()?;
+            // 条件が定数なら実行される側だけを残す
+            match condition {
+                Expr::Integer(0) => else_branch.unwrap_or(Statement::Block(Vec::new())),
+                Expr::Integer(_) => then_branch,
+                condition => Statement::If(
+                    Box::new(condition),
+                    Box::new(then_branch),
+                    else_branch.map(Box::new),
+                    span,
+                ),
+            }
+        }
+        Statement::Function(func) => Statement::Function(Function {
+            body: fold_program(func.body)?,
+            return_expr: fold_expr(func.return_expr)?,
+            ..func
+        }),
+        statement @ (Statement::Mod(_) | Statement::Import(_) | Statement::Extern(_)) => statement,
+    };
+    Ok(statement)
+}
+
+pub fn fold_expr(expr: Expr) -> Result<Expr, FoldError> {
+    let expr = match expr {
+        Expr::BinaryOp(left, op, right, span) => {
+            let left = fold_expr(*left)?;
+            let right = fold_expr(*right)?;
+            return fold_binary(left, op, right, span);
+        }
+        Expr::Assign(name, value) => Expr::Assign(name, Box::new(fold_expr(*value)?)),
+        Expr::If(condition, then_branch, else_branch) => Expr::If(
+            Box::new(fold_expr(*condition)?),
+            Box::new(fold_statement(*then_branch)?),
+            else_branch
+                .map(|e| fold_statement(*e))
+                .transpose()?
+                .map(Box::new),
+        ),
+        Expr::FunctionCall(name, args, span) => Expr::FunctionCall(
+            name,
+            args.into_iter().map(fold_expr).collect::<Result<_, _>>()?,
+            span,
+        ),
+        Expr::Deref(pointer, span) => Expr::Deref(Box::new(fold_expr(*pointer)?), span),
+        Expr::New(value) => Expr::New(Box::new(fold_expr(*value)?)),
+        expr @ (Expr::Integer(_) | Expr::Str(_) | Expr::Variable(..) | Expr::AddressOf(_)) => expr,
+    };
+    Ok(expr)
+}
+
+fn fold_binary(left: Expr, op: Operator, right: Expr, span: Span) -> Result<Expr, FoldError> {
+    if op == Operator::Divide && right == Expr::Integer(0) {
+        return Err(FoldError::DivisionByZero(span));
+    }
+    // 整数はコード生成と同じく 32bit で計算する
+    // オーバーフローする演算は実行時の検査 (または折り返し) に任せるため畳み込まない
+    if let (Expr::Integer(l), Expr::Integer(r)) = (&left, &right) {
+        let (l, r) = (*l as i32, *r as i32);
+        let value = match op {
+            Operator::Plus => l.checked_add(r),
+            Operator::Minus => l.checked_sub(r),
+            Operator::Multiply => l.checked_mul(r),
+            Operator::Divide => l.checked_div(r),
+            Operator::Equals => Some((l == r) as i32),
+            Operator::MoreThan => Some((l > r) as i32),
+            Operator::LessThan => Some((l < r) as i32),
+        };
+        if let Some(value) = value {
+            return Ok(Expr::Integer(value as i64));
+        }
+    }
+
+    let simplified = match (&left, &op, &right) {
+        // x + 0, 0 + x, x - 0
+        (x, Operator::Plus, Expr::Integer(0))
+        | (Expr::Integer(0), Operator::Plus, x)
+        | (x, Operator::Minus, Expr::Integer(0)) => Some(x.clone()),
+        // x * 1, 1 * x, x / 1
+        (x, Operator::Multiply, Expr::Integer(1))
+        | (Expr::Integer(1), Operator::Multiply, x)
+        | (x, Operator::Divide, Expr::Integer(1)) => Some(x.clone()),
+        // x * 0, 0 * x (x の評価に副作用がない場合だけ)
+        (x, Operator::Multiply, Expr::Integer(0)) | (Expr::Integer(0), Operator::Multiply, x)
+            if is_pure(x) =>
+        {
+            Some(Expr::Integer(0))
+        }
+        _ => None,
+    };
+    Ok(simplified.unwrap_or_else(|| Expr::BinaryOp(Box::new(left), op, Box::new(right), span)))
+}
+
+// 評価しても副作用がない式かどうか
+fn is_pure(expr: &Expr) -> bool {
+    match expr {
+        Expr::Integer(_) | Expr::Str(_) | Expr::Variable(..) | Expr::AddressOf(_) => true,
+        Expr::BinaryOp(left, op, right, _) => {
+            // 0 除算の可能性がある割り算は消さない
+            *op != Operator::Divide && is_pure(left) && is_pure(right)
+        }
+        Expr::Deref(pointer, _) => is_pure(pointer),
+        Expr::Assign(..) | Expr::If(..) | Expr::FunctionCall(..) | Expr::New(_) => false,
+    }
+}
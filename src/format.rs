@@ -0,0 +1,302 @@
+use crate::ast::{Span, Token};
+use crate::lexer::{Comment, Lexer, LexerError};
+use crate::parser::{Parser, ParserError};
+use std::fmt;
+
+const INDENT: &str = "    ";
+// これより長い行は最初の引数リストを1引数ずつに折り返す
+const MAX_WIDTH: usize = 100;
+
+// 整形のエラーを表す列挙型
+#[derive(Debug)]
+pub enum FormatError {
+    Lexer(LexerError),
+    Parser(ParserError),
+    Unstable, // 整形結果のトークン列が元と一致しない
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Lexer(e) => write!(f, "{}", e),
+            FormatError::Parser(e) => write!(f, "{}", e),
+            FormatError::Unstable => write!(f, "Formatting would change the program"),
+        }
+    }
+}
+
+// ソースを標準の字下げと空白で整形する
+// 解析済みのトークン列を書き戻すので、整形結果は元と同じ AST に解析され、もう一度整形しても変わらない
+pub fn format_source(source: &str) -> Result<String, FormatError> {
+    let mut lexer = Lexer::new(source);
+    let (tokens, spans) = lexer.lex_with_spans().map_err(FormatError::Lexer)?;
+    let comments = lexer.take_comments();
+    // 構文として正しいプログラムだけを整形する
+    Parser::with_spans(tokens.clone(), spans.clone())
+        .parse_statements()
+        .map_err(FormatError::Parser)?;
+
+    let mut printer = Printer::new(source);
+    printer.print(&tokens, &spans, comments.clone());
+    let output = printer.out;
+
+    // トークン列とコメントが変わっていないことを確かめる
+    let mut relexer = Lexer::new(&output);
+    let relexed = relexer.lex().map_err(|_| FormatError::Unstable)?;
+    let same_comments = relexer
+        .take_comments()
+        .iter()
+        .map(|c| &c.text)
+        .eq(comments.iter().map(|c| &c.text));
+    if relexed != tokens || !same_comments {
+        return Err(FormatError::Unstable);
+    }
+    Ok(output)
+}
+
+// 1行に並べるトークン
+struct Piece {
+    text: String,
+    space_before: bool, // 直前のトークンとの間に空白を入れる
+}
+
+struct Printer<'s> {
+    source: Vec<&'s str>,    // 整数リテラルの元の表記を取り出すための行
+    out: String,             // 整形結果
+    line: Vec<Piece>,        // 出力中の行
+    comment: Option<String>, // 出力中の行の末尾に付けるコメント
+    depth: usize,            // `{` の深さ
+    continuation: bool,      // 文の途中で改行したので1段深く字下げする
+    last_line: usize,        // 最後に出力したトークンかコメントの元の行
+    blank_pending: bool,     // 次の行の前に空行を入れる
+    after_open: bool,        // 直前の行が `{` で終わっている
+}
+
+impl<'s> Printer<'s> {
+    fn new(source: &'s str) -> Self {
+        Printer {
+            source: source.split('\n').collect(),
+            out: String::new(),
+            line: Vec::new(),
+            comment: None,
+            depth: 0,
+            continuation: false,
+            last_line: 0,
+            blank_pending: false,
+            after_open: false,
+        }
+    }
+
+    fn print(&mut self, tokens: &[Token], spans: &[Span], comments: Vec<Comment>) {
+        let mut comments = comments.into_iter().peekable();
+        let mut previous: Option<&Token> = None;
+        let mut previous_unary = false;
+        for (i, (token, span)) in tokens.iter().zip(spans).enumerate() {
+            while let Some(comment) =
+                comments.next_if(|c| (c.span.line, c.span.column) < (span.line, span.column))
+            {
+                self.comment(comment);
+            }
+            match token {
+                Token::EOF => break,
+                Token::LeftBrace => {
+                    self.push("{", true, span.line);
+                    self.end_line();
+                    self.depth += 1;
+                    self.after_open = true;
+                }
+                Token::RightBrace => {
+                    self.end_line();
+                    self.depth = self.depth.saturating_sub(1);
+                    self.after_open = false;
+                    self.push("}", false, span.line);
+                    // `} else {` は1行に書く
+                    let is_else = matches!(tokens.get(i + 1), Some(Token::Identifier(name)) if name == "else");
+                    if !is_else {
+                        self.end_line();
+                        // トップレベルの関数や if の後には空行を入れる
+                        self.blank_pending = self.depth == 0;
+                    }
+                }
+                Token::Semicolon => {
+                    self.push(";", false, span.line);
+                    self.end_line();
+                }
+                _ => {
+                    if self.line.is_empty() {
+                        self.begin_line(span.line);
+                    }
+                    let space = space_between(previous, previous_unary, token);
+                    let text = match token {
+                        Token::Integer(value) => self.literal(*span).unwrap_or(value.to_string()),
+                        token => token.to_string(),
+                    };
+                    self.push(&text, space, span.line);
+                }
+            }
+            // `*` と `&` は直前が値なら二項演算子、そうでなければ単項演算子
+            previous_unary = matches!(token, Token::Asterisk | Token::Ampersand)
+                && !previous.is_some_and(Token::ends_operand);
+            previous = Some(token);
+        }
+        for comment in comments {
+            self.comment(comment);
+        }
+        self.end_line();
+    }
+
+    fn push(&mut self, text: &str, space_before: bool, source_line: usize) {
+        self.line.push(Piece {
+            text: text.to_string(),
+            space_before,
+        });
+        self.last_line = source_line;
+    }
+
+    // 新しい行を始める前に、元のソースで空いていた行を1行にまとめて残す
+    fn begin_line(&mut self, source_line: usize) {
+        let blank = self.blank_pending || source_line > self.last_line + 1;
+        if blank && !self.after_open && !self.out.is_empty() {
+            self.out.push('\n');
+        }
+        self.blank_pending = false;
+        self.after_open = false;
+    }
+
+    fn comment(&mut self, comment: Comment) {
+        let text = format!("//{}", comment.text);
+        let trailing = comment.span.line == self.last_line;
+        if !self.line.is_empty() {
+            // 文の途中のコメントの後は続きの行として字下げする
+            // (引数リストの途中の行末コメントもその行の末尾に残す)
+            if trailing {
+                self.comment = Some(text);
+                self.flush();
+            } else {
+                self.flush();
+                let indent = INDENT.repeat(self.depth + 1);
+                self.out.push_str(&format!("{}{}\n", indent, text));
+            }
+            self.continuation = true;
+        } else if trailing && !self.out.is_empty() {
+            // 前の行の末尾に付ける
+            self.out.pop();
+            self.out.push_str(&format!("  {}\n", text));
+        } else {
+            self.begin_line(comment.span.line);
+            let indent = INDENT.repeat(self.depth + self.continuation as usize);
+            self.out.push_str(&format!("{}{}\n", indent, text));
+        }
+        self.last_line = comment.span.line;
+    }
+
+    fn end_line(&mut self) {
+        self.flush();
+        self.continuation = false;
+    }
+
+    fn flush(&mut self) {
+        if self.line.is_empty() {
+            return;
+        }
+        let indent = self.depth + self.continuation as usize;
+        let pieces = std::mem::take(&mut self.line);
+        let flat = join(&pieces);
+        let text = if INDENT.len() * indent + flat.chars().count() > MAX_WIDTH {
+            wrap(&pieces, indent).unwrap_or(flat)
+        } else {
+            flat
+        };
+        self.out.push_str(&INDENT.repeat(indent));
+        self.out.push_str(&text);
+        if let Some(comment) = self.comment.take() {
+            self.out.push_str("  ");
+            self.out.push_str(&comment);
+        }
+        self.out.push('\n');
+    }
+
+    // 整数リテラルは `0xff` や `-1_000` などの元の表記のまま書き戻す
+    fn literal(&self, span: Span) -> Option<String> {
+        let line = self.source.get(span.line.checked_sub(1)?)?;
+        let literal: String = line
+            .chars()
+            .skip(span.column.checked_sub(1)?)
+            .enumerate()
+            .take_while(|&(i, c)| c.is_ascii_alphanumeric() || c == '_' || (i == 0 && c == '-'))
+            .map(|(_, c)| c)
+            .collect();
+        (!literal.is_empty()).then_some(literal)
+    }
+}
+
+fn join(pieces: &[Piece]) -> String {
+    let mut text = String::new();
+    for (i, piece) in pieces.iter().enumerate() {
+        if i > 0 && piece.space_before {
+            text.push(' ');
+        }
+        text.push_str(&piece.text);
+    }
+    text
+}
+
+// カンマを含む最初の括弧の中身を1引数ずつの行に分ける
+fn wrap(pieces: &[Piece], indent: usize) -> Option<String> {
+    for open in (0..pieces.len()).filter(|&i| pieces[i].text == "(") {
+        let mut depth = 0;
+        let mut commas = Vec::new();
+        let mut close = None;
+        for (i, piece) in pieces.iter().enumerate().skip(open) {
+            match piece.text.as_str() {
+                "(" => depth += 1,
+                ")" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = Some(i);
+                        break;
+                    }
+                }
+                "," if depth == 1 => commas.push(i),
+                _ => {}
+            }
+        }
+        let Some(close) = close else { continue };
+        if commas.is_empty() {
+            continue;
+        }
+
+        let mut text = join(&pieces[..=open]);
+        let mut start = open + 1;
+        for end in commas.into_iter().chain([close]) {
+            text.push('\n');
+            text.push_str(&INDENT.repeat(indent + 1));
+            text.push_str(&join(&pieces[start..end]));
+            if end != close {
+                text.push(',');
+            }
+            start = end + 1;
+        }
+        text.push('\n');
+        text.push_str(&INDENT.repeat(indent));
+        text.push_str(&join(&pieces[close..]));
+        return Some(text);
+    }
+    None
+}
+
+// 直前のトークンとの間に空白を入れるかどうか
+fn space_between(previous: Option<&Token>, previous_unary: bool, token: &Token) -> bool {
+    match (previous, token) {
+        (None, _) => false,
+        (
+            _,
+            Token::RightParen | Token::Comma | Token::Semicolon | Token::Colon | Token::DoubleColon,
+        ) => false,
+        (Some(Token::LeftParen | Token::DoubleColon), _) => false,
+        (Some(Token::Asterisk | Token::Ampersand), _) if previous_unary => false,
+        // 関数呼び出しと `print(...)`、`new(...)`
+        (Some(Token::Identifier(_) | Token::Print | Token::New), Token::LeftParen) => false,
+        _ => true,
+    }
+}
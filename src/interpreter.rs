@@ -0,0 +1,509 @@
+use crate::ast::{Expr, ExternFunction, Function, Operator, Span, Statement, Type};
+use crate::builtin::{self, Builtin};
+use crate::entry;
+use crate::panic::{PanicKind, MAX_FRAMES};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+// 実行時の値 (整数はコード生成と同じく 32bit で扱う)
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int(i32),
+    Str(Rc<str>),
+    Pointer(Rc<RefCell<Value>>), // `&x` や `new(value)` が指す領域
+    Unit,                        // void 関数の戻り値
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Str(value) => write!(f, "{}", value),
+            Value::Pointer(cell) => write!(f, "{:p}", Rc::as_ptr(cell)),
+            Value::Unit => write!(f, "()"),
+        }
+    }
+}
+
+// 実行時エラー
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub message: String,
+    pub span: Option<Span>,
+    pub kind: Option<PanicKind>, // プログラムのパニック (None はインタプリタ自体のエラー)
+}
+
+impl RuntimeError {
+    pub(crate) fn new(message: impl Into<String>, span: Option<Span>) -> Self {
+        RuntimeError {
+            message: message.into(),
+            span,
+            kind: None,
+        }
+    }
+
+    pub(crate) fn panic(kind: PanicKind, message: impl Into<String>, span: Span) -> Self {
+        RuntimeError {
+            message: message.into(),
+            span: Some(span),
+            kind: Some(kind),
+        }
+    }
+
+    // パニックは種類ごとの終了コード、それ以外は 1
+    pub fn exit_code(&self) -> i32 {
+        self.kind.map_or(1, PanicKind::exit_code)
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "{}: {}", span, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+// 変数の環境 (ブロックごとにスコープを積む)
+struct Environment {
+    scopes: Vec<HashMap<String, Rc<RefCell<Value>>>>,
+}
+
+impl Environment {
+    fn new() -> Self {
+        Environment {
+            scopes: vec![HashMap::new()],
+        }
+    }
+
+    fn define(&mut self, name: &str, value: Value) {
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), Rc::new(RefCell::new(value)));
+    }
+
+    fn lookup(&self, name: &str) -> Option<Rc<RefCell<Value>>> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+    }
+}
+
+// 呼び出し可能な関数
+enum Callable {
+    User(Rc<Function>),
+    Extern(ExternFunction),
+}
+
+// AST を直接実行するインタプリタ
+// 関数呼び出しごとに Rust のスタックを使うので、MAX_FRAMES まで再帰するには大きなスタックのスレッドで実行する
+pub struct Interpreter {
+    functions: HashMap<String, Callable>,
+    frames: Vec<Environment>, // 関数呼び出しごとの環境
+    output: Box<dyn Write>,
+    overflow_checks: bool, // `+` `-` `*` `/` のオーバーフローでパニックする
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter::with_output(Box::new(io::stdout()))
+    }
+
+    // print の出力先を指定して作成
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        Interpreter {
+            functions: HashMap::new(),
+            frames: vec![Environment::new()],
+            output,
+            overflow_checks: true,
+        }
+    }
+
+    // 整数演算のオーバーフローの検査を切り替える (無効にすると 32bit で折り返す)
+    pub fn set_overflow_checks(&mut self, enabled: bool) {
+        self.overflow_checks = enabled;
+    }
+
+    // 文のリストを順番に実行し、終了コードを返す
+    // `fn main` があれば最後に呼び出し、int を返すならその戻り値を終了コードにする
+    pub fn run(&mut self, statements: &[Statement]) -> Result<i32, RuntimeError> {
+        self.declare_functions(statements);
+        for statement in statements {
+            self.execute(statement)?;
+        }
+        let exit_code = match entry::find_main(statements) {
+            Some(main) => match self.call(entry::MAIN, Vec::new(), main.span)? {
+                Value::Int(code) if main.return_type == Type::Int => code,
+                _ => 0,
+            },
+            None => 0,
+        };
+        self.output
+            .flush()
+            .map_err(|e| RuntimeError::new(e.to_string(), None))?;
+        Ok(exit_code)
+    }
+
+    // 関数は定義より前からも呼べるので、実行する前に全て登録する
+    fn declare_functions(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Function(func) => {
+                    self.functions
+                        .insert(func.name.clone(), Callable::User(Rc::new(func.clone())));
+                    self.declare_functions(&func.body);
+                }
+                Statement::Extern(func) => {
+                    self.functions
+                        .insert(func.name.clone(), Callable::Extern(func.clone()));
+                }
+                Statement::Block(body) => self.declare_functions(body),
+                Statement::If(_, then_branch, else_branch, _) => {
+                    self.declare_functions(std::slice::from_ref(then_branch));
+                    if let Some(else_branch) = else_branch {
+                        self.declare_functions(std::slice::from_ref(else_branch));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn env(&mut self) -> &mut Environment {
+        self.frames.last_mut().unwrap()
+    }
+
+    // 変数を探す (関数の中ではローカル変数の次にトップレベルの変数を探す)
+    fn lookup(&self, name: &str) -> Option<Rc<RefCell<Value>>> {
+        let (globals, frames) = self.frames.split_first()?;
+        match frames.last() {
+            Some(frame) => frame
+                .lookup(name)
+                .or_else(|| globals.scopes[0].get(name).cloned()),
+            None => globals.lookup(name),
+        }
+    }
+
+    // 文を実行し、その値を返す (式文以外は Unit)
+    pub fn execute(&mut self, statement: &Statement) -> Result<Value, RuntimeError> {
+        match statement {
+            Statement::Expression(expr) => self.evaluate(expr),
+            Statement::Declaration(name, expr, _) => {
+                let value = self.evaluate(expr)?;
+                self.env().define(name, value);
+                Ok(Value::Unit)
+            }
+            Statement::Assignment(name, expr, span) => {
+                let value = self.evaluate(expr)?;
+                self.assign(name, value, Some(*span))?;
+                Ok(Value::Unit)
+            }
+            Statement::DerefAssignment(pointer, expr, _) => {
+                let cell = self.evaluate_pointer(pointer)?;
+                let value = self.evaluate(expr)?;
+                *cell.borrow_mut() = value;
+                Ok(Value::Unit)
+            }
+            Statement::Print(expr) => {
+                let value = self.evaluate(expr)?;
+                writeln!(self.output, "{}", value)
+                    .map_err(|e| RuntimeError::new(e.to_string(), None))?;
+                Ok(Value::Unit)
+            }
+            Statement::Block(statements) => {
+                self.env().scopes.push(HashMap::new());
+                let result = statements
+                    .iter()
+                    .try_for_each(|stmt| self.execute(stmt).map(|_| ()));
+                self.env().scopes.pop();
+                result.map(|_| Value::Unit)
+            }
+            Statement::If(condition, then_branch, else_branch, _) => {
+                if self.evaluate_condition(condition)? {
+                    self.execute(then_branch)?;
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)?;
+                }
+                Ok(Value::Unit)
+            }
+            Statement::Function(func) => {
+                self.functions
+                    .insert(func.name.clone(), Callable::User(Rc::new(func.clone())));
+                Ok(Value::Unit)
+            }
+            Statement::Extern(func) => {
+                self.functions
+                    .insert(func.name.clone(), Callable::Extern(func.clone()));
+                Ok(Value::Unit)
+            }
+            // モジュールはローダーが解決済み
+            Statement::Mod(_) | Statement::Import(_) => Ok(Value::Unit),
+        }
+    }
+
+    fn assign(&mut self, name: &str, value: Value, span: Option<Span>) -> Result<(), RuntimeError> {
+        let cell = self
+            .lookup(name)
+            .ok_or_else(|| RuntimeError::new(format!("Undefined variable `{}`", name), span))?;
+        *cell.borrow_mut() = value;
+        Ok(())
+    }
+
+    fn evaluate_condition(&mut self, expr: &Expr) -> Result<bool, RuntimeError> {
+        match self.evaluate(expr)? {
+            Value::Int(value) => Ok(value != 0),
+            other => Err(RuntimeError::new(
+                format!("Condition must be an integer, found `{}`", other),
+                expr_span(expr),
+            )),
+        }
+    }
+
+    fn evaluate_pointer(&mut self, expr: &Expr) -> Result<Rc<RefCell<Value>>, RuntimeError> {
+        match self.evaluate(expr)? {
+            Value::Pointer(cell) => Ok(cell),
+            other => Err(RuntimeError::new(
+                format!("Cannot dereference `{}`", other),
+                expr_span(expr),
+            )),
+        }
+    }
+
+    // 式を評価する
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        match expr {
+            Expr::Integer(value) => Ok(Value::Int(*value as i32)),
+            Expr::Str(value) => Ok(Value::Str(Rc::from(value.as_str()))),
+            Expr::Variable(name, span) => self
+                .lookup(name)
+                .map(|cell| cell.borrow().clone())
+                .ok_or_else(|| {
+                    RuntimeError::new(format!("Undefined variable `{}`", name), Some(*span))
+                }),
+            Expr::Assign(name, value) => {
+                let value = self.evaluate(value)?;
+                self.assign(name, value.clone(), None)?;
+                Ok(value)
+            }
+            Expr::BinaryOp(left, op, right, span) => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                binary_op(left, op, right, *span, self.overflow_checks)
+            }
+            Expr::If(condition, then_branch, else_branch) => {
+                if self.evaluate_condition(condition)? {
+                    self.execute(then_branch)?;
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)?;
+                }
+                Ok(Value::Int(0))
+            }
+            Expr::AddressOf(name) => {
+                self.lookup(name).map(Value::Pointer).ok_or_else(|| {
+                    RuntimeError::new(format!("Undefined variable `{}`", name), None)
+                })
+            }
+            Expr::Deref(pointer, _) => Ok(self.evaluate_pointer(pointer)?.borrow().clone()),
+            Expr::New(value) => {
+                let value = self.evaluate(value)?;
+                Ok(Value::Pointer(Rc::new(RefCell::new(value))))
+            }
+            Expr::FunctionCall(name, args, span) => {
+                let mut values = Vec::new();
+                for arg in args {
+                    values.push(self.evaluate(arg)?);
+                }
+                self.call(name, values, *span)
+            }
+        }
+    }
+
+    fn call(&mut self, name: &str, args: Vec<Value>, span: Span) -> Result<Value, RuntimeError> {
+        let function = match self.functions.get(name) {
+            Some(Callable::User(function)) => Rc::clone(function),
+            Some(Callable::Extern(function)) => {
+                return call_intrinsic(&function.name, &args, &mut self.output, span);
+            }
+            None if builtin::lookup(name).is_some() => {
+                return call_intrinsic(name, &args, &mut self.output, span);
+            }
+            None => {
+                return Err(RuntimeError::new(
+                    format!("Undefined function `{}`", name),
+                    Some(span),
+                ))
+            }
+        };
+        // トップレベルの環境も1つと数える (バイトコードの VM と同じ深さで止まる)
+        if self.frames.len() >= MAX_FRAMES {
+            return Err(RuntimeError::panic(
+                PanicKind::StackOverflow,
+                "Stack overflow",
+                span,
+            ));
+        }
+        if args.len() != function.params.len() {
+            return Err(RuntimeError::new(
+                format!(
+                    "`{}` takes {} argument(s) but {} were given",
+                    name,
+                    function.params.len(),
+                    args.len()
+                ),
+                Some(span),
+            ));
+        }
+
+        // 呼び出しごとに新しい環境を作り、引数を変数として定義する
+        let mut env = Environment::new();
+        for ((param, _), value) in function.params.iter().zip(args) {
+            env.define(param, value);
+        }
+        self.frames.push(env);
+        let result = function
+            .body
+            .iter()
+            .try_for_each(|stmt| self.execute(stmt).map(|_| ()))
+            .and_then(|_| self.evaluate(&function.return_expr));
+        self.frames.pop();
+        result
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// C関数はインタプリタが用意した一部の関数だけ呼び出せる (組み込み関数もここで計算する)
+pub(crate) fn call_intrinsic(
+    name: &str,
+    args: &[Value],
+    output: &mut dyn Write,
+    span: Span,
+) -> Result<Value, RuntimeError> {
+    if let Some(builtin) = builtin::lookup(name) {
+        return call_builtin(&builtin, name, args, span);
+    }
+    let io_error = |e: io::Error| RuntimeError::new(e.to_string(), Some(span));
+    match (name, args) {
+        ("puts", [value]) => {
+            writeln!(output, "{}", value).map_err(io_error)?;
+            Ok(Value::Int(0))
+        }
+        ("putchar", [Value::Int(code)]) => {
+            let c = char::from_u32(*code as u32).unwrap_or('?');
+            write!(output, "{}", c).map_err(io_error)?;
+            Ok(Value::Int(*code))
+        }
+        ("abs", [Value::Int(value)]) => Ok(Value::Int(value.wrapping_abs())),
+        ("exit", [Value::Int(code)]) => {
+            output.flush().map_err(io_error)?;
+            std::process::exit(*code)
+        }
+        _ => Err(RuntimeError::new(
+            format!("extern fn `{}` cannot be called by the interpreter", name),
+            Some(span),
+        )),
+    }
+}
+
+// 組み込み関数の呼び出し
+fn call_builtin(
+    builtin: &Builtin,
+    name: &str,
+    args: &[Value],
+    span: Span,
+) -> Result<Value, RuntimeError> {
+    let invalid = || RuntimeError::new(format!("Invalid arguments for `{}`", name), Some(span));
+    match (builtin, args) {
+        (Builtin::Assert, [Value::Int(0)]) => Err(RuntimeError::panic(
+            PanicKind::Assertion,
+            "Assertion failed",
+            span,
+        )),
+        (Builtin::Assert, [Value::Int(_)]) => Ok(Value::Unit),
+        (Builtin::Panic, [message]) => Err(RuntimeError::panic(
+            PanicKind::Explicit,
+            message.to_string(),
+            span,
+        )),
+        (_, [Value::Int(left), Value::Int(right)]) => builtin
+            .evaluate(*left, *right)
+            .map(Value::Int)
+            .ok_or_else(invalid),
+        _ => Err(invalid()),
+    }
+}
+
+// 二項演算 (overflow_checks が有効ならオーバーフローでパニックし、無効なら 32bit の2の補数で折り返す)
+pub(crate) fn binary_op(
+    left: Value,
+    op: &Operator,
+    right: Value,
+    span: Span,
+    overflow_checks: bool,
+) -> Result<Value, RuntimeError> {
+    match (left, right) {
+        (Value::Int(l), Value::Int(r)) => {
+            let checked = match op {
+                Operator::Plus => l.checked_add(r),
+                Operator::Minus => l.checked_sub(r),
+                Operator::Multiply => l.checked_mul(r),
+                Operator::Divide if r == 0 => {
+                    return Err(RuntimeError::panic(
+                        PanicKind::DivisionByZero,
+                        "Division by zero",
+                        span,
+                    ))
+                }
+                // int の最小値 / -1 だけがオーバーフローする
+                Operator::Divide => l.checked_div(r),
+                Operator::Equals => Some((l == r) as i32),
+                Operator::MoreThan => Some((l > r) as i32),
+                Operator::LessThan => Some((l < r) as i32),
+            };
+            let value = match checked {
+                Some(value) => value,
+                None if overflow_checks => {
+                    return Err(RuntimeError::panic(
+                        PanicKind::Overflow,
+                        format!("Arithmetic overflow in `{}`", op),
+                        span,
+                    ))
+                }
+                None => match op {
+                    Operator::Plus => l.wrapping_add(r),
+                    Operator::Minus => l.wrapping_sub(r),
+                    Operator::Multiply => l.wrapping_mul(r),
+                    _ => l.wrapping_div(r),
+                },
+            };
+            Ok(Value::Int(value))
+        }
+        (Value::Str(l), Value::Str(r)) if *op == Operator::Plus => {
+            Ok(Value::Str(Rc::from(format!("{}{}", l, r))))
+        }
+        (l, r) => Err(RuntimeError::new(
+            format!("Unsupported operands for {:?}: `{}` and `{}`", op, l, r),
+            Some(span),
+        )),
+    }
+}
+
+// エラー表示に使う式の位置
+fn expr_span(expr: &Expr) -> Option<Span> {
+    match expr {
+        Expr::Variable(_, span)
+        | Expr::BinaryOp(_, _, _, span)
+        | Expr::FunctionCall(_, _, span) => Some(*span),
+        _ => None,
+    }
+}
@@ -1,12 +1,27 @@
 extern crate inkwell;
 
-use crate::ast::{Expr, Operator, Statement, Type};
+mod arith;
+mod debug;
+mod panic;
+mod program;
+mod refcount;
+
+use self::debug::DebugInfo;
+use self::program::symbol_name;
+use self::refcount::{is_managed_type, Ownership};
+use crate::ast::{ExternFunction, Expr, Operator, Span, Statement, Type};
+use crate::builtin::{self, Builtin};
+use inkwell::basic_block::BasicBlock;
 use inkwell::builder::Builder;
 use inkwell::context::Context;
-use inkwell::module::Module;
-use inkwell::values::{FunctionValue, PointerValue};
+use inkwell::module::{Linkage, Module};
+use inkwell::targets::{TargetData, TargetMachine};
+use inkwell::types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType};
+use inkwell::values::{BasicValueEnum, FunctionValue, GlobalValue, PointerValue};
+use inkwell::AddressSpace;
 use inkwell::IntPredicate;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 
 // IR生成器の構造体
 pub struct IRGenerator<'a> {
@@ -14,13 +29,37 @@ pub struct IRGenerator<'a> {
     pub module: Module<'a>,
     builder: Builder<'a>,
     variables: HashMap<String, PointerValue<'a>>,
-    printf_func: Option<FunctionValue<'a>>,
+    managed_variables: HashSet<String>, // 参照カウントを持つ値を保持している変数
+    managed_scopes: Vec<Vec<PointerValue<'a>>>, // スコープごとの解放が必要な変数
+    managed_functions: HashSet<String>, // 参照カウントを持つ値を返す関数
+    globals: HashMap<String, PointerValue<'a>>, // 関数から参照できるトップレベルの変数
+    managed_globals: HashSet<String>,   // 参照カウントを持つ値を保持しているトップレベルの変数
+    global_declarations: HashMap<(String, usize, usize), GlobalValue<'a>>, // 宣言の位置ごとのグローバル変数
+    leak_check: bool,                   // 終了時に解放されていないオブジェクトを報告する
+    target_data: Option<TargetData>,    // 型のサイズの計算に使うターゲットのデータレイアウト
+    debug_info: Option<DebugInfo<'a>>,  // `-g` で出力するデバッグ情報
+    overflow_checks: bool,              // `+` `-` `*` `/` のオーバーフローを実行時に検査する
+    source_files: Vec<PathBuf>,         // Span の file が指すソースファイル (実行時のエラーとデバッグ情報に使う)
 }
 
 impl<'a> IRGenerator<'a> {
-    pub fn initialize_entry_block(&mut self, function: &FunctionValue) {
+    pub fn initialize_entry_block(&mut self, function: &FunctionValue<'a>) {
         let entry_block = self.context.append_basic_block(*function, "entry");
         self.builder.position_at_end(entry_block);
+        // トップレベルの文は初期化関数 (REPL では int を返す main) の中にあるものとして扱う
+        let name = function.get_name().to_string_lossy().into_owned();
+        let return_type = match function.get_type().get_return_type() {
+            Some(_) => Type::Int,
+            None => Type::Void,
+        };
+        self.begin_debug_function(*function, &name, Span::new(1, 1), &[], &return_type);
+    }
+    // 新しい基本ブロックへ分岐し、以降の命令をそこに追加する
+    pub fn begin_block(&mut self, function: &FunctionValue<'a>, name: &str) -> BasicBlock<'a> {
+        let block = self.context.append_basic_block(*function, name);
+        self.builder.build_unconditional_branch(block).expect("Failed to build branch");
+        self.builder.position_at_end(block);
+        block
     }
     // Moduleの取得
     pub fn get_module(&self) -> &Module<'a> {
@@ -31,51 +70,138 @@ impl<'a> IRGenerator<'a> {
         let module = context.create_module("main");
         let builder = context.create_builder();
 
-        IRGenerator {
+        let generator = IRGenerator {
             context,
             module,
             builder,
             variables: HashMap::new(), // 変数の保持用
-            printf_func: None,
-        }
+            managed_variables: HashSet::new(),
+            managed_scopes: vec![Vec::new()], // トップレベルのスコープ
+            managed_functions: HashSet::new(),
+            globals: HashMap::new(),
+            managed_globals: HashSet::new(),
+            global_declarations: HashMap::new(),
+            leak_check: false,
+            target_data: None,
+            debug_info: None,
+            overflow_checks: false,
+            source_files: Vec::new(),
+        };
+        generator.link_runtime();
+        generator
+    }
+    // モジュールのターゲットトリプルとデータレイアウトを設定する
+    pub fn set_target(&mut self, machine: &TargetMachine) {
+        let target_data = machine.get_target_data();
+        self.module.set_triple(&machine.get_triple());
+        self.module.set_data_layout(&target_data.get_data_layout());
+        self.target_data = Some(target_data);
+    }
+    // 終了時のリーク検出を有効にする
+    pub fn set_leak_check(&mut self, enabled: bool) {
+        self.leak_check = enabled;
+    }
+    // 整数演算のオーバーフローの検査を有効にする
+    pub fn set_overflow_checks(&mut self, enabled: bool) {
+        self.overflow_checks = enabled;
+    }
+    // 実行時のエラーメッセージに表示するソースファイルを設定する (ローダーが読み込んだ順)
+    pub fn set_source_files(&mut self, files: &[PathBuf]) {
+        self.source_files = files.to_vec();
     }
     // 関数のリターン命令を生成
     pub fn build_return(&self, value: inkwell::values::IntValue) {
         self.builder.build_return(Some(&value)).expect("Failed to build return");
     }
+    // トップレベルの変数を解放してから main を終える
+    pub fn finish_main(&mut self, exit_code: inkwell::values::IntValue<'a>) {
+        self.pop_scope();
+        if self.leak_check {
+            let report = self.module.get_function("omochi_report_leaks").unwrap();
+            self.builder
+                .build_call(report, &[], "")
+                .expect("Failed to build call");
+        }
+        self.build_return(exit_code);
+        self.finalize_debug_info();
+    }
     fn get_printf_function(&mut self) -> FunctionValue<'a> {
-        if let Some(func) = self.printf_func {
-            return func;
+        // `int printf(const char *, ...)`
+        let printf = ExternFunction {
+            name: String::from("printf"),
+            is_pub: false,
+            params: vec![(String::from("format"), Type::Str)],
+            variadic: true,
+            return_type: Type::Int,
+        };
+        self.declare_extern_function(&printf)
+    }
+    // C関数の宣言を追加する (同名の宣言が既にあればそれを使う)
+    fn declare_extern_function(&mut self, func: &ExternFunction) -> FunctionValue<'a> {
+        if let Some(existing) = self.module.get_function(&func.name) {
+            return existing;
+        }
+        let fn_type = self.function_type(&func.return_type, &func.params, func.variadic);
+        self.module.add_function(&func.name, fn_type, Some(Linkage::External))
+    }
+    // omochiの型をC ABIに合わせたLLVMの型に変換
+    fn basic_type(&self, ty: &Type) -> BasicTypeEnum<'a> {
+        match ty {
+            // int
+            Type::Int => self.context.i32_type().into(),
+            // char * (string は参照カウント付きのヒープ文字列)
+            Type::Str | Type::String => {
+                self.context.i8_type().ptr_type(AddressSpace::default()).into()
+            }
+            Type::Void => panic!("void is only allowed as a return type"),
+            // T * (void へのポインタは char * として扱う)
+            Type::Pointer(inner) | Type::Reference(inner) => match **inner {
+                Type::Void => self.context.i8_type().ptr_type(AddressSpace::default()).into(),
+                ref pointee => self.basic_type(pointee).ptr_type(AddressSpace::default()).into(),
+            },
+        }
+    }
+    // 関数シグネチャのLLVM型を作成
+    fn function_type(
+        &self,
+        return_type: &Type,
+        params: &[(String, Type)],
+        variadic: bool,
+    ) -> FunctionType<'a> {
+        let param_types: Vec<BasicMetadataTypeEnum> = params
+            .iter()
+            .map(|(_, param_type)| self.basic_type(param_type).into())
+            .collect();
+        match return_type {
+            Type::Void => self.context.void_type().fn_type(&param_types, variadic),
+            other => self.basic_type(other).fn_type(&param_types, variadic),
         }
-
-        let printf_type = self
-            .context
-            .i8_type()
-            .ptr_type(inkwell::AddressSpace::default())
-            .fn_type(
-                &[self
-                    .context
-                    .i8_type()
-                    .ptr_type(inkwell::AddressSpace::default())
-                    .into()],
-                true,
-            );
-
-        let printf_func = self.module.add_function("printf", printf_type, None);
-        self.printf_func = Some(printf_func);
-        printf_func
     }
     // StatementタイプのIRを生成するメソッド
     pub fn generate_ir_for_statement(
         &mut self,
         statement: &Statement,
         function: &FunctionValue<'a>,
-    ) -> Result<inkwell::values::IntValue<'a>, ()> {
+    ) -> Result<BasicValueEnum<'a>, ()> {
+        if let Some(span) = statement.span() {
+            self.set_debug_location(span);
+        }
         match statement {
-            Statement::Expression(expr) => Ok(self.generate_ir_inner(expr, function)),
-            Statement::Declaration(name, expr) => {
-                self.generate_declaration_ir(name, expr, function).unwrap();
-                Ok(self.context.i32_type().const_int(0, false))
+            Statement::Expression(expr) => {
+                let value = self.generate_ir_inner(expr, function);
+                // 使われない一時的なヒープ値はすぐに解放する
+                let ownership = self.ownership(expr, value);
+                self.release_if_owned(value, ownership);
+                Ok(value)
+            }
+            Statement::Declaration(name, expr, span) => {
+                // トップレベルの変数はグローバル変数にする
+                if self.managed_scopes.len() == 1 {
+                    self.generate_global_declaration(name, expr, *span, function);
+                } else {
+                    self.generate_declaration_ir(name, expr, *span, function).unwrap();
+                }
+                Ok(self.context.i32_type().const_int(0, false).into())
             }
             Statement::Print(expr) => {
                 // printf関数の取得
@@ -84,10 +210,19 @@ impl<'a> IRGenerator<'a> {
                 // exprを評価してvalue_to_printを生成
                 let value_to_print = self.generate_ir_inner(expr, function);
             
-                // フォーマット文字列の定義
+                // フォーマット文字列の定義 (文字列は %s、その他のポインタは %p、整数は %d)
+                let format = match value_to_print {
+                    BasicValueEnum::PointerValue(pointer)
+                        if pointer.get_type().get_element_type() == self.context.i8_type().into() =>
+                    {
+                        "%s\n"
+                    }
+                    BasicValueEnum::PointerValue(_) => "%p\n",
+                    _ => "%d\n",
+                };
                 let format_str = self
                     .builder
-                    .build_global_string_ptr("%d\n", "fmt")
+                    .build_global_string_ptr(format, "fmt")
                     .expect("Failed to create format string");
             
                 // printf関数の呼び出し
@@ -96,201 +231,331 @@ impl<'a> IRGenerator<'a> {
                     &[format_str.as_pointer_value().into(), value_to_print.into()],
                     "printf_call",
                 ).expect("Failed to build call");
+                let ownership = self.ownership(expr, value_to_print);
+                self.release_if_owned(value_to_print, ownership);
             
                 // Print文は値を返さないので、0を返す
-                Ok(self.context.i32_type().const_int(0, false))
+                Ok(self.context.i32_type().const_int(0, false).into())
             }
-            Statement::If(condition, then_branch, else_branch) => {
-                // If文のIR生成ロジック
-                let condition_value = self.generate_ir_inner(condition, function);
-                let then_block = self.context.append_basic_block(*function, "then");
-                let else_block = self.context.append_basic_block(*function, "else");
-                let continue_block = self.context.append_basic_block(*function, "ifcont");
-
-                self.builder
-                    .build_conditional_branch(condition_value, then_block, else_block)
-                    .expect("Failed to build conditional branch");
-
-                self.builder.position_at_end(then_block);
-                self.generate_ir_for_statement(then_branch, function)
-                    .expect("Failed to generate ir for statement");
-                self.builder.build_unconditional_branch(continue_block)
-                    .expect("Failed to build unconditional branch");
-
-                self.builder.position_at_end(else_block);
-                if let Some(else_stmt) = else_branch {
-                    self.generate_ir_for_statement(else_stmt, function)
-                        .expect("Failed to generate ir for statement");
-                }
-                self.builder.build_unconditional_branch(continue_block)
-                    .expect("Failed to build unconditional branch");
-
-                self.builder.position_at_end(continue_block);
-
-                Ok(self.context.i32_type().const_int(0, false))
+            Statement::If(condition, then_branch, else_branch, _) => {
+                self.generate_if(condition, then_branch, else_branch.as_deref(), function);
+                Ok(self.context.i32_type().const_int(0, false).into())
             }
             Statement::Function(func) => {
-                // 関数シグネチャの生成
-                let return_type = match func.return_type {
-                    Type::Int => self.context.i32_type(),
-                    // 他の型の場合の処理
-                };
-                let fn_type = return_type.fn_type(&[], false);
-                let function = self.module.add_function(&func.name, fn_type, None);
+                // 関数シグネチャの生成 (宣言済みならその宣言に本体を付ける)
+                let function = self.declare_function(func);
+
+                // 呼び出し元の挿入位置と変数を退避 (関数からはトップレベルの変数だけが見える)
+                let saved_block = self.builder.get_insert_block();
+                let saved_variables = std::mem::replace(&mut self.variables, self.globals.clone());
+                let saved_managed =
+                    std::mem::replace(&mut self.managed_variables, self.managed_globals.clone());
+                self.push_scope();
             
                 // 関数のエントリーブロックの生成
                 let entry_block = self.context.append_basic_block(function, "entry");
                 self.builder.position_at_end(entry_block);
+                self.begin_debug_function(
+                    function,
+                    &func.name,
+                    func.span,
+                    &func.params,
+                    &func.return_type,
+                );
+
+                // 引数をallocaに格納して変数として登録
+                // 参照カウントを持つ引数は関数の中で1つ参照を持ち、終了時に解放する
+                // (型検査で、型の分からない値やリテラル・`&x` は渡せないようになっている)
+                for (i, (name, param_type)) in func.params.iter().enumerate() {
+                    let param = function.get_nth_param(i as u32).expect("Missing parameter");
+                    let alloca = self
+                        .create_entry_block_alloca(&function, name, param.get_type())
+                        .unwrap();
+                    self.builder.build_store(alloca, param).expect("Failed to build store");
+                    self.declare_debug_variable(name, alloca, func.span, Some(i as u32 + 1));
+                    self.variables.insert(name.clone(), alloca);
+                    if is_managed_type(param_type) {
+                        self.build_retain(param);
+                        self.register_managed_variable(name, alloca);
+                    } else {
+                        self.managed_variables.remove(name);
+                    }
+                }
             
                 // 関数本体の生成
-                let mut last_instruction = None;
                 for statement in &func.body {
-                    let instruction = self.generate_ir_for_statement(statement, &function)?;
-                    last_instruction = Some(instruction);
+                    self.generate_ir_for_statement(statement, &function)?;
                 }
             
-                // 関数のエンドポイントの設定
-                if let Some(instruction) = last_instruction {
-                    self.build_return_instruction(Some(&instruction));
+                // 最後の式を戻り値にする
+                let return_value = self.generate_ir_inner(&func.return_expr, &function);
+                let ownership = self.ownership(&func.return_expr, return_value);
+                if is_managed_type(&func.return_type) {
+                    // 呼び出し元には参照を1つ渡す (型検査で戻り値は管理された値に限られる)
+                    self.take_ownership(return_value, ownership);
                 } else {
-                    // Void
+                    self.release_if_owned(return_value, ownership);
+                }
+                self.pop_scope();
+                if func.return_type == Type::Void {
                     self.build_return_instruction(None);
+                } else {
+                    self.build_return_instruction(Some(&return_value));
+                }
+
+                // 呼び出し元に戻る
+                self.end_debug_scope();
+                self.variables = saved_variables;
+                self.managed_variables = saved_managed;
+                if let Some(block) = saved_block {
+                    self.builder.position_at_end(block);
                 }
             
                 // ダミーの戻り値
-                Ok(self.context.i32_type().const_int(0, false))
+                Ok(self.context.i32_type().const_int(0, false).into())
             }
 
             Statement::Block(statements) => {
+                // ブロック内で宣言した変数はブロックの終わりで見えなくなる
+                let saved_variables = self.variables.clone();
+                let saved_managed = self.managed_variables.clone();
+                self.push_scope();
+                self.begin_debug_block();
                 for stmt in statements {
                     self.generate_ir_for_statement(stmt, function)
                         .expect("Failed to generate ir for statement");
                 }
+                self.pop_scope();
+                self.end_debug_scope();
+                self.variables = saved_variables;
+                self.managed_variables = saved_managed;
                 // ブロック自体は値を返さないので0を返す
-                Ok(self.context.i32_type().const_int(0, false))
+                Ok(self.context.i32_type().const_int(0, false).into())
             }
-            Statement::Assignment(name, expr) => {
+            Statement::Assignment(name, expr, _) => Ok(self.generate_assignment(name, expr, function)),
+            Statement::DerefAssignment(pointer, expr, _) => {
+                // ポインタの参照先に値を書き込む (参照先が値の参照を1つ持つ)
+                let pointer = self.generate_ir_inner(pointer, function).into_pointer_value();
                 let value = self.generate_ir_inner(expr, function);
-                let variable = self.variables.get(name).expect("Variable not found");
-                self.builder.build_store(*variable, value).expect("Failed to build store");
+                let ownership = self.ownership(expr, value);
+                // 型検査で参照先と同じ型の値だけが書き込まれるので、管理された値なら参照先も管理されている
+                if ownership == Ownership::Unmanaged {
+                    self.builder.build_store(pointer, value).expect("Failed to build store");
+                } else {
+                    self.take_ownership(value, ownership);
+                    let old_value = self
+                        .builder
+                        .build_load(pointer, "rc.old")
+                        .expect("Failed to load pointee");
+                    self.builder.build_store(pointer, value).expect("Failed to build store");
+                    self.build_release(old_value);
+                }
                 Ok(value)
             }
-            _ => todo!("IR generation for other statement types"),
+            Statement::Extern(func) => {
+                self.declare_extern_function(func);
+                Ok(self.context.i32_type().const_int(0, false).into())
+            }
+            // `mod` と `import` はローダーが解決済み (ブロックの中のものはインタプリタや VM と同じく何もしない)
+            Statement::Mod(_) | Statement::Import(_) => {
+                Ok(self.context.i32_type().const_int(0, false).into())
+            }
         }
     }
-    fn build_return_instruction(&mut self, value: Option<&inkwell::values::IntValue<'a>>) {
+    fn build_return_instruction(&mut self, value: Option<&BasicValueEnum<'a>>) {
         let basic_value = value.map(|v| v as &dyn inkwell::values::BasicValue);
         self.builder.build_return(basic_value).expect("Failed to build return");
     }
 
+    // if 文と if 式の分岐 (どちらの枝もどんな文でも書ける)
+    fn generate_if(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Statement,
+        else_branch: Option<&Statement>,
+        function: &FunctionValue<'a>,
+    ) {
+        // 条件は int なので 0 でなければ then に進む
+        let condition_value = self.generate_ir_inner(condition, function).into_int_value();
+        let condition_value = self
+            .builder
+            .build_int_compare(
+                IntPredicate::NE,
+                condition_value,
+                condition_value.get_type().const_zero(),
+                "ifcond",
+            )
+            .expect("Failed to compare values");
+        let then_block = self.context.append_basic_block(*function, "then");
+        let else_block = self.context.append_basic_block(*function, "else");
+        let continue_block = self.context.append_basic_block(*function, "ifcont");
+
+        self.builder
+            .build_conditional_branch(condition_value, then_block, else_block)
+            .expect("Failed to build conditional branch");
+
+        self.builder.position_at_end(then_block);
+        self.generate_ir_for_statement(then_branch, function)
+            .expect("Failed to generate ir for statement");
+        self.builder.build_unconditional_branch(continue_block)
+            .expect("Failed to build unconditional branch");
+
+        self.builder.position_at_end(else_block);
+        if let Some(else_stmt) = else_branch {
+            self.generate_ir_for_statement(else_stmt, function)
+                .expect("Failed to generate ir for statement");
+        }
+        self.builder.build_unconditional_branch(continue_block)
+            .expect("Failed to build unconditional branch");
+
+        self.builder.position_at_end(continue_block);
+    }
+
     // 再帰的にASTを走査してIRを生成
     fn generate_ir_inner(
         &mut self,
         expr: &Expr,
         function: &FunctionValue<'a>,
-    ) -> inkwell::values::IntValue<'a> {
+    ) -> BasicValueEnum<'a> {
         match expr {
             // 整数リテラル
-            Expr::Integer(value) => self.context.i32_type().const_int(*value as u64, false),
+            Expr::Integer(value) => self.context.i32_type().const_int(*value as u64, false).into(),
+            // 文字列リテラル (NUL終端のグローバル定数へのポインタ)
+            Expr::Str(value) => self
+                .builder
+                .build_global_string_ptr(value, "str")
+                .expect("Failed to create string literal")
+                .as_pointer_value()
+                .into(),
             // 二項演算
-            Expr::BinaryOp(left, op, right) => {
+            Expr::BinaryOp(left, op, right, span) => {
                 let left_val = self.generate_ir_inner(left, function);
                 let right_val = self.generate_ir_inner(right, function);
-                self.build_binary_op(left_val, right_val, op)
+                self.set_debug_location(*span);
+                // 文字列同士の `+` は連結して新しいヒープ文字列を作る
+                if let (
+                    Operator::Plus,
+                    BasicValueEnum::PointerValue(left_ptr),
+                    BasicValueEnum::PointerValue(right_ptr),
+                ) = (op, left_val, right_val)
+                {
+                    let result = self.build_string_concat(left_ptr, right_ptr);
+                    let left_ownership = self.ownership(left, left_val);
+                    let right_ownership = self.ownership(right, right_val);
+                    self.release_if_owned(left_val, left_ownership);
+                    self.release_if_owned(right_val, right_ownership);
+                    return result;
+                }
+                let (left_val, right_val) = (left_val.into_int_value(), right_val.into_int_value());
+                self.build_binary_op(left_val, right_val, op, *span).into()
             }
             // 変数の参照
-            Expr::Variable(name) => {
+            Expr::Variable(name, _) => {
                 // 変数のアドレスを取得
                 let variable_address = match self.variables.get(name) {
                     Some(address) => *address,
                     None => panic!("Variable not found"),
                 };
 
-                // 変数の値をロードしてIntValueに変換
+                // 変数の値をロード
                 match self.builder.build_load(variable_address, name) {
-                    Ok(value) => value.into_int_value(),
+                    Ok(value) => value,
                     Err(_) => panic!("Failed to load variable value"),
                 }
             }
             // 変数への代入
-            Expr::Assign(name, value) => {
-                // 代入する値を計算
-                let value_to_assign = self.generate_ir_inner(value, function);
-                // 変数のアドレスを取得
+            Expr::Assign(name, value) => self.generate_assignment(name, value, function),
+            // if式 (値はインタプリタや VM と同じく 0)
+            Expr::If(condition, then_branch, else_branch) => {
+                self.generate_if(condition, then_branch, else_branch.as_deref(), function);
+                self.context.i32_type().const_int(0, false).into()
+            }
+            // 変数のアドレス (allocaへのポインタ)
+            Expr::AddressOf(name) => {
                 let variable_address = self.variables.get(name).expect("Variable not found");
-                // 値を変数にストア
-                self.builder.build_store(*variable_address, value_to_assign).expect("Failed to build store");
-                value_to_assign
+                (*variable_address).into()
             }
-            // if文のIR生成
-            Expr::If(condition, then_branch, else_branch) => {
-                // 条件、thenブロック、elseブロックの生成
-                let condition_value = self.generate_ir_inner(condition, function);
-                let then_block = self.context.append_basic_block(*function, "then");
-                let else_block = self.context.append_basic_block(*function, "else");
-                let continue_block = self.context.append_basic_block(*function, "ifcont");
-                // 条件に基づいて分岐
-                self.builder
-                    .build_conditional_branch(condition_value, then_block, else_block)
-                    .expect("Failed to build conditional branch");
-
-                // thenブロックの生成
-                self.builder.position_at_end(then_block);
-                if let Statement::Block(then_statements) = &**then_branch {
-                    for stmt in then_statements {
-                        match stmt {
-                            Statement::Expression(expr) => {
-                                self.generate_ir_inner(expr, function);
+            // ポインタの参照先をロード
+            Expr::Deref(pointer_expr, _) => {
+                let pointer = self.generate_ir_inner(pointer_expr, function);
+                let value = self
+                    .builder
+                    .build_load(pointer.into_pointer_value(), "deref")
+                    .expect("Failed to load through pointer");
+                let ownership = self.ownership(pointer_expr, pointer);
+                self.release_if_owned(pointer, ownership);
+                value
+            }
+            // 参照カウント付きで値の型の大きさだけ確保し、値を格納する
+            Expr::New(value_expr) => {
+                let value = self.generate_ir_inner(value_expr, function);
+                // 格納した値の参照は確保した領域が持つ
+                let ownership = self.ownership(value_expr, value);
+                self.take_ownership(value, ownership);
+                self.build_managed_new(value).into()
+            }
+            // 関数呼び出しのIR生成
+            Expr::FunctionCall(name, args, span) => {
+                // 同名の関数がなければ組み込み関数として扱う
+                if self.module.get_function(symbol_name(name)).is_none() {
+                    if let Some(builtin) = builtin::lookup(name) {
+                        let values: Vec<BasicValueEnum<'a>> = args
+                            .iter()
+                            .map(|arg| self.generate_ir_inner(arg, function))
+                            .collect();
+                        self.set_debug_location(*span);
+                        let unit = self.context.i32_type().const_int(0, false).into();
+                        return match (&builtin, values.as_slice()) {
+                            (Builtin::Assert, [condition]) => {
+                                self.build_assert(condition.into_int_value(), *span);
+                                unit
                             }
-                            // 他の文タイプに対するIR生成は未実装
-                            _ => todo!("IR generation for other statement types"),
-                        }
-                    }
-                }
-                self.builder.build_unconditional_branch(continue_block)
-                    .expect("Failed to build unconditional branch");
-
-                // elseブロックの生成
-                self.builder.position_at_end(else_block);
-                if let Some(else_stmt) = else_branch {
-                    if let Statement::Block(else_statements) = &**else_stmt {
-                        for stmt in else_statements {
-                            match stmt {
-                                Statement::Expression(expr) => {
-                                    self.generate_ir_inner(expr, function);
-                                }
-                                // 他の文タイプに対するIR生成は未実装
-                                _ => todo!("IR generation for other statement types"),
+                            (Builtin::Panic, [message]) => {
+                                self.build_panic(message.into_pointer_value(), *span);
+                                unit
+                            }
+                            (Builtin::Wrapping(_) | Builtin::Saturating(_), [left, right]) => {
+                                let (left, right) = (left.into_int_value(), right.into_int_value());
+                                self.build_builtin(&builtin, left, right).into()
                             }
-                        }
+                            // 引数の数と型は types::check で検査済み
+                            _ => unreachable!("Invalid arguments for `{}`", name),
+                        };
                     }
                 }
-                self.builder.build_unconditional_branch(continue_block)
-                    .expect("Failed to build unconditional branch");
-
-                // continueブロックに移動
-                self.builder.position_at_end(continue_block);
-
-                // 一時的に0を返す
-                self.context.i32_type().const_int(0, false)
-            }
-            // 関数呼び出しのIR生成
-            Expr::FunctionCall(name, args) => {
                 // 関数の検索
-                let function = self.module.get_function(name).expect("Function not found");
+                let callee = self
+                    .module
+                    .get_function(symbol_name(name))
+                    .expect("Function not found");
 
                 // 引数のIRを生成
                 let mut arg_values = Vec::new();
+                let mut temporaries = Vec::new();
                 for arg in args {
-                    let arg_value = self.generate_ir_inner(arg, &function);
+                    let arg_value = self.generate_ir_inner(arg, function);
                     arg_values.push(arg_value.into());
+                    temporaries.push((arg_value, self.ownership(arg, arg_value)));
                 }
 
-                // 関数呼び出し
-                match self.builder.build_call(function, &arg_values, "calltmp") {
-                    Ok(call) => call.try_as_basic_value().left().unwrap().into_int_value(),
+                // 関数呼び出し (再帰の深さを数え、深すぎればスタックオーバーフローで終了する)
+                self.set_debug_location(*span);
+                self.build_enter_call(*span);
+                // void関数の呼び出しは値を持たないので0を返す
+                let result = match self.builder.build_call(callee, &arg_values, "calltmp") {
+                    Ok(call) => call
+                        .try_as_basic_value()
+                        .left()
+                        .unwrap_or_else(|| self.context.i32_type().const_int(0, false).into()),
                     Err(_) => panic!("Failed to call function"),
+                };
+                self.build_leave_call();
+
+                // 引数として渡した一時的なヒープ値は呼び出し後に解放する
+                for (value, ownership) in temporaries {
+                    self.release_if_owned(value, ownership);
                 }
+                result
             }
         }
     }
@@ -299,8 +564,12 @@ impl<'a> IRGenerator<'a> {
         left_val: inkwell::values::IntValue<'a>,
         right_val: inkwell::values::IntValue<'a>,
         op: &Operator,
+        span: Span,
     ) -> inkwell::values::IntValue<'a> {
         match op {
+            Operator::Plus | Operator::Minus | Operator::Multiply if self.overflow_checks => {
+                self.build_checked_op(left_val, right_val, op, span)
+            }
             Operator::Plus => self
                 .builder
                 .build_int_add(left_val, right_val, "addtmp")
@@ -310,41 +579,68 @@ impl<'a> IRGenerator<'a> {
                 .build_int_sub(left_val, right_val, "subtmp")
                 .expect("Failed to subtract values"),
             // 等値比較のIR生成
-            Operator::Equals => self
-                .builder
-                .build_int_compare(IntPredicate::EQ, left_val, right_val, "eqtmp")
-                .expect("Failed to compare values for equality"),
+            Operator::Equals => self.build_comparison(IntPredicate::EQ, left_val, right_val),
             Operator::Multiply => {
                 // 乗算のIRコード生成
                 self.builder
                     .build_int_mul(left_val, right_val, "multmp")
                     .expect("Failed to multiply values")
             }
-            Operator::MoreThan => self
-                .builder
-                .build_int_compare(IntPredicate::SGT, left_val, right_val, "gttmp")
-                .expect("Failed to compare values"),
-            Operator::LessThan => self
-                .builder
-                .build_int_compare(IntPredicate::SLT, left_val, right_val, "lttmp")
-                .expect("Failed to compare values"),
+            Operator::Divide => {
+                self.build_division_check(right_val, span);
+                if self.overflow_checks {
+                    self.build_division_overflow_check(left_val, right_val, span);
+                }
+                self.builder
+                    .build_int_signed_div(left_val, right_val, "divtmp")
+                    .expect("Failed to divide values")
+            }
+            Operator::MoreThan => self.build_comparison(IntPredicate::SGT, left_val, right_val),
+            Operator::LessThan => self.build_comparison(IntPredicate::SLT, left_val, right_val),
         }
     }
+    // 比較の結果は型検査と同じく int (1 か 0) にする (i1 のままでは int の変数や引数に渡せない)
+    fn build_comparison(
+        &self,
+        predicate: IntPredicate,
+        left_val: inkwell::values::IntValue<'a>,
+        right_val: inkwell::values::IntValue<'a>,
+    ) -> inkwell::values::IntValue<'a> {
+        let result = self
+            .builder
+            .build_int_compare(predicate, left_val, right_val, "cmptmp")
+            .expect("Failed to compare values");
+        self.builder
+            .build_int_z_extend(result, self.context.i32_type(), "booltmp")
+            .expect("Failed to extend comparison")
+    }
     // 変数宣言のIR生成
     fn generate_declaration_ir(
         &mut self,
         name: &str,
         expr: &Expr,
+        span: Span,
         function: &FunctionValue<'a>,
     ) -> Result<(), String> {
-        // 変数のアロケーションを作成
-        let alloca = self.create_entry_block_alloca(function, name)?;
+        // IR値を生成
+        let ir_value = self.generate_ir_inner(expr, function);
+
+        // 値の型に合わせて変数のアロケーションを作成
+        let alloca = self.create_entry_block_alloca(function, name, ir_value.get_type())?;
+
+        self.declare_debug_variable(name, alloca, span, None);
 
         // 変数をハッシュマップに登録
         self.variables.insert(name.to_string(), alloca);
 
-        // IR値を生成
-        let ir_value = self.generate_ir_inner(expr, function);
+        // 参照カウントを持つ値は変数が参照を1つ持つ
+        let ownership = self.ownership(expr, ir_value);
+        if ownership == Ownership::Unmanaged {
+            self.managed_variables.remove(name);
+        } else {
+            self.take_ownership(ir_value, ownership);
+            self.register_managed_variable(name, alloca);
+        }
 
         // IR値を変数にストア
         self.builder.build_store(alloca, ir_value).expect("Failed to build store");
@@ -352,11 +648,37 @@ impl<'a> IRGenerator<'a> {
         Ok(())
     }
 
+    // 変数への代入 (型検査で変数と同じ型の値だけが代入されるので、管理された変数には管理された値が入る)
+    fn generate_assignment(
+        &mut self,
+        name: &str,
+        expr: &Expr,
+        function: &FunctionValue<'a>,
+    ) -> BasicValueEnum<'a> {
+        let value = self.generate_ir_inner(expr, function);
+        let ownership = self.ownership(expr, value);
+        let variable = *self.variables.get(name).expect("Variable not found");
+        if self.managed_variables.contains(name) {
+            // 新しい値の参照を確保してから古い値を解放する
+            self.take_ownership(value, ownership);
+            let old_value = self
+                .builder
+                .build_load(variable, "rc.old")
+                .expect("Failed to load variable value");
+            self.builder.build_store(variable, value).expect("Failed to build store");
+            self.build_release(old_value);
+        } else {
+            self.builder.build_store(variable, value).expect("Failed to build store");
+        }
+        value
+    }
+
     // ブロックの先頭に変数を割り当てるための関数
     fn create_entry_block_alloca(
         &mut self,
         function: &FunctionValue,
         name: &str,
+        ty: BasicTypeEnum<'a>,
     ) -> Result<PointerValue<'a>, String> {
         let builder = self.context.create_builder();
 
@@ -370,7 +692,7 @@ impl<'a> IRGenerator<'a> {
         }
 
         builder
-            .build_alloca(self.context.i32_type(), name)
+            .build_alloca(ty, name)
             .map_err(|e| e.to_string())
     }
 }
@@ -0,0 +1,135 @@
+use super::IRGenerator;
+use crate::ast::{Operator, Span};
+use crate::builtin::Builtin;
+use crate::panic::PanicKind;
+use inkwell::intrinsics::Intrinsic;
+use inkwell::values::{BasicMetadataValueEnum, BasicValueEnum, IntValue};
+use inkwell::IntPredicate;
+
+impl<'a> IRGenerator<'a> {
+    // `llvm.sadd.with.overflow` など int 型でオーバーロードされた組み込み関数を呼び出す
+    fn call_intrinsic(
+        &self,
+        name: &str,
+        args: &[BasicMetadataValueEnum<'a>],
+        value_name: &str,
+    ) -> BasicValueEnum<'a> {
+        let intrinsic = Intrinsic::find(name)
+            .and_then(|intrinsic| {
+                intrinsic.get_declaration(&self.module, &[self.context.i32_type().into()])
+            })
+            .unwrap_or_else(|| panic!("Intrinsic `{}` not found", name));
+        self.builder
+            .build_call(intrinsic, args, value_name)
+            .expect("Failed to build call")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+    }
+
+    // オーバーフローを検査する `+` `-` `*`
+    pub(super) fn build_checked_op(
+        &mut self,
+        left: IntValue<'a>,
+        right: IntValue<'a>,
+        op: &Operator,
+        span: Span,
+    ) -> IntValue<'a> {
+        let name = match op {
+            Operator::Plus => "llvm.sadd.with.overflow",
+            Operator::Minus => "llvm.ssub.with.overflow",
+            _ => "llvm.smul.with.overflow",
+        };
+        // 結果は { 値, 溢れたかどうか } の構造体
+        let result = self
+            .call_intrinsic(name, &[left.into(), right.into()], "checked")
+            .into_struct_value();
+        let value = self
+            .builder
+            .build_extract_value(result, 0, "checked.value")
+            .expect("Failed to extract value")
+            .into_int_value();
+        let overflow = self
+            .builder
+            .build_extract_value(result, 1, "checked.overflow")
+            .expect("Failed to extract value")
+            .into_int_value();
+        let message = format!("Arithmetic overflow in `{}`", op);
+        self.build_check(overflow, PanicKind::Overflow, &message, span);
+        value
+    }
+
+    // 割り算は i32::MIN / -1 だけが溢れる
+    pub(super) fn build_division_overflow_check(
+        &mut self,
+        left: IntValue<'a>,
+        right: IntValue<'a>,
+        span: Span,
+    ) {
+        let int_type = self.context.i32_type();
+        let is_min = self
+            .builder
+            .build_int_compare(
+                IntPredicate::EQ,
+                left,
+                int_type.const_int(i32::MIN as u64, true),
+                "div.min",
+            )
+            .expect("Failed to compare values");
+        let is_minus_one = self
+            .builder
+            .build_int_compare(
+                IntPredicate::EQ,
+                right,
+                int_type.const_all_ones(),
+                "div.neg",
+            )
+            .expect("Failed to compare values");
+        let overflow = self
+            .builder
+            .build_and(is_min, is_minus_one, "div.overflow")
+            .expect("Failed to build and");
+        let message = format!("Arithmetic overflow in `{}`", Operator::Divide);
+        self.build_check(overflow, PanicKind::Overflow, &message, span);
+    }
+
+    // 組み込み関数 `wrapping_*` と `saturating_*`
+    pub(super) fn build_builtin(
+        &mut self,
+        builtin: &Builtin,
+        left: IntValue<'a>,
+        right: IntValue<'a>,
+    ) -> IntValue<'a> {
+        match builtin {
+            Builtin::Wrapping(Operator::Plus) => self
+                .builder
+                .build_int_add(left, right, "wrapping")
+                .expect("Failed to add values"),
+            Builtin::Wrapping(Operator::Minus) => self
+                .builder
+                .build_int_sub(left, right, "wrapping")
+                .expect("Failed to subtract values"),
+            Builtin::Wrapping(_) => self
+                .builder
+                .build_int_mul(left, right, "wrapping")
+                .expect("Failed to multiply values"),
+            Builtin::Saturating(Operator::Plus) => self
+                .call_intrinsic("llvm.sadd.sat", &[left.into(), right.into()], "saturating")
+                .into_int_value(),
+            Builtin::Saturating(Operator::Minus) => self
+                .call_intrinsic("llvm.ssub.sat", &[left.into(), right.into()], "saturating")
+                .into_int_value(),
+            // 小数部が 0 bit の固定小数点数の乗算は整数の飽和乗算になる
+            Builtin::Saturating(_) => {
+                let scale = self.context.i32_type().const_zero();
+                self.call_intrinsic(
+                    "llvm.smul.fix.sat",
+                    &[left.into(), right.into(), scale.into()],
+                    "saturating",
+                )
+                .into_int_value()
+            }
+            Builtin::Assert | Builtin::Panic => unreachable!("not an arithmetic builtin"),
+        }
+    }
+}
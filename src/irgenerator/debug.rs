@@ -0,0 +1,315 @@
+use super::IRGenerator;
+use crate::ast::{Span, Type};
+use inkwell::debug_info::{
+    AsDIScope, DICompileUnit, DIFile, DIFlags, DIFlagsConstants, DIScope, DIType,
+    DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder,
+};
+use inkwell::module::FlagBehavior;
+use inkwell::types::BasicTypeEnum;
+use inkwell::values::{FunctionValue, GlobalValue, PointerValue};
+use inkwell::AddressSpace;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// DWARF の基本型のエンコーディング
+const DW_ATE_BOOLEAN: u32 = 0x02;
+const DW_ATE_SIGNED: u32 = 0x05;
+const DW_ATE_SIGNED_CHAR: u32 = 0x06;
+
+// `-g` で出力するデバッグ情報
+pub(super) struct DebugInfo<'a> {
+    builder: DebugInfoBuilder<'a>,
+    compile_unit: DICompileUnit<'a>,
+    directory: PathBuf, // エントリファイルのディレクトリ (子モジュールも同じ場所にある)
+    optimized: bool,
+    pointer_bits: u64,
+    // 関数とブロックのスコープ (最後が一番内側、Span はスコープに入る前の位置)
+    scopes: Vec<(DIScope<'a>, DIFile<'a>, Span)>,
+    span: Span, // 最後に設定した位置
+}
+
+impl<'a> DebugInfo<'a> {
+    // 位置のあるファイル (0 はコンパイル単位のエントリファイル)
+    fn file(&self, span: Span, files: &[PathBuf]) -> DIFile<'a> {
+        match files.get(span.file) {
+            Some(path) if span.file != 0 => {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                self.builder
+                    .create_file(&name, &self.directory.to_string_lossy())
+            }
+            _ => self.compile_unit.get_file(),
+        }
+    }
+
+    fn basic_type(&self, name: &str, bits: u64, encoding: u32) -> DIType<'a> {
+        self.builder
+            .create_basic_type(name, bits, encoding, DIFlags::PUBLIC)
+            .expect("Failed to create debug type")
+            .as_type()
+    }
+
+    // LLVM の型に対応するデバッグ用の型 (gdb では C の型として表示される)
+    fn debug_type(&self, ty: BasicTypeEnum<'a>) -> DIType<'a> {
+        match ty {
+            BasicTypeEnum::IntType(int) if int.get_bit_width() == 1 => {
+                self.basic_type("bool", 8, DW_ATE_BOOLEAN)
+            }
+            BasicTypeEnum::IntType(int) if int.get_bit_width() == 8 => {
+                self.basic_type("char", 8, DW_ATE_SIGNED_CHAR)
+            }
+            BasicTypeEnum::IntType(int) => {
+                self.basic_type("int", int.get_bit_width() as u64, DW_ATE_SIGNED)
+            }
+            BasicTypeEnum::PointerType(pointer) => {
+                let pointee = match BasicTypeEnum::try_from(pointer.get_element_type()) {
+                    Ok(pointee) => self.debug_type(pointee),
+                    Err(_) => self.basic_type("char", 8, DW_ATE_SIGNED_CHAR),
+                };
+                self.builder
+                    .create_pointer_type(
+                        "",
+                        pointee,
+                        self.pointer_bits,
+                        self.pointer_bits as u32,
+                        AddressSpace::default(),
+                    )
+                    .as_type()
+            }
+            _ => panic!("No debug type for {:?}", ty),
+        }
+    }
+}
+
+impl<'a> IRGenerator<'a> {
+    // デバッグ情報の出力を有効にする (コンパイル単位はエントリファイル)
+    pub fn enable_debug_info(&mut self, path: &Path, optimized: bool) {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let directory = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let filename = path.file_name().unwrap_or_default().to_string_lossy();
+        let (builder, compile_unit) = self.module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C,
+            &filename,
+            &directory.to_string_lossy(),
+            "omochi",
+            optimized,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+        let version = self.context.i32_type().const_int(3, false);
+        self.module
+            .add_basic_value_flag("Debug Info Version", FlagBehavior::Warning, version);
+        let pointer_bits = match &self.target_data {
+            Some(target_data) => target_data.get_pointer_byte_size(None) as u64 * 8,
+            None => 64,
+        };
+        self.debug_info = Some(DebugInfo {
+            builder,
+            compile_unit,
+            directory,
+            optimized,
+            pointer_bits,
+            scopes: Vec::new(),
+            span: Span::new(1, 1),
+        });
+    }
+
+    // 未解決のデバッグ情報を確定する (検証や最適化の前に呼ぶ)
+    pub(super) fn finalize_debug_info(&self) {
+        if let Some(debug) = &self.debug_info {
+            debug.builder.finalize();
+        }
+    }
+
+    // 以降に生成する命令にソース上の位置を付ける
+    pub(super) fn set_debug_location(&mut self, span: Span) {
+        let Some(debug) = &mut self.debug_info else {
+            return;
+        };
+        let Some(&(scope, _, _)) = debug.scopes.last() else {
+            return;
+        };
+        debug.span = span;
+        let location = debug.builder.create_debug_location(
+            self.context,
+            span.line as u32,
+            span.column as u32,
+            scope,
+            None,
+        );
+        self.builder.set_current_debug_location(location);
+    }
+
+    // 以降に生成する命令の位置を消す (位置を持たない関数に命令を生成するとき)
+    pub(super) fn clear_debug_location(&self) {
+        if self.debug_info.is_some() {
+            self.builder.unset_current_debug_location();
+        }
+    }
+
+    // 関数にサブプログラムを付けて、そのスコープに入る
+    pub(super) fn begin_debug_function(
+        &mut self,
+        function: FunctionValue<'a>,
+        name: &str,
+        span: Span,
+        params: &[(String, Type)],
+        return_type: &Type,
+    ) {
+        let param_types: Vec<BasicTypeEnum<'a>> =
+            params.iter().map(|(_, ty)| self.basic_type(ty)).collect();
+        let return_type = (*return_type != Type::Void).then(|| self.basic_type(return_type));
+        let Some(debug) = &mut self.debug_info else {
+            return;
+        };
+        let file = debug.file(span, &self.source_files);
+        let param_types: Vec<DIType<'a>> = param_types
+            .into_iter()
+            .map(|ty| debug.debug_type(ty))
+            .collect();
+        let subroutine = debug.builder.create_subroutine_type(
+            file,
+            return_type.map(|ty| debug.debug_type(ty)),
+            &param_types,
+            DIFlags::PUBLIC,
+        );
+        let subprogram = debug.builder.create_function(
+            debug.compile_unit.as_debug_info_scope(),
+            name,
+            None,
+            file,
+            span.line as u32,
+            subroutine,
+            false,
+            true,
+            span.line as u32,
+            DIFlags::PUBLIC,
+            debug.optimized,
+        );
+        function.set_subprogram(subprogram);
+        debug
+            .scopes
+            .push((subprogram.as_debug_info_scope(), file, debug.span));
+        self.set_debug_location(span);
+    }
+
+    // ブロックのスコープに入る (ブロック内の変数は gdb でもブロックの外から見えない)
+    pub(super) fn begin_debug_block(&mut self) {
+        let Some(debug) = &mut self.debug_info else {
+            return;
+        };
+        let Some(&(parent, file, _)) = debug.scopes.last() else {
+            return;
+        };
+        let block = debug.builder.create_lexical_block(
+            parent,
+            file,
+            debug.span.line as u32,
+            debug.span.column as u32,
+        );
+        debug
+            .scopes
+            .push((block.as_debug_info_scope(), file, debug.span));
+    }
+
+    // 関数やブロックのスコープを出て、入る前の位置に戻す
+    pub(super) fn end_debug_scope(&mut self) {
+        let Some(debug) = &mut self.debug_info else {
+            return;
+        };
+        let Some((_, _, span)) = debug.scopes.pop() else {
+            return;
+        };
+        self.set_debug_location(span);
+    }
+
+    // 変数の alloca に gdb で表示するための変数情報を付ける (arg_no は1始まりの引数の番号)
+    pub(super) fn declare_debug_variable(
+        &mut self,
+        name: &str,
+        alloca: PointerValue<'a>,
+        span: Span,
+        arg_no: Option<u32>,
+    ) {
+        let Some(debug) = &self.debug_info else {
+            return;
+        };
+        let Some(&(scope, file, _)) = debug.scopes.last() else {
+            return;
+        };
+        let Some(block) = self.builder.get_insert_block() else {
+            return;
+        };
+        let ty = match BasicTypeEnum::try_from(alloca.get_type().get_element_type()) {
+            Ok(ty) => debug.debug_type(ty),
+            Err(_) => panic!("Variable `{}` has no value type", name),
+        };
+        let line = span.line as u32;
+        let variable = match arg_no {
+            Some(arg_no) => debug.builder.create_parameter_variable(
+                scope,
+                name,
+                arg_no,
+                file,
+                line,
+                ty,
+                true,
+                DIFlags::ZERO,
+            ),
+            None => debug.builder.create_auto_variable(
+                scope,
+                name,
+                file,
+                line,
+                ty,
+                true,
+                DIFlags::ZERO,
+                0,
+            ),
+        };
+        let location = debug.builder.create_debug_location(
+            self.context,
+            line,
+            span.column as u32,
+            scope,
+            None,
+        );
+        debug
+            .builder
+            .insert_declare_at_end(alloca, Some(variable), None, location, block);
+    }
+
+    // トップレベルの変数のグローバル変数に gdb で表示するための変数情報を付ける
+    pub(super) fn declare_debug_global(&self, name: &str, global: GlobalValue<'a>, span: Span) {
+        let Some(debug) = &self.debug_info else {
+            return;
+        };
+        let ty = match BasicTypeEnum::try_from(
+            global.as_pointer_value().get_type().get_element_type(),
+        ) {
+            Ok(ty) => debug.debug_type(ty),
+            Err(_) => panic!("Variable `{}` has no value type", name),
+        };
+        let expression = debug.builder.create_global_variable_expression(
+            debug.compile_unit.as_debug_info_scope(),
+            name,
+            "",
+            debug.compile_unit.get_file(),
+            span.line as u32,
+            ty,
+            true,
+            None,
+            None,
+            0,
+        );
+        let kind = self.context.get_kind_id("dbg");
+        global.set_metadata(expression.as_metadata_value(self.context), kind);
+    }
+}
@@ -0,0 +1,178 @@
+use super::IRGenerator;
+use crate::ast::Span;
+use crate::panic::{PanicKind, MAX_FRAMES};
+use inkwell::module::Linkage;
+use inkwell::values::{FunctionValue, IntValue, PointerValue};
+use inkwell::IntPredicate;
+
+// 関数呼び出しの深さを数えるグローバル変数
+const CALL_DEPTH: &str = "omochi_call_depth";
+
+impl<'a> IRGenerator<'a> {
+    // `panic(message)` (以降の命令は到達しないブロックに追加される)
+    pub(super) fn build_panic(&mut self, message: PointerValue<'a>, span: Span) {
+        self.build_panic_call(PanicKind::Explicit, message, span);
+        let after = self
+            .context
+            .append_basic_block(self.current_function(), "panic.after");
+        self.builder.position_at_end(after);
+    }
+
+    // 実行時エラーを `file:line:col: message` の形で報告して終了する
+    fn build_panic_call(&mut self, kind: PanicKind, message: PointerValue<'a>, span: Span) {
+        let file = self.source_file(span);
+        let file = self
+            .builder
+            .build_global_string_ptr(&file, "panic.file")
+            .expect("Failed to create string literal")
+            .as_pointer_value();
+        let int_type = self.context.i32_type();
+        let panic = self.module.get_function("omochi_panic").unwrap();
+        self.builder
+            .build_call(
+                panic,
+                &[
+                    file.into(),
+                    int_type.const_int(span.line as u64, false).into(),
+                    int_type.const_int(span.column as u64, false).into(),
+                    message.into(),
+                    int_type.const_int(kind.exit_code() as u64, false).into(),
+                ],
+                "",
+            )
+            .expect("Failed to build call");
+        self.builder
+            .build_unreachable()
+            .expect("Failed to build unreachable");
+    }
+
+    // failed が真ならメッセージを表示して終了する
+    pub(super) fn build_check(
+        &mut self,
+        failed: IntValue<'a>,
+        kind: PanicKind,
+        message: &str,
+        span: Span,
+    ) {
+        let function = self.current_function();
+        let panic_block = self.context.append_basic_block(function, "check.failed");
+        let continue_block = self.context.append_basic_block(function, "check.ok");
+        self.builder
+            .build_conditional_branch(failed, panic_block, continue_block)
+            .expect("Failed to build conditional branch");
+
+        self.builder.position_at_end(panic_block);
+        let message = self
+            .builder
+            .build_global_string_ptr(message, "panic.message")
+            .expect("Failed to create string literal")
+            .as_pointer_value();
+        self.build_panic_call(kind, message, span);
+
+        self.builder.position_at_end(continue_block);
+    }
+
+    // `assert(condition)`
+    pub(super) fn build_assert(&mut self, condition: IntValue<'a>, span: Span) {
+        let failed = self
+            .builder
+            .build_int_compare(
+                IntPredicate::EQ,
+                condition,
+                condition.get_type().const_zero(),
+                "assert.failed",
+            )
+            .expect("Failed to compare values");
+        self.build_check(failed, PanicKind::Assertion, "Assertion failed", span);
+    }
+
+    // 0 による割り算の検査
+    pub(super) fn build_division_check(&mut self, divisor: IntValue<'a>, span: Span) {
+        let is_zero = self
+            .builder
+            .build_int_compare(
+                IntPredicate::EQ,
+                divisor,
+                divisor.get_type().const_zero(),
+                "div.zero",
+            )
+            .expect("Failed to compare values");
+        self.build_check(is_zero, PanicKind::DivisionByZero, "Division by zero", span);
+    }
+
+    // 関数を呼び出す前に深さを1つ増やす (MAX_FRAMES に達していればスタックオーバーフローで終了する)
+    pub(super) fn build_enter_call(&mut self, span: Span) {
+        let int_type = self.context.i32_type();
+        let depth = self.call_depth();
+        let current = self
+            .builder
+            .build_load(depth, "depth")
+            .expect("Failed to load call depth")
+            .into_int_value();
+        let overflow = self
+            .builder
+            .build_int_compare(
+                IntPredicate::UGE,
+                current,
+                int_type.const_int(MAX_FRAMES as u64, false),
+                "depth.overflow",
+            )
+            .expect("Failed to compare values");
+        self.build_check(overflow, PanicKind::StackOverflow, "Stack overflow", span);
+        let next = self
+            .builder
+            .build_int_add(current, int_type.const_int(1, false), "depth.next")
+            .expect("Failed to add values");
+        self.builder
+            .build_store(depth, next)
+            .expect("Failed to build store");
+    }
+
+    // 関数から戻った後に深さを1つ減らす
+    pub(super) fn build_leave_call(&mut self) {
+        let depth = self.call_depth();
+        let current = self
+            .builder
+            .build_load(depth, "depth")
+            .expect("Failed to load call depth")
+            .into_int_value();
+        let previous = self
+            .builder
+            .build_int_sub(
+                current,
+                self.context.i32_type().const_int(1, false),
+                "depth.prev",
+            )
+            .expect("Failed to subtract values");
+        self.builder
+            .build_store(depth, previous)
+            .expect("Failed to build store");
+    }
+
+    // 深さはインタプリタや VM のフレーム数と同じくトップレベルの分の1から数える
+    fn call_depth(&self) -> PointerValue<'a> {
+        if let Some(global) = self.module.get_global(CALL_DEPTH) {
+            return global.as_pointer_value();
+        }
+        let int_type = self.context.i32_type();
+        let global = self.module.add_global(int_type, None, CALL_DEPTH);
+        global.set_linkage(Linkage::Internal);
+        global.set_initializer(&int_type.const_int(1, false));
+        global.as_pointer_value()
+    }
+
+    fn current_function(&self) -> FunctionValue<'a> {
+        self.builder
+            .get_insert_block()
+            .and_then(|block| block.get_parent())
+            .expect("No current function")
+    }
+
+    // 実行時のメッセージに表示するソースファイル (位置のファイル番号から選ぶ)
+    fn source_file(&self, span: Span) -> String {
+        self.source_files
+            .get(span.file)
+            .map(|path| path.display().to_string())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,209 @@
+use super::refcount::{is_managed_type, Ownership};
+use super::IRGenerator;
+use crate::ast::{Expr, Function, Span, Statement, Type};
+use crate::entry;
+use crate::types::TypeEnvironment;
+use inkwell::module::Linkage;
+use inkwell::types::BasicTypeEnum;
+use inkwell::values::{BasicValueEnum, FunctionValue, GlobalValue};
+
+// トップレベルの文をまとめた初期化関数 (`omochi_` はランタイムの名前)
+const INIT_FUNCTION: &str = "omochi_init";
+// ユーザー定義の `fn main` のシンボル名 (C の main は初期化関数とこれを呼び出す)
+const USER_MAIN: &str = "omochi_main";
+
+// 関数のシンボル名 (ユーザー定義の main だけ C の main と重ならない名前にする)
+pub(super) fn symbol_name(name: &str) -> &str {
+    if name == entry::MAIN {
+        USER_MAIN
+    } else {
+        name
+    }
+}
+
+impl<'a> IRGenerator<'a> {
+    // プログラム全体の IR を生成する
+    // トップレベルの変数はグローバル変数、関数はモジュールの関数になり、
+    // それ以外のトップレベルの文は初期化関数にまとめて main から呼び出す
+    // `fn main` があれば初期化関数の後に呼び出し、int を返すならその戻り値を終了コードにする
+    pub fn generate_program(&mut self, statements: &[Statement]) {
+        self.declare_functions(statements);
+        self.declare_globals(statements);
+
+        let init_type = self.context.void_type().fn_type(&[], false);
+        let init = self
+            .module
+            .add_function(INIT_FUNCTION, init_type, Some(Linkage::Internal));
+        self.initialize_entry_block(&init);
+        for statement in statements {
+            self.generate_ir_for_statement(statement, &init)
+                .expect("Failed to generate IR");
+        }
+        self.builder
+            .build_return(None)
+            .expect("Failed to build return");
+        self.end_debug_scope();
+
+        // main は初期化関数を呼び出し、トップレベルの変数を解放して終了コードを返す
+        let main_type = self.context.i32_type().fn_type(&[], false);
+        let main = self.module.add_function("main", main_type, None);
+        let entry = self.context.append_basic_block(main, "entry");
+        self.builder.position_at_end(entry);
+        self.clear_debug_location();
+        self.builder
+            .build_call(init, &[], "")
+            .expect("Failed to build call");
+        let mut exit_code = self.context.i32_type().const_int(0, false);
+        if let Some(func) = entry::find_main(statements) {
+            let user_main = self
+                .module
+                .get_function(USER_MAIN)
+                .expect("`main` was not declared");
+            let value = self
+                .builder
+                .build_call(user_main, &[], "main.ret")
+                .expect("Failed to build call")
+                .try_as_basic_value()
+                .left();
+            if let (Some(BasicValueEnum::IntValue(code)), Type::Int) = (value, &func.return_type) {
+                exit_code = code;
+            }
+        }
+        self.finish_main(exit_code);
+    }
+
+    // 関数は定義より前からも呼べるので、全ての関数を本体なしで先に宣言する
+    fn declare_functions(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Function(func) => {
+                    self.declare_function(func);
+                    self.declare_functions(&func.body);
+                }
+                Statement::Extern(func) => {
+                    self.declare_extern_function(func);
+                }
+                Statement::Block(body) => self.declare_functions(body),
+                Statement::If(_, then_branch, else_branch, _) => {
+                    self.declare_functions(std::slice::from_ref(then_branch));
+                    if let Some(else_branch) = else_branch {
+                        self.declare_functions(std::slice::from_ref(else_branch));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // 本体がまだない宣言があればそれを返す (REPL で定義し直した関数は新しく追加する)
+    pub(super) fn declare_function(&mut self, func: &Function) -> FunctionValue<'a> {
+        if is_managed_type(&func.return_type) {
+            self.managed_functions.insert(func.name.clone());
+        } else {
+            self.managed_functions.remove(&func.name);
+        }
+        let symbol = symbol_name(&func.name);
+        if let Some(function) = self.module.get_function(symbol) {
+            if function.count_basic_blocks() == 0 {
+                return function;
+            }
+        }
+        let fn_type = self.function_type(&func.return_type, &func.params, false);
+        self.module.add_function(symbol, fn_type, None)
+    }
+
+    // トップレベルの変数をグローバル変数として先に宣言し、定義より前の関数からも参照できるようにする
+    // 型は初期化式から推論する (推論できない変数は初期化関数で値を計算するときに作る)
+    fn declare_globals(&mut self, statements: &[Statement]) {
+        let mut types = TypeEnvironment::new();
+        types.declare_functions(statements);
+        for statement in statements {
+            let Statement::Declaration(name, expr, span) = statement else {
+                continue;
+            };
+            let Some(ty) = types.infer(expr) else {
+                continue;
+            };
+            types.declare(name, ty.clone());
+            if ty == Type::Void {
+                continue;
+            }
+            let global = self.add_global_variable(name, self.basic_type(&ty), expr);
+            self.global_declarations
+                .insert((name.clone(), span.line, span.column), global);
+            self.globals.insert(name.clone(), global.as_pointer_value());
+            if is_managed_type(&ty) {
+                self.managed_globals.insert(name.clone());
+            } else {
+                self.managed_globals.remove(name);
+            }
+        }
+    }
+
+    // 整数リテラルで初期化する変数は定数を初期値にする (それ以外は 0 で初期化し、初期化関数で代入する)
+    fn add_global_variable(
+        &self,
+        name: &str,
+        ty: BasicTypeEnum<'a>,
+        expr: &Expr,
+    ) -> GlobalValue<'a> {
+        let global = self.module.add_global(ty, None, name);
+        global.set_linkage(Linkage::Internal);
+        match (expr, ty) {
+            (Expr::Integer(value), BasicTypeEnum::IntType(int_type)) => {
+                global.set_initializer(&int_type.const_int(*value as u64, false))
+            }
+            _ => global.set_initializer(&ty.const_zero()),
+        }
+        global
+    }
+
+    // トップレベルの変数宣言 (値は初期化関数の中で計算してグローバル変数に格納する)
+    pub(super) fn generate_global_declaration(
+        &mut self,
+        name: &str,
+        expr: &Expr,
+        span: Span,
+        function: &FunctionValue<'a>,
+    ) {
+        let value = self.generate_ir_inner(expr, function);
+        let declared = self
+            .global_declarations
+            .get(&(name.to_string(), span.line, span.column))
+            .copied()
+            .filter(|global| {
+                let element_type = global.as_pointer_value().get_type().get_element_type();
+                BasicTypeEnum::try_from(element_type) == Ok(value.get_type())
+            });
+        let global = match declared {
+            Some(global) => global,
+            // 型を推論できなかった変数 (と REPL の入力) はここで作る
+            None => {
+                let global = self.add_global_variable(name, value.get_type(), expr);
+                self.globals
+                    .insert(name.to_string(), global.as_pointer_value());
+                global
+            }
+        };
+        let pointer = global.as_pointer_value();
+        self.declare_debug_global(name, global, span);
+        self.variables.insert(name.to_string(), pointer);
+
+        // 参照カウントを持つ値は変数が参照を1つ持ち、main の最後に解放する
+        let ownership = self.ownership(expr, value);
+        if ownership == Ownership::Unmanaged {
+            self.managed_variables.remove(name);
+            self.managed_globals.remove(name);
+        } else {
+            self.take_ownership(value, ownership);
+            self.register_managed_variable(name, pointer);
+            self.managed_globals.insert(name.to_string());
+        }
+        // 整数リテラルは初期値として格納済み
+        if !matches!(expr, Expr::Integer(_)) {
+            self.builder
+                .build_store(pointer, value)
+                .expect("Failed to build store");
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use super::IRGenerator;
+use crate::ast::{Expr, Operator, Type};
+use inkwell::memory_buffer::MemoryBuffer;
+use inkwell::values::{BasicValueEnum, FunctionValue, PointerValue};
+use inkwell::AddressSpace;
+
+// 参照カウントのヘルパー関数などを定義したランタイム
+const RUNTIME_IR: &str = include_str!("runtime.ll");
+
+// 式の値の所有権
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Ownership {
+    Owned,     // 新しく確保された値 (参照カウントを1つ持っている)
+    Borrowed,  // 変数から読み出した値 (コピーするには retain が必要)
+    Unmanaged, // 参照カウントを持たない値
+}
+
+// 参照カウントで管理される型かどうか
+pub fn is_managed_type(ty: &Type) -> bool {
+    ty.is_managed()
+}
+
+impl<'a> IRGenerator<'a> {
+    // ランタイムをモジュールにリンクする
+    pub(super) fn link_runtime(&self) {
+        let buffer = MemoryBuffer::create_from_memory_range_copy(RUNTIME_IR.as_bytes(), "runtime");
+        let runtime = self
+            .context
+            .create_module_from_ir(buffer)
+            .expect("Failed to parse runtime IR");
+        self.module
+            .link_in_module(runtime)
+            .expect("Failed to link runtime");
+    }
+
+    fn runtime_function(&self, name: &str) -> FunctionValue<'a> {
+        self.module
+            .get_function(name)
+            .unwrap_or_else(|| panic!("Runtime function `{}` not found", name))
+    }
+
+    fn to_i8_pointer(&self, pointer: PointerValue<'a>) -> PointerValue<'a> {
+        let i8_pointer = self.context.i8_type().ptr_type(AddressSpace::default());
+        self.builder
+            .build_pointer_cast(pointer, i8_pointer, "rc.ptr")
+            .expect("Failed to cast pointer")
+    }
+
+    // 参照カウント1のヒープ領域を確保し、値を格納する
+    pub(super) fn build_managed_new(&mut self, value: BasicValueEnum<'a>) -> PointerValue<'a> {
+        // ターゲットが決まっていればそのデータレイアウトでサイズを求める
+        let size = match &self.target_data {
+            Some(target_data) => {
+                let bytes = target_data.get_store_size(&value.get_type());
+                self.context.i64_type().const_int(bytes, false)
+            }
+            None => value.get_type().size_of().expect("Type has no size"),
+        };
+        let alloc = self.runtime_function("omochi_alloc");
+        let raw = self
+            .builder
+            .build_call(alloc, &[size.into()], "new.raw")
+            .expect("Failed to build call")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value();
+        let pointer = self
+            .builder
+            .build_pointer_cast(
+                raw,
+                value.get_type().ptr_type(AddressSpace::default()),
+                "new",
+            )
+            .expect("Failed to cast pointer");
+        self.builder
+            .build_store(pointer, value)
+            .expect("Failed to build store");
+        pointer
+    }
+
+    // 2つの文字列を連結した新しいヒープ文字列を作る
+    pub(super) fn build_string_concat(
+        &mut self,
+        left: PointerValue<'a>,
+        right: PointerValue<'a>,
+    ) -> BasicValueEnum<'a> {
+        let concat = self.runtime_function("omochi_string_concat");
+        self.builder
+            .build_call(concat, &[left.into(), right.into()], "concat")
+            .expect("Failed to build call")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+    }
+
+    pub(super) fn build_retain(&mut self, value: BasicValueEnum<'a>) {
+        let pointer = self.to_i8_pointer(value.into_pointer_value());
+        let retain = self.runtime_function("omochi_retain");
+        self.builder
+            .build_call(retain, &[pointer.into()], "")
+            .expect("Failed to build call");
+    }
+
+    pub(super) fn build_release(&mut self, value: BasicValueEnum<'a>) {
+        let pointer = self.to_i8_pointer(value.into_pointer_value());
+        let release = self.runtime_function("omochi_release");
+        self.builder
+            .build_call(release, &[pointer.into()], "")
+            .expect("Failed to build call");
+    }
+
+    // 一時的な値を使い終わったら解放する
+    pub(super) fn release_if_owned(&mut self, value: BasicValueEnum<'a>, ownership: Ownership) {
+        if ownership == Ownership::Owned {
+            self.build_release(value);
+        }
+    }
+
+    // 値を変数などにコピーする前に、所有権を1つ確保する
+    pub(super) fn take_ownership(&mut self, value: BasicValueEnum<'a>, ownership: Ownership) {
+        if ownership == Ownership::Borrowed {
+            self.build_retain(value);
+        }
+    }
+
+    // 生成済みの式の値が参照カウントを持つかどうかを判定する
+    pub(super) fn ownership(&self, expr: &Expr, value: BasicValueEnum<'a>) -> Ownership {
+        match expr {
+            Expr::New(_) => Ownership::Owned,
+            // ポインタを返す二項演算は文字列の連結だけ
+            Expr::BinaryOp(_, Operator::Plus, _, _) if value.is_pointer_value() => Ownership::Owned,
+            Expr::FunctionCall(name, _, _) if self.managed_functions.contains(name) => {
+                Ownership::Owned
+            }
+            Expr::Variable(name, _) | Expr::Assign(name, _)
+                if self.managed_variables.contains(name) =>
+            {
+                Ownership::Borrowed
+            }
+            _ => Ownership::Unmanaged,
+        }
+    }
+
+    // 参照カウントを持つ変数を現在のスコープに登録する
+    pub(super) fn register_managed_variable(&mut self, name: &str, alloca: PointerValue<'a>) {
+        self.managed_variables.insert(name.to_string());
+        self.managed_scopes
+            .last_mut()
+            .expect("No scope")
+            .push(alloca);
+    }
+
+    pub(super) fn push_scope(&mut self) {
+        self.managed_scopes.push(Vec::new());
+    }
+
+    // スコープを抜けるときに、そのスコープの変数が持つ参照を解放する
+    pub(super) fn pop_scope(&mut self) {
+        let scope = self.managed_scopes.pop().expect("No scope");
+        for alloca in scope.into_iter().rev() {
+            let value = self
+                .builder
+                .build_load(alloca, "rc.load")
+                .expect("Failed to load variable value");
+            self.build_release(value);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+; omochi ランタイム
+; 参照カウント付きのヒープオブジェクトは [i64 参照カウント][ペイロード] の形で確保し、
+; プログラムにはペイロードへのポインタを渡す
+
+@omochi_live_objects = internal global i64 0
+@omochi_leak_format = private unnamed_addr constant [36 x i8] c"omochi: %lld heap object(s) leaked\0A\00"
+@omochi_panic_format = private unnamed_addr constant [14 x i8] c"%s:%d:%d: %s\0A\00"
+
+declare i8* @malloc(i64)
+declare void @free(i8*)
+declare i64 @strlen(i8*)
+declare i32 @dprintf(i32, i8*, ...)
+declare void @exit(i32) noreturn
+declare void @llvm.memcpy.p0i8.p0i8.i64(i8*, i8*, i64, i1)
+
+; 参照カウント 1 のオブジェクトを確保する
+define i8* @omochi_alloc(i64 %size) {
+entry:
+  %total = add i64 %size, 8
+  %raw = call i8* @malloc(i64 %total)
+  %header = bitcast i8* %raw to i64*
+  store i64 1, i64* %header
+  %live = load i64, i64* @omochi_live_objects
+  %live.next = add i64 %live, 1
+  store i64 %live.next, i64* @omochi_live_objects
+  %payload = getelementptr i8, i8* %raw, i64 8
+  ret i8* %payload
+}
+
+; 参照カウントを 1 増やす (null は無視する)
+define void @omochi_retain(i8* %payload) {
+entry:
+  %is.null = icmp eq i8* %payload, null
+  br i1 %is.null, label %done, label %increment
+
+increment:
+  %raw = getelementptr i8, i8* %payload, i64 -8
+  %header = bitcast i8* %raw to i64*
+  %count = load i64, i64* %header
+  %count.next = add i64 %count, 1
+  store i64 %count.next, i64* %header
+  br label %done
+
+done:
+  ret void
+}
+
+; 参照カウントを 1 減らし、0 になったら解放する (null は無視する)
+define void @omochi_release(i8* %payload) {
+entry:
+  %is.null = icmp eq i8* %payload, null
+  br i1 %is.null, label %done, label %decrement
+
+decrement:
+  %raw = getelementptr i8, i8* %payload, i64 -8
+  %header = bitcast i8* %raw to i64*
+  %count = load i64, i64* %header
+  %count.next = sub i64 %count, 1
+  store i64 %count.next, i64* %header
+  %is.dead = icmp eq i64 %count.next, 0
+  br i1 %is.dead, label %free, label %done
+
+free:
+  call void @free(i8* %raw)
+  %live = load i64, i64* @omochi_live_objects
+  %live.next = sub i64 %live, 1
+  store i64 %live.next, i64* @omochi_live_objects
+  br label %done
+
+done:
+  ret void
+}
+
+; 2つの文字列を連結した新しいヒープ文字列を返す
+define i8* @omochi_string_concat(i8* %left, i8* %right) {
+entry:
+  %left.len = call i64 @strlen(i8* %left)
+  %right.len = call i64 @strlen(i8* %right)
+  %len = add i64 %left.len, %right.len
+  %size = add i64 %len, 1
+  %result = call i8* @omochi_alloc(i64 %size)
+  call void @llvm.memcpy.p0i8.p0i8.i64(i8* %result, i8* %left, i64 %left.len, i1 false)
+  %tail = getelementptr i8, i8* %result, i64 %left.len
+  %right.size = add i64 %right.len, 1
+  call void @llvm.memcpy.p0i8.p0i8.i64(i8* %tail, i8* %right, i64 %right.size, i1 false)
+  ret i8* %result
+}
+
+; 解放されていないオブジェクトがあれば標準エラーに報告する
+define void @omochi_report_leaks() {
+entry:
+  %live = load i64, i64* @omochi_live_objects
+  %has.leaks = icmp ne i64 %live, 0
+  br i1 %has.leaks, label %report, label %done
+
+report:
+  %format = getelementptr [36 x i8], [36 x i8]* @omochi_leak_format, i64 0, i64 0
+  %ignored = call i32 (i32, i8*, ...) @dprintf(i32 2, i8* %format, i64 %live)
+  br label %done
+
+done:
+  ret void
+}
+
+; 実行時エラーを `file:line:col: message` の形で標準エラーに出力し、種類ごとの終了コードで終了する
+; (exit は標準出力のバッファを書き出してから終了する)
+define void @omochi_panic(i8* %file, i32 %line, i32 %column, i8* %message, i32 %code) noreturn {
+entry:
+  %format = getelementptr [14 x i8], [14 x i8]* @omochi_panic_format, i64 0, i64 0
+  %ignored = call i32 (i32, i8*, ...) @dprintf(i32 2, i8* %format, i8* %file, i32 %line, i32 %column, i8* %message)
+  call void @exit(i32 %code)
+  unreachable
+}
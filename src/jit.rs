@@ -0,0 +1,44 @@
+use inkwell::execution_engine::JitFunction;
+use inkwell::module::Module;
+use inkwell::targets::{InitializationConfig, Target};
+use inkwell::OptimizationLevel;
+use std::fmt;
+
+// JIT 実行のエラーを表す列挙型
+#[derive(Debug)]
+pub enum JitError {
+    Initialization(String),
+    EngineCreation(String),
+    MissingMain,
+}
+
+impl fmt::Display for JitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JitError::Initialization(e) => {
+                write!(f, "Failed to initialize the native target: {}", e)
+            }
+            JitError::EngineCreation(e) => write!(f, "Failed to create the JIT engine: {}", e),
+            JitError::MissingMain => write!(f, "The module has no `main` function"),
+        }
+    }
+}
+
+// 生成された main 関数の型
+type MainFunction = unsafe extern "C" fn() -> i32;
+
+// モジュールを JIT コンパイルして main を呼び出し、その戻り値を返す
+// printf などの C 関数はこのプロセスにリンクされているものが使われる
+pub fn run_main(module: &Module, level: OptimizationLevel) -> Result<i32, JitError> {
+    Target::initialize_native(&InitializationConfig::default())
+        .map_err(JitError::Initialization)?;
+    let engine = module
+        .create_jit_execution_engine(level)
+        .map_err(|e| JitError::EngineCreation(e.to_string()))?;
+    unsafe {
+        let main: JitFunction<MainFunction> = engine
+            .get_function("main")
+            .map_err(|_| JitError::MissingMain)?;
+        Ok(main.call())
+    }
+}
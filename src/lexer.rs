@@ -1,26 +1,73 @@
-use crate::ast::Token;
+use crate::ast::{Span, Token};
 use std::fmt;
 use std::str::Chars;
 
+// 整数リテラルの型 (int) の範囲。コード生成では i32 として扱う
+const INT_MAX: i64 = i32::MAX as i64;
+const INT_MIN: i64 = i32::MIN as i64;
+
 // 字句解析器のエラーを表す列挙型
 #[derive(Debug)]
 pub enum LexerError {
-    UnknownToken(char),
-    InvalidNumber(String),
+    UnknownToken(char, Span),
+    InvalidNumber(String, Span),
+    IntegerOverflow(String, Span),
+    UnterminatedString(Span),
+    InvalidEscape(char, Span),
+}
+
+impl LexerError {
+    // エラーの位置
+    pub fn span(&self) -> Span {
+        match self {
+            LexerError::UnknownToken(_, span)
+            | LexerError::InvalidNumber(_, span)
+            | LexerError::IntegerOverflow(_, span)
+            | LexerError::UnterminatedString(span)
+            | LexerError::InvalidEscape(_, span) => *span,
+        }
+    }
 }
 
 impl fmt::Display for LexerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            LexerError::UnknownToken(c) => write!(f, "Unknown token: {}", c),
-            LexerError::InvalidNumber(n) => write!(f, "Invalid number: {}", n),
+            LexerError::UnknownToken(c, span) => write!(f, "{}: Unknown token: {}", span, c),
+            LexerError::InvalidNumber(n, span) => write!(f, "{}: Invalid number: {}", span, n),
+            LexerError::IntegerOverflow(n, span) if n.starts_with('-') => write!(
+                f,
+                "{}: Integer literal `{}` does not fit in `int` (min {})",
+                span, n, INT_MIN
+            ),
+            LexerError::IntegerOverflow(n, span) => write!(
+                f,
+                "{}: Integer literal `{}` does not fit in `int` (max {})",
+                span, n, INT_MAX
+            ),
+            LexerError::UnterminatedString(span) => {
+                write!(f, "{}: Unterminated string literal", span)
+            }
+            LexerError::InvalidEscape(c, span) => {
+                write!(f, "{}: Invalid escape sequence: \\{}", span, c)
+            }
         }
     }
 }
+
+// `//` から行末までのコメント (フォーマッタが元の位置に書き戻す)
+#[derive(Debug, PartialEq, Clone)]
+pub struct Comment {
+    pub text: String, // `//` の後ろの文字列
+    pub span: Span,   // `//` の位置
+}
+
 // 字句解析器本体の構造体
 pub struct Lexer<'a> {
     input: Chars<'a>,           // 入力文字列
     current_char: Option<char>, // 現在解析中の文字
+    line: usize,                // 現在の文字の行
+    column: usize,              // 現在の文字の列
+    comments: Vec<Comment>,     // 読み飛ばしたコメント
 }
 
 impl<'a> Lexer<'a> {
@@ -29,6 +76,9 @@ impl<'a> Lexer<'a> {
         let mut lexer = Lexer {
             input: input.chars(),
             current_char: None,
+            line: 1,
+            column: 0,
+            comments: Vec::new(),
         };
         lexer.next_char(); // 最初の文字を読み込む
         lexer
@@ -36,7 +86,16 @@ impl<'a> Lexer<'a> {
 
     // 次の文字に進む
     fn next_char(&mut self) {
+        if self.current_char == Some('\n') {
+            self.line += 1;
+            self.column = 0;
+        }
         self.current_char = self.input.next();
+        self.column += 1;
+    }
+    // 現在の文字の位置
+    fn span(&self) -> Span {
+        Span::new(self.line, self.column)
     }
     // 現在の文字と次の文字をチェックする
     fn peek_next_char(&self) -> Option<char> {
@@ -44,8 +103,15 @@ impl<'a> Lexer<'a> {
     }
     // 字句解析のメインロジック
     pub fn lex(&mut self) -> Result<Vec<Token>, LexerError> {
+        self.lex_with_spans().map(|(tokens, _)| tokens)
+    }
+
+    // 字句解析を行い、各トークンの開始位置も返す
+    pub fn lex_with_spans(&mut self) -> Result<(Vec<Token>, Vec<Span>), LexerError> {
         let mut tokens = Vec::new();
+        let mut spans = Vec::new();
         while let Some(ch) = self.current_char {
+            let start = self.span();
             match ch {
                 // 空白文字は無視
                 ' ' | '\n' | '\t' | '\r' => self.next_char(),
@@ -54,6 +120,12 @@ impl<'a> Lexer<'a> {
                     tokens.push(Token::Plus);
                     self.next_char();
                 }
+                // 値の直後でない `-` に続く数字は負の整数リテラル (単項マイナスはないので `-2147483648` はこう書く)
+                '-' if self.peek_next_char().is_some_and(|c| c.is_ascii_digit())
+                    && !tokens.last().is_some_and(Token::ends_operand) =>
+                {
+                    tokens.push(self.integer()?)
+                }
                 // 減算演算子,アロー演算子
                 '-' => {
                     // 次の文字が '>' なら Arrow トークンを作成
@@ -111,43 +183,162 @@ impl<'a> Lexer<'a> {
                     tokens.push(Token::LessThan);
                     self.next_char();
                 }
+                // 行コメント
+                '/' if self.peek_next_char() == Some('/') => self.comment(),
+                // スラッシュ（除算演算子）
+                '/' => {
+                    tokens.push(Token::Slash);
+                    self.next_char();
+                }
                 // アスタリスク（乗算演算子）
                 '*' => {
                     tokens.push(Token::Asterisk);
                     self.next_char();
                 }
-                // コロン
+                // アドレス演算子
+                '&' => {
+                    tokens.push(Token::Ampersand);
+                    self.next_char();
+                }
+                // コロン,パス区切り
                 ':' => {
-                    tokens.push(Token::Colon);
+                    if self.peek_next_char() == Some(':') {
+                        tokens.push(Token::DoubleColon);
+                        self.next_char(); // 2つ目の ':' を消費
+                    } else {
+                        tokens.push(Token::Colon);
+                    }
                     self.next_char();
                 }
                 ',' => {
                     tokens.push(Token::Comma);
                     self.next_char();
                 }
+                // 可変長引数 `...`
+                '.' if self.peek_next_char() == Some('.')
+                    && self.input.clone().nth(1) == Some('.') =>
+                {
+                    tokens.push(Token::Ellipsis);
+                    self.next_char();
+                    self.next_char();
+                    self.next_char();
+                }
+                // 文字列リテラル
+                '"' => tokens.push(self.string()?),
                 // 数字なら整数リテラルの解析を行う
                 '0'..='9' => tokens.push(self.integer()?),
                 // 英字またはアンダースコアなら識別子の解析を行う
                 'a'..='z' | 'A'..='Z' | '_' => tokens.push(self.identifier()?),
                 // 例外
-                _ => return Err(LexerError::UnknownToken(ch)),
+                _ => return Err(LexerError::UnknownToken(ch, self.span())),
+            }
+            // 新しいトークンが追加されたら開始位置を記録
+            if spans.len() < tokens.len() {
+                spans.push(start);
             }
         }
         tokens.push(Token::EOF); // 入力の終了を表すトークン
-        Ok(tokens)
+        spans.push(self.span());
+        Ok((tokens, spans))
+    }
+
+    // 読み飛ばしたコメントを取り出す
+    pub fn take_comments(&mut self) -> Vec<Comment> {
+        std::mem::take(&mut self.comments)
+    }
+
+    // `//` から行末までを読み飛ばして記録する
+    fn comment(&mut self) {
+        let span = self.span();
+        self.next_char();
+        self.next_char();
+        let mut text = String::new();
+        while let Some(c) = self.current_char.filter(|&c| c != '\n') {
+            text.push(c);
+            self.next_char();
+        }
+        let text = text.trim_end().to_string();
+        self.comments.push(Comment { text, span });
     }
 
     // 整数リテラルを解析する関数
+    // 0x (16進), 0o (8進), 0b (2進) の接頭辞と `_` による桁区切りに対応する
+    // 先頭の `-` は負のリテラル (範囲は `int` の最小値まで)
     fn integer(&mut self) -> Result<Token, LexerError> {
-        let mut number = String::new();
-        while let Some('0'..='9') = self.current_char {
-            number.push(self.current_char.unwrap());
+        let span = self.span();
+        let mut literal = String::new();
+        let mut radix = 10;
+        let negative = self.current_char == Some('-');
+        if negative {
+            literal.push('-');
+            self.next_char();
+        }
+        if self.current_char == Some('0') {
+            let prefix = match self.peek_next_char() {
+                Some('x') | Some('X') => Some(16),
+                Some('o') | Some('O') => Some(8),
+                Some('b') | Some('B') => Some(2),
+                _ => None,
+            };
+            if let Some(r) = prefix {
+                radix = r;
+                literal.push('0');
+                self.next_char();
+                literal.push(self.current_char.unwrap());
+                self.next_char();
+            }
+        }
+
+        // 英数字と `_` をまとめて読み、基数に合わない文字はエラーにする
+        let mut digits = String::new();
+        while let Some(c @ ('a'..='z' | 'A'..='Z' | '0'..='9' | '_')) = self.current_char {
+            literal.push(c);
+            if c != '_' {
+                digits.push(c);
+            }
+            self.next_char();
+        }
+        if digits.is_empty() || literal.ends_with('_') || !digits.chars().all(|c| c.is_digit(radix))
+        {
+            return Err(LexerError::InvalidNumber(literal, span));
+        }
+
+        match i64::from_str_radix(&digits, radix) {
+            Ok(value) if negative && -value >= INT_MIN => Ok(Token::Integer(-value)),
+            Ok(value) if !negative && value <= INT_MAX => Ok(Token::Integer(value)),
+            _ => Err(LexerError::IntegerOverflow(literal, span)),
+        }
+    }
+
+    // 文字列リテラルを解析する関数
+    fn string(&mut self) -> Result<Token, LexerError> {
+        let span = self.span();
+        self.next_char(); // 開始の '"' を消費
+        let mut value = String::new();
+        loop {
+            match self.current_char {
+                Some('"') => break,
+                Some('\\') => {
+                    let escape_span = self.span();
+                    self.next_char();
+                    match self.current_char {
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some('r') => value.push('\r'),
+                        Some('0') => value.push('\0'),
+                        Some('\\') => value.push('\\'),
+                        Some('"') => value.push('"'),
+                        Some(c) => return Err(LexerError::InvalidEscape(c, escape_span)),
+                        None => return Err(LexerError::UnterminatedString(span)),
+                    }
+                }
+                Some(c) => value.push(c),
+                None => return Err(LexerError::UnterminatedString(span)),
+            }
             self.next_char();
         }
-        number
-            .parse::<i64>()
-            .map(Token::Integer)
-            .map_err(|_| LexerError::InvalidNumber(number))
+        self.next_char(); // 終了の '"' を消費
+        Ok(Token::Str(value))
     }
 
     // 識別子を解析する関数
@@ -162,6 +353,11 @@ impl<'a> Lexer<'a> {
             "fn" => Token::Fn,
             "if" => Token::If,
             "print" => Token::Print,
+            "mod" => Token::Mod,
+            "import" => Token::Import,
+            "pub" => Token::Pub,
+            "extern" => Token::Extern,
+            "new" => Token::New,
             _ => Token::Identifier(identifier),
         })
     }
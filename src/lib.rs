@@ -0,0 +1,42 @@
+// omochi コンパイラのライブラリ
+// `compile` でソースコードから成果物を作る。各段階は `tokenize` `parse` `load` `check` `fold_constants` などで個別に呼び出せる
+pub mod ast;
+pub mod builtin;
+pub mod bytecode;
+pub mod cli;
+pub mod diagnostic;
+pub mod driver;
+pub mod dump;
+pub mod entry;
+pub mod fold;
+pub mod format;
+pub mod interpreter;
+#[cfg(feature = "llvm")]
+pub mod irgenerator;
+#[cfg(feature = "llvm")]
+pub mod jit;
+pub mod json;
+pub mod lexer;
+#[cfg(feature = "llvm")]
+pub mod link;
+pub mod lint;
+pub mod loader;
+pub mod lsp;
+#[cfg(feature = "llvm")]
+pub mod optimize;
+pub mod panic;
+pub mod parser;
+pub mod repl;
+pub mod resolve;
+#[cfg(feature = "llvm")]
+pub mod target;
+pub mod types;
+
+pub use cli::OptLevel;
+pub use diagnostic::{Diagnostic, Severity};
+pub use driver::{
+    check, compile, compile_bytecode, fold_constants, load, parse, tokenize, Artifact, Backend,
+    CompileOptions, Output,
+};
+#[cfg(feature = "llvm")]
+pub use driver::{emit_llvm_ir, generate_ir};
@@ -0,0 +1,114 @@
+use crate::cli::Options;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, ExitStatus};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 一時ディレクトリの名前が既存のものと重なったときに作り直す回数
+const TEMP_DIR_ATTEMPTS: u32 = 100;
+
+// リンク処理のエラーを表す列挙型
+#[derive(Debug)]
+pub enum LinkError {
+    ToolNotFound(String),
+    ToolFailed(String, ExitStatus),
+    Io(String, io::Error),
+    TempDir(PathBuf, io::Error),
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::ToolNotFound(tool) => write!(
+                f,
+                "`{}` was not found in PATH; install a C toolchain (gcc or clang) to link executables",
+                tool
+            ),
+            LinkError::ToolFailed(tool, status) => write!(f, "`{}` failed ({})", tool, status),
+            LinkError::Io(tool, e) => write!(f, "Failed to run `{}`: {}", tool, e),
+            LinkError::TempDir(path, e) => {
+                write!(f, "Failed to create temporary directory {}: {}", path.display(), e)
+            }
+        }
+    }
+}
+
+// 中間ファイルを置く一時ディレクトリ (破棄されるときに削除する)
+pub struct TempDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl TempDir {
+    // 他のユーザーが先に同じ名前で作ったディレクトリを使わないように、既存なら名前を変えて作り直す
+    // (Unix では所有者だけが読み書きできる 0700 で作る)
+    pub fn new(keep: bool) -> Result<TempDir, LinkError> {
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        let mut attempt = 0;
+        loop {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.subsec_nanos());
+            let name = format!("omochi-{}-{}-{}", process::id(), nanos, attempt);
+            let path = std::env::temp_dir().join(name);
+            match builder.create(&path) {
+                Ok(()) => return Ok(TempDir { path, keep }),
+                Err(e)
+                    if e.kind() == io::ErrorKind::AlreadyExists && attempt < TEMP_DIR_ATTEMPTS =>
+                {
+                    attempt += 1;
+                }
+                Err(e) => return Err(LinkError::TempDir(path, e)),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if self.keep {
+            eprintln!("Intermediate files kept in {}", self.path.display());
+        } else {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+// 外部コマンドを実行し、失敗したらエラーにする
+fn run(tool: &str, command: &mut Command) -> Result<(), LinkError> {
+    let status = command.status().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => LinkError::ToolNotFound(tool.to_string()),
+        _ => LinkError::Io(tool.to_string(), e),
+    })?;
+    if !status.success() {
+        return Err(LinkError::ToolFailed(tool.to_string(), status));
+    }
+    Ok(())
+}
+
+// オブジェクトファイルを追加の入力やライブラリと一緒にリンクする
+pub fn link_executable(object_path: &Path, options: &Options) -> Result<(), LinkError> {
+    let mut cc = Command::new("cc");
+    cc.arg("-fPIE").arg("-pie").arg(object_path);
+    // .c ファイルは cc がそのままコンパイルする
+    cc.args(&options.link_inputs);
+    for path in &options.library_paths {
+        cc.arg("-L").arg(path);
+    }
+    for library in &options.libraries {
+        cc.arg(format!("-l{}", library));
+    }
+    cc.arg("-o").arg(&options.output);
+    run("cc", &mut cc)
+}
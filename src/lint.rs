@@ -0,0 +1,447 @@
+use crate::ast::{Expr, Function, Span, Statement};
+use crate::fold;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// リントの報告レベル
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Level {
+    Allow, // 報告しない
+    Warn,  // 警告として報告する
+    Deny,  // エラーとして報告し、コンパイルを止める
+}
+
+// リント名
+pub const UNUSED_VARIABLES: &str = "unused_variables"; // 読まれない `let` 変数
+pub const UNUSED_PARAMETERS: &str = "unused_parameters"; // 読まれない引数
+pub const UNUSED_ASSIGNMENTS: &str = "unused_assignments"; // 読まれる前に上書きされる代入
+pub const SHADOWING: &str = "shadowing"; // 同じ名前の変数を隠す `let`
+pub const UNREACHABLE_CODE: &str = "unreachable_code"; // `exit` などの後の文
+pub const CONSTANT_CONDITION: &str = "constant_condition"; // 常に真または偽の `if` 条件
+pub const DEAD_CODE: &str = "dead_code"; // 一度も呼ばれない関数
+
+pub const LINTS: [&str; 7] = [
+    UNUSED_VARIABLES,
+    UNUSED_PARAMETERS,
+    UNUSED_ASSIGNMENTS,
+    SHADOWING,
+    UNREACHABLE_CODE,
+    CONSTANT_CONDITION,
+    DEAD_CODE,
+];
+
+// `-W warnings` などですべてのリントを指定する名前
+pub const ALL_LINTS: &str = "warnings";
+
+// 呼び出すと戻ってこない関数
+const DIVERGING_FUNCTIONS: [&str; 4] = ["exit", "_exit", "abort", "panic"];
+
+// リントごとの報告レベル (コマンドラインの `-W` / `-A` / `-D` で変更する)
+#[derive(Debug, Clone)]
+pub struct LintConfig {
+    levels: HashMap<&'static str, Level>,
+}
+
+impl LintConfig {
+    pub fn new() -> Self {
+        LintConfig {
+            levels: LINTS.iter().map(|&lint| (lint, Level::Warn)).collect(),
+        }
+    }
+
+    // 知らないリント名なら Err を返す
+    pub fn set(&mut self, name: &str, level: Level) -> Result<(), String> {
+        if name == ALL_LINTS {
+            for value in self.levels.values_mut() {
+                *value = level;
+            }
+            return Ok(());
+        }
+        let lint = LINTS
+            .iter()
+            .find(|&&lint| lint == name)
+            .ok_or_else(|| name.to_string())?;
+        self.levels.insert(lint, level);
+        Ok(())
+    }
+
+    fn level(&self, name: &str) -> Level {
+        self.levels[name]
+    }
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// リントの報告
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub lint: &'static str,
+    pub level: Level,
+    pub span: Span,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.level {
+            Level::Deny => "error",
+            _ => "warning",
+        };
+        write!(
+            f,
+            "{}: {}: {} [{}]",
+            self.span, level, self.message, self.lint
+        )
+    }
+}
+
+// 変数の状態
+struct Binding {
+    name: String,
+    span: Span,
+    is_param: bool,
+    read: bool,
+    escaped: bool, // アドレスを取られたのでどこから読まれるか分からない
+    pending_write: Option<(Span, Vec<usize>)>, // まだ読まれていない代入と、その時点の分岐の経路
+    dead_writes: Vec<Span>,
+}
+
+// AST を走査してリントを集める
+struct Linter<'c> {
+    config: &'c LintConfig,
+    diagnostics: Vec<Diagnostic>,
+    scopes: Vec<Vec<Binding>>,
+    path: Vec<usize>, // 現在の分岐の経路 (if の各分岐に一意な番号を振る)
+    next_branch: usize,
+    in_function: bool,
+}
+
+// プログラム全体にリントを実行する
+pub fn check(statements: &[Statement], config: &LintConfig) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        config,
+        diagnostics: Vec::new(),
+        scopes: Vec::new(),
+        path: Vec::new(),
+        next_branch: 0,
+        in_function: false,
+    };
+    linter.dead_functions(statements);
+    linter.scopes.push(Vec::new());
+    linter.block(statements);
+    // 関数はトップレベルの変数を参照できるので、すべて宣言してから本体を調べる
+    for statement in statements {
+        if let Statement::Function(func) = statement {
+            linter.function(func);
+        }
+    }
+    linter.pop_scope();
+    linter
+        .diagnostics
+        .sort_by_key(|d| (d.span.line, d.span.column));
+    linter.diagnostics
+}
+
+impl Linter<'_> {
+    fn report(&mut self, lint: &'static str, span: Span, message: String) {
+        let level = self.config.level(lint);
+        if level != Level::Allow {
+            self.diagnostics.push(Diagnostic {
+                lint,
+                level,
+                span,
+                message,
+            });
+        }
+    }
+
+    // 一度も呼ばれない関数
+    fn dead_functions(&mut self, statements: &[Statement]) {
+        let mut functions = Vec::new();
+        let mut called = HashSet::new();
+        collect_functions(statements, &mut functions, &mut called);
+        for func in functions {
+            if !func.is_pub && func.name != "main" && !called.contains(func.name.as_str()) {
+                self.report(
+                    DEAD_CODE,
+                    func.span,
+                    format!("function `{}` is never called", func.name),
+                );
+            }
+        }
+    }
+
+    fn block(&mut self, statements: &[Statement]) {
+        let mut diverged: Option<(Span, String)> = None;
+        for statement in statements {
+            if let Some((span, name)) = diverged.take() {
+                self.report(
+                    UNREACHABLE_CODE,
+                    span,
+                    format!("statements after this call to `{}` are unreachable", name),
+                );
+            }
+            self.statement(statement);
+            if let Statement::Expression(Expr::FunctionCall(name, _, span)) = statement {
+                if DIVERGING_FUNCTIONS.contains(&name.as_str()) {
+                    diverged = Some((*span, name.clone()));
+                }
+            }
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(expr) | Statement::Print(expr) => self.expr(expr),
+            Statement::Declaration(name, expr, span) => {
+                self.expr(expr);
+                if self.lookup(name).is_some() && !name.starts_with('_') {
+                    self.report(
+                        SHADOWING,
+                        *span,
+                        format!("`{}` shadows an earlier binding", name),
+                    );
+                }
+                let pending_write = Some((*span, self.path.clone()));
+                self.declare(name, *span, false, pending_write);
+            }
+            Statement::Assignment(name, expr, span) => {
+                self.expr(expr);
+                self.write(name, *span);
+            }
+            Statement::DerefAssignment(pointer, expr, _) => {
+                self.expr(pointer);
+                self.expr(expr);
+            }
+            Statement::Block(statements) => {
+                self.scopes.push(Vec::new());
+                self.block(statements);
+                self.pop_scope();
+            }
+            Statement::If(condition, then_branch, else_branch, span) => {
+                if let Ok(Expr::Integer(value)) = fold::fold_expr(condition.as_ref().clone()) {
+                    let always = if value == 0 { "false" } else { "true" };
+                    self.report(
+                        CONSTANT_CONDITION,
+                        *span,
+                        format!("this `if` condition is always {}", always),
+                    );
+                }
+                self.expr(condition);
+                self.branch(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.branch(else_branch);
+                }
+            }
+            // トップレベルの関数は最後に調べる
+            Statement::Function(_) if self.scopes.len() == 1 && !self.in_function => {}
+            Statement::Function(func) => self.function(func),
+            Statement::Mod(_) | Statement::Import(_) | Statement::Extern(_) => {}
+        }
+    }
+
+    fn branch(&mut self, statement: &Statement) {
+        self.path.push(self.next_branch);
+        self.next_branch += 1;
+        self.statement(statement);
+        self.path.pop();
+    }
+
+    // 関数の本体は呼び出し元の変数を参照できないので、トップレベルの変数だけが見える環境で調べる
+    fn function(&mut self, func: &Function) {
+        let outer_scopes = self.scopes.split_off(1);
+        let outer_path = std::mem::take(&mut self.path);
+        let outer_in_function = std::mem::replace(&mut self.in_function, true);
+        self.scopes.push(Vec::new());
+        for ((param, _), span) in func.params.iter().zip(&func.param_spans) {
+            self.declare(param, *span, true, None);
+        }
+        self.block(&func.body);
+        self.expr(&func.return_expr);
+        self.pop_scope();
+        self.scopes.extend(outer_scopes);
+        self.path = outer_path;
+        self.in_function = outer_in_function;
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Integer(_) | Expr::Str(_) => {}
+            Expr::Variable(name, _) => self.read(name),
+            Expr::Assign(name, value) => {
+                // 式の中の代入は位置が分からないので、読まれたものとして扱う
+                self.expr(value);
+                self.read(name);
+            }
+            Expr::BinaryOp(left, _, right, _) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::If(condition, then_branch, else_branch) => {
+                self.expr(condition);
+                self.branch(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.branch(else_branch);
+                }
+            }
+            Expr::FunctionCall(_, args, _) => args.iter().for_each(|arg| self.expr(arg)),
+            Expr::AddressOf(name) => {
+                self.read(name);
+                if let Some(binding) = self.lookup(name) {
+                    binding.escaped = true;
+                }
+            }
+            Expr::Deref(value, _) | Expr::New(value) => self.expr(value),
+        }
+    }
+
+    fn declare(
+        &mut self,
+        name: &str,
+        span: Span,
+        is_param: bool,
+        pending_write: Option<(Span, Vec<usize>)>,
+    ) {
+        self.scopes.last_mut().unwrap().push(Binding {
+            name: name.to_string(),
+            span,
+            is_param,
+            read: false,
+            escaped: false,
+            pending_write,
+            dead_writes: Vec::new(),
+        });
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<&mut Binding> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.iter_mut().rev().find(|b| b.name == name))
+    }
+
+    // 読み書きする変数 (関数からトップレベルの変数を使う場合は、いつ使われるか分からない)
+    fn access(&mut self, name: &str) -> Option<&mut Binding> {
+        let in_function = self.in_function;
+        let global = !self.scopes[1..]
+            .iter()
+            .any(|scope| scope.iter().any(|b| b.name == name));
+        let binding = self.lookup(name)?;
+        if in_function && global {
+            binding.escaped = true;
+        }
+        Some(binding)
+    }
+
+    fn read(&mut self, name: &str) {
+        if let Some(binding) = self.access(name) {
+            binding.read = true;
+            binding.pending_write = None;
+        }
+    }
+
+    fn write(&mut self, name: &str, span: Span) {
+        let path = self.path.clone();
+        if let Some(binding) = self.access(name) {
+            // 前の代入の後のすべての経路でこの代入を通るなら、前の値は読まれない
+            if let Some((previous, previous_path)) = binding.pending_write.take() {
+                if previous_path.starts_with(&path) {
+                    binding.dead_writes.push(previous);
+                }
+            }
+            binding.pending_write = Some((span, path));
+        }
+    }
+
+    fn pop_scope(&mut self) {
+        for binding in self.scopes.pop().unwrap() {
+            if binding.name.starts_with('_') || binding.escaped {
+                continue;
+            }
+            if !binding.read {
+                let (lint, kind) = if binding.is_param {
+                    (UNUSED_PARAMETERS, "parameter")
+                } else {
+                    (UNUSED_VARIABLES, "variable")
+                };
+                self.report(
+                    lint,
+                    binding.span,
+                    format!("unused {} `{}`", kind, binding.name),
+                );
+                continue;
+            }
+            let last_write = binding.pending_write.map(|(span, _)| span);
+            for span in binding.dead_writes.into_iter().chain(last_write) {
+                self.report(
+                    UNUSED_ASSIGNMENTS,
+                    span,
+                    format!("value assigned to `{}` is never read", binding.name),
+                );
+            }
+        }
+    }
+}
+
+// 定義された関数と、呼び出されている関数名を集める
+fn collect_functions<'a>(
+    statements: &'a [Statement],
+    functions: &mut Vec<&'a Function>,
+    called: &mut HashSet<&'a str>,
+) {
+    for statement in statements {
+        match statement {
+            Statement::Expression(expr) | Statement::Print(expr) => collect_calls(expr, called),
+            Statement::Declaration(_, expr, _) | Statement::Assignment(_, expr, _) => {
+                collect_calls(expr, called)
+            }
+            Statement::DerefAssignment(pointer, expr, _) => {
+                collect_calls(pointer, called);
+                collect_calls(expr, called);
+            }
+            Statement::Block(body) => collect_functions(body, functions, called),
+            Statement::If(condition, then_branch, else_branch, _) => {
+                collect_calls(condition, called);
+                collect_functions(std::slice::from_ref(then_branch), functions, called);
+                if let Some(else_branch) = else_branch {
+                    collect_functions(std::slice::from_ref(else_branch), functions, called);
+                }
+            }
+            Statement::Function(func) => {
+                functions.push(func);
+                collect_functions(&func.body, functions, called);
+                collect_calls(&func.return_expr, called);
+            }
+            Statement::Mod(_) | Statement::Import(_) | Statement::Extern(_) => {}
+        }
+    }
+}
+
+fn collect_calls<'a>(expr: &'a Expr, called: &mut HashSet<&'a str>) {
+    match expr {
+        Expr::FunctionCall(name, args, _) => {
+            called.insert(name);
+            args.iter().for_each(|arg| collect_calls(arg, called));
+        }
+        Expr::BinaryOp(left, _, right, _) => {
+            collect_calls(left, called);
+            collect_calls(right, called);
+        }
+        Expr::Assign(_, value) | Expr::Deref(value, _) | Expr::New(value) => {
+            collect_calls(value, called)
+        }
+        Expr::If(condition, then_branch, else_branch) => {
+            collect_calls(condition, called);
+            let mut functions = Vec::new();
+            collect_functions(std::slice::from_ref(then_branch), &mut functions, called);
+            if let Some(else_branch) = else_branch {
+                collect_functions(std::slice::from_ref(else_branch), &mut functions, called);
+            }
+        }
+        Expr::Integer(_) | Expr::Str(_) | Expr::Variable(..) | Expr::AddressOf(_) => {}
+    }
+}
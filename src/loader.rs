@@ -0,0 +1,404 @@
+use crate::ast::{Expr, Span, Statement};
+use crate::builtin;
+use crate::lexer::{Lexer, LexerError};
+use crate::parser::{Parser, ParserError};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// ソースファイルの拡張子
+const SOURCE_EXTENSION: &str = "omo";
+
+// モジュール読み込みのエラーを表す列挙型
+#[derive(Debug)]
+pub enum LoaderError {
+    Io(PathBuf, io::Error),
+    Lexer(PathBuf, LexerError),
+    Parser(PathBuf, ParserError, Span),
+    ImportCycle(Vec<PathBuf>),
+    UnresolvedPath(PathBuf, String, Option<Span>), // Span は呼び出しの位置 (import は位置を持たない)
+    PrivateItem(PathBuf, String, Option<Span>),
+    NotAnItem(PathBuf),
+}
+
+impl fmt::Display for LoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoaderError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            LoaderError::Lexer(path, e) => write!(f, "{}:{}", path.display(), e),
+            LoaderError::Parser(path, e, span) => {
+                write!(f, "{}:{}: {}", path.display(), span, e)
+            }
+            LoaderError::ImportCycle(cycle) => {
+                let names: Vec<String> = cycle.iter().map(|p| p.display().to_string()).collect();
+                write!(f, "Import cycle: {}", names.join(" -> "))
+            }
+            LoaderError::UnresolvedPath(path, name, span) => {
+                write!(f, "{}: Cannot resolve `{}`", located(path, *span), name)
+            }
+            LoaderError::PrivateItem(path, name, span) => {
+                write!(f, "{}: `{}` is private", located(path, *span), name)
+            }
+            LoaderError::NotAnItem(path) => write!(
+                f,
+                "{}: Only `fn`, `extern fn`, `mod` and `import` are allowed in an imported module",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl LoaderError {
+    // エラーのあったファイル (循環は複数のファイルにまたがる)
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            LoaderError::Io(path, _)
+            | LoaderError::Lexer(path, _)
+            | LoaderError::Parser(path, _, _)
+            | LoaderError::UnresolvedPath(path, _, _)
+            | LoaderError::PrivateItem(path, _, _)
+            | LoaderError::NotAnItem(path) => Some(path),
+            LoaderError::ImportCycle(_) => None,
+        }
+    }
+
+    // エラーの位置 (ファイル全体に関わるエラーは None)
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            LoaderError::Parser(_, _, span) => Some(*span),
+            LoaderError::Lexer(_, e) => Some(e.span()),
+            LoaderError::UnresolvedPath(_, _, span) | LoaderError::PrivateItem(_, _, span) => *span,
+            LoaderError::Io(..) | LoaderError::ImportCycle(_) | LoaderError::NotAnItem(_) => None,
+        }
+    }
+}
+
+// `path:line:col` (位置がなければ `path`)
+fn located(path: &Path, span: Option<Span>) -> String {
+    match span {
+        Some(span) => format!("{}:{}", path.display(), span),
+        None => path.display().to_string(),
+    }
+}
+
+// 読み込み済みモジュールの情報
+struct LoadedModule {
+    path: PathBuf,
+    functions: HashMap<String, (String, bool)>, // 関数名 -> (シンボル名, pub かどうか)
+    children: HashMap<String, usize>,           // `mod` で宣言した子モジュール
+    imports: HashMap<String, Binding>,          // `import` で持ち込んだ名前
+    prefix: String,                             // 関数のシンボル名の接頭辞 (エントリファイルは空)
+}
+
+// パスの解決結果
+#[derive(Clone)]
+enum Binding {
+    Function(String),
+    Module(usize),
+}
+
+// 複数ファイルからなるプログラムを読み込み、1つの文のリストにまとめる
+pub struct Loader {
+    modules: Vec<LoadedModule>,
+    by_path: HashMap<PathBuf, usize>,
+    stack: Vec<PathBuf>, // 読み込み中のファイル (循環の検出用)
+    items: Vec<Statement>,
+}
+
+impl Loader {
+    pub fn new() -> Self {
+        Loader {
+            modules: Vec::new(),
+            by_path: HashMap::new(),
+            stack: Vec::new(),
+            items: Vec::new(),
+        }
+    }
+
+    // エントリファイルから辿れるすべてのモジュールを読み込む
+    // 子モジュールの関数が先に並び、最後にエントリファイルの文が続く
+    // 読み込んだファイルのパスも返す (Span の file はこの添字で、0 がエントリファイル)
+    pub fn load_program(
+        mut self,
+        entry: &Path,
+    ) -> Result<(Vec<Statement>, Vec<PathBuf>), LoaderError> {
+        self.load(entry, None, String::new())?;
+        Ok(self.finish())
+    }
+
+    // 読み込み済みのソースをエントリファイルとして使う (子モジュールは entry からの相対パスで探す)
+    pub fn load_source(
+        mut self,
+        entry: &Path,
+        source: &str,
+    ) -> Result<(Vec<Statement>, Vec<PathBuf>), LoaderError> {
+        self.load(entry, Some(source), String::new())?;
+        Ok(self.finish())
+    }
+
+    fn finish(self) -> (Vec<Statement>, Vec<PathBuf>) {
+        let files = self.modules.into_iter().map(|module| module.path).collect();
+        (self.items, files)
+    }
+
+    // 1つのファイルを読み込み、名前解決した文を items に追加する
+    fn load(
+        &mut self,
+        path: &Path,
+        source: Option<&str>,
+        prefix: String,
+    ) -> Result<usize, LoaderError> {
+        // ソースが与えられた場合はファイルが存在しなくてもよい
+        let path = match fs::canonicalize(path) {
+            Ok(path) => path,
+            Err(_) if source.is_some() => path.to_path_buf(),
+            Err(e) => return Err(LoaderError::Io(path.to_path_buf(), e)),
+        };
+        if let Some(start) = self.stack.iter().position(|p| *p == path) {
+            let mut cycle = self.stack[start..].to_vec();
+            cycle.push(path);
+            return Err(LoaderError::ImportCycle(cycle));
+        }
+        if let Some(&id) = self.by_path.get(&path) {
+            return Ok(id);
+        }
+
+        let source = match source {
+            Some(source) => source.to_string(),
+            None => fs::read_to_string(&path).map_err(|e| LoaderError::Io(path.clone(), e))?,
+        };
+        let (tokens, mut spans) = Lexer::new(&source)
+            .lex_with_spans()
+            .map_err(|e| LoaderError::Lexer(path.clone(), e))?;
+        // 位置にファイルの番号を付ける (このファイルは次に modules に追加される)
+        let id = self.modules.len();
+        for span in &mut spans {
+            span.file = id;
+        }
+        let mut parser = Parser::with_spans(tokens, spans);
+        let mut statements = parser
+            .parse_statements()
+            .map_err(|e| {
+                let span = e.span().unwrap_or_else(|| parser.error_span());
+                LoaderError::Parser(path.clone(), e, span)
+            })?;
+
+        self.modules.push(LoadedModule {
+            path: path.clone(),
+            functions: HashMap::new(),
+            children: HashMap::new(),
+            imports: HashMap::new(),
+            prefix: prefix.clone(),
+        });
+        self.stack.push(path.clone());
+
+        // 1. 子モジュールの読み込みと関数名の登録
+        let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        for statement in &statements {
+            match statement {
+                Statement::Mod(name) => {
+                    let child_path = dir.join(name).with_extension(SOURCE_EXTENSION);
+                    let child_prefix = if prefix.is_empty() {
+                        name.clone()
+                    } else {
+                        format!("{}.{}", prefix, name)
+                    };
+                    let child = self.load(&child_path, None, child_prefix)?;
+                    self.modules[id].children.insert(name.clone(), child);
+                }
+                Statement::Function(func) => {
+                    let symbol = mangle(&prefix, &func.name);
+                    self.modules[id]
+                        .functions
+                        .insert(func.name.clone(), (symbol, func.is_pub));
+                }
+                // C関数はシンボル名をそのまま使う
+                Statement::Extern(func) => {
+                    self.modules[id]
+                        .functions
+                        .insert(func.name.clone(), (func.name.clone(), func.is_pub));
+                }
+                _ => {}
+            }
+        }
+
+        // 2. import の解決
+        for statement in &statements {
+            if let Statement::Import(segments) = statement {
+                let binding = self.resolve(id, segments, None)?;
+                let name = segments.last().unwrap().clone();
+                self.modules[id].imports.insert(name, binding);
+            }
+        }
+
+        // 3. 関数呼び出しをシンボル名に書き換えて出力に追加
+        for statement in statements.iter_mut() {
+            self.resolve_statement(id, statement)?;
+        }
+        for statement in statements {
+            match statement {
+                Statement::Mod(_) | Statement::Import(_) => {}
+                Statement::Function(mut func) => {
+                    func.name = mangle(&prefix, &func.name);
+                    self.items.push(Statement::Function(func));
+                }
+                Statement::Extern(func) => self.items.push(Statement::Extern(func)),
+                other if prefix.is_empty() => self.items.push(other),
+                _ => return Err(LoaderError::NotAnItem(path)),
+            }
+        }
+
+        self.stack.pop();
+        self.by_path.insert(path, id);
+        Ok(id)
+    }
+
+    // `a::b::f` のようなパスをモジュール id から解決する
+    fn resolve(
+        &self,
+        id: usize,
+        segments: &[String],
+        span: Option<Span>,
+    ) -> Result<Binding, LoaderError> {
+        let module = &self.modules[id];
+        let unresolved =
+            || LoaderError::UnresolvedPath(module.path.clone(), segments.join("::"), span);
+
+        // 先頭の名前は自モジュールの関数・子モジュール・import から探す
+        let first = &segments[0];
+        let mut binding = if let Some((symbol, _)) = module.functions.get(first) {
+            Binding::Function(symbol.clone())
+        } else if let Some(&child) = module.children.get(first) {
+            Binding::Module(child)
+        } else if let Some(binding) = module.imports.get(first) {
+            binding.clone()
+        } else {
+            return Err(unresolved());
+        };
+
+        // 残りの名前は pub な関数か子モジュールでなければならない
+        for segment in &segments[1..] {
+            let Binding::Module(current) = binding else {
+                return Err(unresolved());
+            };
+            let current = &self.modules[current];
+            binding = if let Some((symbol, is_pub)) = current.functions.get(segment) {
+                if !is_pub {
+                    return Err(LoaderError::PrivateItem(
+                        module.path.clone(),
+                        segments.join("::"),
+                        span,
+                    ));
+                }
+                Binding::Function(symbol.clone())
+            } else if let Some(&child) = current.children.get(segment) {
+                Binding::Module(child)
+            } else {
+                return Err(unresolved());
+            };
+        }
+        Ok(binding)
+    }
+
+    // 文の中の関数呼び出しを解決する
+    fn resolve_statement(&self, id: usize, statement: &mut Statement) -> Result<(), LoaderError> {
+        match statement {
+            Statement::Expression(expr)
+            | Statement::Declaration(_, expr, _)
+            | Statement::Assignment(_, expr, _)
+            | Statement::Print(expr) => self.resolve_expr(id, expr),
+            Statement::DerefAssignment(pointer, value, _) => {
+                self.resolve_expr(id, pointer)?;
+                self.resolve_expr(id, value)
+            }
+            Statement::Block(statements) => {
+                for stmt in statements {
+                    self.resolve_statement(id, stmt)?;
+                }
+                Ok(())
+            }
+            Statement::If(condition, then_branch, else_branch, _) => {
+                self.resolve_expr(id, condition)?;
+                self.resolve_statement(id, then_branch)?;
+                if let Some(else_branch) = else_branch {
+                    self.resolve_statement(id, else_branch)?;
+                }
+                Ok(())
+            }
+            Statement::Function(func) => {
+                for stmt in func.body.iter_mut() {
+                    self.resolve_statement(id, stmt)?;
+                }
+                self.resolve_expr(id, &mut func.return_expr)
+            }
+            Statement::Mod(_) | Statement::Import(_) | Statement::Extern(_) => Ok(()),
+        }
+    }
+
+    // 式の中の関数呼び出しを解決する
+    fn resolve_expr(&self, id: usize, expr: &mut Expr) -> Result<(), LoaderError> {
+        match expr {
+            Expr::Integer(_) | Expr::Str(_) | Expr::Variable(..) | Expr::AddressOf(_) => Ok(()),
+            Expr::Deref(inner, _) | Expr::New(inner) => self.resolve_expr(id, inner),
+            Expr::BinaryOp(left, _, right, _) => {
+                self.resolve_expr(id, left)?;
+                self.resolve_expr(id, right)
+            }
+            Expr::Assign(_, value) => self.resolve_expr(id, value),
+            Expr::If(condition, then_branch, else_branch) => {
+                self.resolve_expr(id, condition)?;
+                self.resolve_statement(id, then_branch)?;
+                if let Some(else_branch) = else_branch {
+                    self.resolve_statement(id, else_branch)?;
+                }
+                Ok(())
+            }
+            Expr::FunctionCall(name, args, span) => {
+                for arg in args.iter_mut() {
+                    self.resolve_expr(id, arg)?;
+                }
+                let segments: Vec<String> = name.split("::").map(String::from).collect();
+                match self.resolve(id, &segments, Some(*span)) {
+                    Ok(Binding::Function(symbol)) => {
+                        *name = symbol;
+                        Ok(())
+                    }
+                    Ok(Binding::Module(_)) => Err(LoaderError::UnresolvedPath(
+                        self.modules[id].path.clone(),
+                        name.clone(),
+                        Some(*span),
+                    )),
+                    // 組み込み関数は名前をそのまま使う
+                    Err(_) if builtin::lookup(name).is_some() => Ok(()),
+                    // 見つからない修飾なしの名前は名前解決 (resolve::check) で他の未定義の名前と一緒に報告する
+                    // 子モジュールでは別のモジュールの同じ名前の関数と取り違えないようにモジュールのパスで修飾する
+                    Err(LoaderError::UnresolvedPath(..)) if segments.len() == 1 => {
+                        let prefix = &self.modules[id].prefix;
+                        if !prefix.is_empty() {
+                            *name = format!("{}::{}", prefix.replace('.', "::"), name);
+                        }
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+        }
+    }
+}
+
+impl Default for Loader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// モジュールの接頭辞と関数名から LLVM のシンボル名を作る
+fn mangle(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", prefix, name)
+    }
+}
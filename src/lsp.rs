@@ -0,0 +1,435 @@
+mod analysis;
+
+use crate::ast::Span;
+use crate::json::Json;
+use analysis::{Analysis, Severity, SymbolKind};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+// LSP のエラーコード
+const PARSE_ERROR: i64 = -32700;
+const INVALID_PARAMS: i64 = -32602;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// LSP の CompletionItemKind と SymbolKind
+const COMPLETION_FUNCTION: i64 = 3;
+const COMPLETION_VARIABLE: i64 = 6;
+const COMPLETION_KEYWORD: i64 = 14;
+const SYMBOL_FUNCTION: i64 = 12;
+
+// 受け付けるメッセージの最大の長さ (Content-Length を信じて巨大な領域を確保しない)
+const MAX_CONTENT_LENGTH: usize = 64 * 1024 * 1024;
+
+const KEYWORDS: [&str; 10] = [
+    "let", "fn", "if", "else", "print", "mod", "import", "pub", "extern", "new",
+];
+
+// 標準入出力で LSP を話す言語サーバ
+// `exit` 通知を受け取るか入力が終わるまで続け、終了コードを返す
+pub fn run<R: BufRead, W: Write>(mut input: R, output: W) -> io::Result<i32> {
+    let mut server = Server {
+        output,
+        documents: HashMap::new(),
+        shutdown: false,
+    };
+    while let Some(body) = read_message(&mut input)? {
+        if let Some(code) = server.handle(&body)? {
+            return Ok(code);
+        }
+    }
+    Ok(1)
+}
+
+// `Content-Length: n` ヘッダと空行の後に n バイトの JSON が続く
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    if length > MAX_CONTENT_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Content-Length {} exceeds {}", length, MAX_CONTENT_LENGTH),
+        ));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+struct Server<W: Write> {
+    output: W,
+    documents: HashMap<String, Analysis>, // 開いているドキュメントの URI ごとの解析結果
+    shutdown: bool,                       // `shutdown` を受け取った
+}
+
+impl<W: Write> Server<W> {
+    fn send(&mut self, message: Json) -> io::Result<()> {
+        let body = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.output.flush()
+    }
+
+    fn respond(&mut self, id: Json, result: Json) -> io::Result<()> {
+        self.send(Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("id", id),
+            ("result", result),
+        ]))
+    }
+
+    fn respond_error(&mut self, id: Json, code: i64, message: &str) -> io::Result<()> {
+        self.send(Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("id", id),
+            (
+                "error",
+                Json::object([("code", code.into()), ("message", message.into())]),
+            ),
+        ]))
+    }
+
+    fn notify(&mut self, method: &str, params: Json) -> io::Result<()> {
+        self.send(Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("method", method.into()),
+            ("params", params),
+        ]))
+    }
+
+    // 1つのメッセージを処理する (`exit` なら終了コードを返す)
+    fn handle(&mut self, body: &str) -> io::Result<Option<i32>> {
+        let message = match Json::parse(body) {
+            Ok(message) => message,
+            Err(e) => {
+                self.respond_error(Json::Null, PARSE_ERROR, &e.to_string())?;
+                return Ok(None);
+            }
+        };
+        let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = message.get("params").cloned().unwrap_or(Json::Null);
+        let Some(id) = message.get("id").cloned() else {
+            // 通知には応答しない
+            return self.handle_notification(method, &params);
+        };
+
+        let result = match method {
+            "initialize" => Some(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Some(Json::Null)
+            }
+            "textDocument/hover" => self.with_position(&params, Self::hover),
+            "textDocument/definition" => self.with_position(&params, Self::definition),
+            "textDocument/references" => self.with_position(&params, Self::references),
+            "textDocument/completion" => self.with_position(&params, Self::completion),
+            "textDocument/documentSymbol" => self.document_symbols(&params),
+            _ => {
+                let message = format!("Unknown method `{}`", method);
+                return self
+                    .respond_error(id, METHOD_NOT_FOUND, &message)
+                    .map(|_| None);
+            }
+        };
+        match result {
+            Some(result) => self.respond(id, result)?,
+            None => self.respond_error(id, INVALID_PARAMS, "Unknown document or position")?,
+        }
+        Ok(None)
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Json) -> io::Result<Option<i32>> {
+        let uri = params
+            .get("textDocument")
+            .and_then(|d| d.get("uri"))
+            .and_then(Json::as_str)
+            .map(str::to_string);
+        match (method, uri) {
+            ("exit", _) => return Ok(Some(if self.shutdown { 0 } else { 1 })),
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = params
+                    .get("textDocument")
+                    .and_then(|d| d.get("text"))
+                    .and_then(Json::as_str);
+                if let Some(text) = text {
+                    self.update(uri, text)?;
+                }
+            }
+            // 同期はドキュメント全体を送る方式なので最後の変更だけを見ればよい
+            ("textDocument/didChange", Some(uri)) => {
+                let text = params
+                    .get("contentChanges")
+                    .and_then(Json::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Json::as_str);
+                if let Some(text) = text {
+                    self.update(uri, text)?;
+                }
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(&uri);
+                self.publish_diagnostics(&uri)?;
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    // ドキュメントを解析し直して診断を送る
+    fn update(&mut self, uri: String, text: &str) -> io::Result<()> {
+        let analysis = Analysis::new(text, &uri_to_path(&uri));
+        self.documents.insert(uri.clone(), analysis);
+        self.publish_diagnostics(&uri)
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) -> io::Result<()> {
+        let diagnostics = match self.documents.get(uri) {
+            Some(analysis) => analysis
+                .diagnostics
+                .iter()
+                .map(|d| {
+                    let mut fields = vec![
+                        (String::from("range"), range(d.span, d.length)),
+                        (
+                            String::from("severity"),
+                            match d.severity {
+                                Severity::Error => 1i64,
+                                Severity::Warning => 2,
+                            }
+                            .into(),
+                        ),
+                        (String::from("source"), "omochi".into()),
+                        (String::from("message"), d.message.clone().into()),
+                    ];
+                    if let Some(code) = d.code {
+                        fields.push((String::from("code"), code.into()));
+                    }
+                    Json::Object(fields)
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        self.notify(
+            "textDocument/publishDiagnostics",
+            Json::object([("uri", uri.into()), ("diagnostics", diagnostics.into())]),
+        )
+    }
+
+    // `textDocument` と `position` を持つリクエストを処理する
+    fn with_position(
+        &self,
+        params: &Json,
+        handler: fn(&Self, &str, &Analysis, Span, &Json) -> Json,
+    ) -> Option<Json> {
+        let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+        let position = params.get("position")?;
+        // LSP の位置は0始まり (負の値や大きすぎる値は不正なパラメータとして扱う)
+        let one_based = |key: &str| {
+            usize::try_from(position.get(key)?.as_i64()?)
+                .ok()?
+                .checked_add(1)
+        };
+        let span = Span::new(one_based("line")?, one_based("character")?);
+        let analysis = self.documents.get(uri)?;
+        Some(handler(self, uri, analysis, span, params))
+    }
+
+    fn hover(&self, _uri: &str, analysis: &Analysis, position: Span, _params: &Json) -> Json {
+        let Some(symbol) = analysis.symbol_at(position) else {
+            return Json::Null;
+        };
+        let detail = &analysis.symbols[symbol].detail;
+        Json::object([(
+            "contents",
+            Json::object([
+                ("kind", "markdown".into()),
+                ("value", format!("```omochi\n{}\n```", detail).into()),
+            ]),
+        )])
+    }
+
+    fn definition(&self, uri: &str, analysis: &Analysis, position: Span, _params: &Json) -> Json {
+        match analysis.symbol_at(position) {
+            Some(symbol) => {
+                let symbol = &analysis.symbols[symbol];
+                location(uri, symbol.span, symbol.name.chars().count())
+            }
+            None => Json::Null,
+        }
+    }
+
+    fn references(&self, uri: &str, analysis: &Analysis, position: Span, params: &Json) -> Json {
+        let Some(index) = analysis.symbol_at(position) else {
+            return Json::Array(Vec::new());
+        };
+        let symbol = &analysis.symbols[index];
+        let length = symbol.name.chars().count();
+        let include_declaration = params
+            .get("context")
+            .and_then(|c| c.get("includeDeclaration"))
+            .and_then(Json::as_bool)
+            .unwrap_or(true);
+        let mut spans = Vec::new();
+        if include_declaration {
+            spans.push(symbol.span);
+        }
+        spans.extend(analysis.references_to(index));
+        spans
+            .into_iter()
+            .map(|span| location(uri, span, length))
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    fn completion(&self, _uri: &str, analysis: &Analysis, position: Span, _params: &Json) -> Json {
+        let mut items: Vec<Json> = analysis
+            .visible_at(position)
+            .into_iter()
+            .map(|symbol| {
+                let kind = match symbol.kind {
+                    SymbolKind::Function | SymbolKind::Extern => COMPLETION_FUNCTION,
+                    SymbolKind::Variable | SymbolKind::Parameter => COMPLETION_VARIABLE,
+                };
+                Json::object([
+                    ("label", symbol.name.as_str().into()),
+                    ("kind", kind.into()),
+                    ("detail", symbol.detail.as_str().into()),
+                ])
+            })
+            .collect();
+        items.extend(KEYWORDS.iter().map(|keyword| {
+            Json::object([
+                ("label", (*keyword).into()),
+                ("kind", COMPLETION_KEYWORD.into()),
+            ])
+        }));
+        items.into()
+    }
+
+    fn document_symbols(&self, params: &Json) -> Option<Json> {
+        let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+        let analysis = self.documents.get(uri)?;
+        let symbols: Vec<Json> = analysis
+            .symbols
+            .iter()
+            .filter(|symbol| symbol.kind == SymbolKind::Function)
+            .map(|symbol| {
+                let selection = range(symbol.span, symbol.name.chars().count());
+                // 名前から本体の `}` までを関数の範囲とする
+                let full = match symbol.body {
+                    Some((_, end)) => Json::object([
+                        ("start", position(symbol.span)),
+                        (
+                            "end",
+                            position(Span {
+                                column: end.column + 1,
+                                ..end
+                            }),
+                        ),
+                    ]),
+                    None => selection.clone(),
+                };
+                Json::object([
+                    ("name", symbol.name.as_str().into()),
+                    ("detail", symbol.detail.as_str().into()),
+                    ("kind", SYMBOL_FUNCTION.into()),
+                    ("range", full),
+                    ("selectionRange", selection),
+                ])
+            })
+            .collect();
+        Some(symbols.into())
+    }
+}
+
+// サーバが対応している機能
+fn capabilities() -> Json {
+    Json::object([
+        (
+            "capabilities",
+            Json::object([
+                ("textDocumentSync", 1i64.into()), // 変更のたびにドキュメント全体を受け取る
+                ("hoverProvider", true.into()),
+                ("definitionProvider", true.into()),
+                ("referencesProvider", true.into()),
+                ("documentSymbolProvider", true.into()),
+                ("completionProvider", Json::object([])),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object([
+                ("name", "omochi".into()),
+                ("version", env!("CARGO_PKG_VERSION").into()),
+            ]),
+        ),
+    ])
+}
+
+// 1始まりの Span を0始まりの LSP の位置にする
+fn position(span: Span) -> Json {
+    Json::object([
+        ("line", span.line.saturating_sub(1).into()),
+        ("character", span.column.saturating_sub(1).into()),
+    ])
+}
+
+fn range(span: Span, length: usize) -> Json {
+    let end = Span {
+        column: span.column + length,
+        ..span
+    };
+    Json::object([("start", position(span)), ("end", position(end))])
+}
+
+// `file://` の URI をファイルのパスにする (`%20` などはデコードする)
+// それ以外の URI (保存前のドキュメントなど) はそのままパスとして扱う
+fn uri_to_path(uri: &str) -> PathBuf {
+    let Some(path) = uri.strip_prefix("file://") else {
+        return PathBuf::from(uri);
+    };
+    let mut bytes = Vec::new();
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(decoded) if byte == b'%' => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn location(uri: &str, span: Span, length: usize) -> Json {
+    Json::object([("uri", uri.into()), ("range", range(span, length))])
+}
@@ -0,0 +1,468 @@
+use crate::ast::{Expr, ExternFunction, Function, Span, Statement, Token, Type};
+pub use crate::diagnostic::Severity;
+use crate::driver::{self, Backend, CompileOptions};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::types::TypeEnvironment;
+use std::collections::HashMap;
+use std::path::Path;
+
+// エディタに表示する診断
+pub struct Diagnostic {
+    pub span: Span,
+    pub length: usize, // 下線を引く文字数
+    pub severity: Severity,
+    pub message: String,
+    pub code: Option<&'static str>, // リント名
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SymbolKind {
+    Function,
+    Extern,
+    Variable,
+    Parameter,
+}
+
+// 名前の定義
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub span: Span,                 // 定義している名前の位置
+    pub detail: String,             // ホバーに表示する宣言 (`let x: int` など)
+    pub body: Option<(Span, Span)>, // 関数の `{` と `}` の位置
+    scope: usize,                   // 定義されたスコープ
+}
+
+// 名前の使用箇所
+pub struct Reference {
+    pub span: Span,
+    pub symbol: usize,
+}
+
+// `{` から `}` までのスコープ
+struct Scope {
+    start: Span,
+    end: Span,
+    parent: Option<usize>,
+    is_function: bool, // 関数の本体 (トップレベル以外の外側の変数は見えない)
+}
+
+// 1つのドキュメントの解析結果
+pub struct Analysis {
+    pub diagnostics: Vec<Diagnostic>,
+    pub symbols: Vec<Symbol>,
+    pub references: Vec<Reference>,
+    scopes: Vec<Scope>,
+}
+
+impl Analysis {
+    // コンパイラと同じ検査で診断を集め、名前の定義と使用箇所を集める
+    // `mod` で読み込むファイルは path からの相対パスで探す
+    pub fn new(source: &str, path: &Path) -> Analysis {
+        let mut analysis = Analysis {
+            diagnostics: Vec::new(),
+            symbols: Vec::new(),
+            references: Vec::new(),
+            scopes: vec![Scope {
+                start: Span::new(0, 0),
+                end: Span::new(usize::MAX, 0),
+                parent: None,
+                is_function: false,
+            }],
+        };
+        analysis.check(source, path);
+        // 字句解析か構文解析に失敗したドキュメントは診断だけを返す
+        let Ok((tokens, spans)) = Lexer::new(source).lex_with_spans() else {
+            return analysis;
+        };
+        let Ok(statements) = Parser::with_spans(tokens.clone(), spans.clone()).parse_statements()
+        else {
+            return analysis;
+        };
+
+        let mut indexer = Indexer {
+            analysis,
+            tokens: &tokens,
+            spans: &spans,
+            types: TypeEnvironment::new(),
+            names: vec![HashMap::new()],
+            functions: HashMap::new(),
+            scope: 0,
+        };
+        indexer.declare_functions(&statements);
+        indexer.statements(&statements);
+        let mut analysis = indexer.analysis;
+        for diagnostic in &mut analysis.diagnostics {
+            diagnostic.length = token_length(&tokens, &spans, diagnostic.span);
+        }
+        analysis
+    }
+
+    // コンパイラ (`mod` の読み込み・名前解決・`fn main`・型検査・リント・定数畳み込み) の診断のうち、
+    // このドキュメントのもの (位置のない診断は先頭に表示する)
+    fn check(&mut self, source: &str, path: &Path) {
+        let options = CompileOptions::new(path, Backend::Ast);
+        let diagnostics = match driver::compile(source, &options) {
+            Ok(artifact) => artifact.warnings,
+            Err(diagnostics) => diagnostics,
+        };
+        for diagnostic in diagnostics {
+            if diagnostic.path.as_deref().is_some_and(|p| p != path) {
+                continue;
+            }
+            self.diagnostics.push(Diagnostic {
+                span: diagnostic.span.unwrap_or(Span::new(1, 1)),
+                length: 1,
+                severity: diagnostic.severity,
+                message: diagnostic.message,
+                code: diagnostic.code,
+            });
+        }
+    }
+
+    // カーソル位置にある名前が指す定義
+    pub fn symbol_at(&self, position: Span) -> Option<usize> {
+        let covers = |span: Span, name: &str| {
+            span.line == position.line
+                && span.column <= position.column
+                && position.column < span.column + name.chars().count()
+        };
+        self.symbols
+            .iter()
+            .position(|symbol| covers(symbol.span, &symbol.name))
+            .or_else(|| {
+                self.references
+                    .iter()
+                    .find(|r| covers(r.span, &self.symbols[r.symbol].name))
+                    .map(|r| r.symbol)
+            })
+    }
+
+    // 定義を参照しているすべての位置
+    pub fn references_to(&self, symbol: usize) -> Vec<Span> {
+        self.references
+            .iter()
+            .filter(|r| r.symbol == symbol)
+            .map(|r| r.span)
+            .collect()
+    }
+
+    // 指定した位置で使える名前 (内側のスコープの定義を優先する)
+    pub fn visible_at(&self, position: Span) -> Vec<&Symbol> {
+        let innermost = (0..self.scopes.len())
+            .rev()
+            .find(|&i| {
+                let scope = &self.scopes[i];
+                before(scope.start, position) && before(position, scope.end)
+            })
+            .unwrap_or(0);
+
+        let mut visible: Vec<&Symbol> = Vec::new();
+        let mut crossed_function = false;
+        let mut scope = Some(innermost);
+        while let Some(current) = scope {
+            for symbol in self.symbols.iter().filter(|s| s.scope == current) {
+                let is_function = matches!(symbol.kind, SymbolKind::Function | SymbolKind::Extern);
+                let declared = is_function
+                    || symbol.kind == SymbolKind::Parameter
+                    || before(symbol.span, position);
+                if (is_function || !crossed_function || current == 0)
+                    && declared
+                    && !visible.iter().any(|v| v.name == symbol.name)
+                {
+                    visible.push(symbol);
+                }
+            }
+            crossed_function |= self.scopes[current].is_function;
+            scope = self.scopes[current].parent;
+        }
+        visible
+    }
+}
+
+// AST をたどって定義と使用箇所を集める
+struct Indexer<'a> {
+    analysis: Analysis,
+    tokens: &'a [Token],
+    spans: &'a [Span],
+    types: TypeEnvironment,
+    names: Vec<HashMap<String, usize>>, // 変数名から定義への対応 (最後が一番内側)
+    functions: HashMap<String, usize>,
+    scope: usize, // 現在のスコープ
+}
+
+impl Indexer<'_> {
+    fn define(&mut self, name: &str, kind: SymbolKind, span: Span, detail: String) -> usize {
+        self.analysis.symbols.push(Symbol {
+            name: name.to_string(),
+            kind,
+            span,
+            detail,
+            body: None,
+            scope: self.scope,
+        });
+        self.analysis.symbols.len() - 1
+    }
+
+    fn refer(&mut self, span: Span, symbol: Option<usize>) {
+        if let Some(symbol) = symbol {
+            self.analysis.references.push(Reference { span, symbol });
+        }
+    }
+
+    fn variable(&self, name: &str) -> Option<usize> {
+        self.names
+            .iter()
+            .rev()
+            .find_map(|names| names.get(name).copied())
+    }
+
+    // 関数は定義より前からも呼べるので先に登録する
+    fn declare_functions(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Function(func) => {
+                    self.types.record(statement);
+                    let symbol = self.define(
+                        &func.name,
+                        SymbolKind::Function,
+                        func.span,
+                        function_detail(func),
+                    );
+                    self.analysis.symbols[symbol].body = self.block_after(func.span);
+                    self.functions.insert(func.name.clone(), symbol);
+                    self.declare_functions(&func.body);
+                }
+                Statement::Extern(func) => {
+                    self.types.record(statement);
+                    let span = self.extern_span(&func.name).unwrap_or_default();
+                    let symbol =
+                        self.define(&func.name, SymbolKind::Extern, span, extern_detail(func));
+                    self.functions.insert(func.name.clone(), symbol);
+                }
+                Statement::Block(body) => self.declare_functions(body),
+                Statement::If(_, then_branch, else_branch, _) => {
+                    self.declare_functions(std::slice::from_ref(then_branch));
+                    if let Some(else_branch) = else_branch {
+                        self.declare_functions(std::slice::from_ref(else_branch));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(expr) | Statement::Print(expr) => self.expr(expr),
+            Statement::Declaration(name, expr, span) => {
+                self.expr(expr);
+                let ty = self.types.infer(expr);
+                let detail = match &ty {
+                    Some(ty) => format!("let {}: {}", name, ty),
+                    None => format!("let {}", name),
+                };
+                if let Some(ty) = ty {
+                    self.types.declare(name, ty);
+                }
+                let symbol = self.define(name, SymbolKind::Variable, *span, detail);
+                self.names.last_mut().unwrap().insert(name.clone(), symbol);
+            }
+            Statement::Assignment(name, expr, span) => {
+                self.expr(expr);
+                let symbol = self.variable(name);
+                self.refer(*span, symbol);
+            }
+            Statement::DerefAssignment(pointer, expr, _) => {
+                self.expr(pointer);
+                self.expr(expr);
+            }
+            Statement::Block(statements) => self.statements(statements),
+            Statement::If(condition, then_branch, else_branch, span) => {
+                self.expr(condition);
+                let then_block = self.block_after(*span);
+                self.branch(then_branch, then_block);
+                if let (Some(else_branch), Some((_, then_end))) = (else_branch, then_block) {
+                    let else_block = self.block_after(then_end);
+                    self.branch(else_branch, else_block);
+                }
+            }
+            Statement::Function(func) => self.function(func),
+            Statement::Mod(_) | Statement::Import(_) | Statement::Extern(_) => {}
+        }
+    }
+
+    fn branch(&mut self, statement: &Statement, block: Option<(Span, Span)>) {
+        self.enter(block, false);
+        self.statement(statement);
+        self.leave();
+    }
+
+    // 関数の本体からはトップレベルの変数だけが見える
+    fn function(&mut self, func: &Function) {
+        let globals = self.names[0].clone();
+        let outer_names = std::mem::replace(&mut self.names, vec![globals]);
+        self.enter(self.block_after(func.span), true);
+        for ((name, ty), &span) in func.params.iter().zip(&func.param_spans) {
+            self.types.declare(name, ty.clone());
+            let symbol = self.define(
+                name,
+                SymbolKind::Parameter,
+                span,
+                format!("{}: {}", name, ty),
+            );
+            self.names.last_mut().unwrap().insert(name.clone(), symbol);
+        }
+        self.statements(&func.body);
+        self.expr(&func.return_expr);
+        self.leave();
+        self.names = outer_names;
+    }
+
+    fn enter(&mut self, block: Option<(Span, Span)>, is_function: bool) {
+        let parent = &self.analysis.scopes[self.scope];
+        let (start, end) = block.unwrap_or((parent.start, parent.end));
+        self.analysis.scopes.push(Scope {
+            start,
+            end,
+            parent: Some(self.scope),
+            is_function,
+        });
+        self.scope = self.analysis.scopes.len() - 1;
+        self.names.push(HashMap::new());
+        self.types.push_scope();
+    }
+
+    fn leave(&mut self) {
+        self.scope = self.analysis.scopes[self.scope].parent.unwrap_or(0);
+        self.names.pop();
+        self.types.pop_scope();
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Integer(_) | Expr::Str(_) | Expr::AddressOf(_) => {}
+            Expr::Variable(name, span) => {
+                let symbol = self.variable(name);
+                self.refer(*span, symbol);
+            }
+            Expr::FunctionCall(name, args, span) => {
+                let symbol = self.functions.get(name).copied();
+                self.refer(*span, symbol);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            Expr::BinaryOp(left, _, right, _) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Assign(_, value) | Expr::Deref(value, _) | Expr::New(value) => self.expr(value),
+            Expr::If(condition, then_branch, else_branch) => {
+                self.expr(condition);
+                self.branch(then_branch, None);
+                if let Some(else_branch) = else_branch {
+                    self.branch(else_branch, None);
+                }
+            }
+        }
+    }
+
+    // 位置が `span` 以降の最初のトークン
+    fn token_index(&self, span: Span) -> Option<usize> {
+        self.spans.iter().position(|&s| !before(s, span))
+    }
+
+    // `span` の後にある最初の `{` と、対応する `}` の位置
+    fn block_after(&self, span: Span) -> Option<(Span, Span)> {
+        let start = self.token_index(span)?;
+        let open = start
+            + self.tokens[start..]
+                .iter()
+                .position(|t| *t == Token::LeftBrace)?;
+        let mut depth = 0;
+        for (i, token) in self.tokens.iter().enumerate().skip(open) {
+            match token {
+                Token::LeftBrace => depth += 1,
+                Token::RightBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some((self.spans[open], self.spans[i]));
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    // `extern fn name` の名前の位置
+    fn extern_span(&self, name: &str) -> Option<Span> {
+        self.tokens
+            .windows(3)
+            .enumerate()
+            .find_map(|(i, window)| match window {
+                [Token::Extern, Token::Fn, Token::Identifier(n)] if n == name => {
+                    Some(self.spans[i + 2])
+                }
+                _ => None,
+            })
+    }
+}
+
+fn function_detail(func: &Function) -> String {
+    let params: Vec<String> = func
+        .params
+        .iter()
+        .map(|(name, ty)| format!("{}: {}", name, ty))
+        .collect();
+    let visibility = if func.is_pub { "pub " } else { "" };
+    format!(
+        "{}fn {}({}) -> {}",
+        visibility,
+        func.name,
+        params.join(", "),
+        func.return_type
+    )
+}
+
+fn extern_detail(func: &ExternFunction) -> String {
+    let mut params: Vec<String> = func
+        .params
+        .iter()
+        .map(|(name, ty)| format!("{}: {}", name, ty))
+        .collect();
+    if func.variadic {
+        params.push(String::from("..."));
+    }
+    let return_type = match &func.return_type {
+        Type::Void => String::new(),
+        ty => format!(" -> {}", ty),
+    };
+    format!(
+        "extern fn {}({}){}",
+        func.name,
+        params.join(", "),
+        return_type
+    )
+}
+
+// `a` が `b` より前にあるか
+fn before(a: Span, b: Span) -> bool {
+    (a.line, a.column) < (b.line, b.column)
+}
+
+// 診断の位置から始まるトークンの長さ
+fn token_length(tokens: &[Token], spans: &[Span], span: Span) -> usize {
+    spans
+        .iter()
+        .position(|&s| s == span)
+        .map(|i| tokens[i].to_string().chars().count())
+        .filter(|&length| length > 0)
+        .unwrap_or(1)
+}
@@ -1,52 +1,300 @@
-mod ast;
-mod irgenerator;
-mod lexer;
-mod parser;
+use omochi::ast::Statement;
+use omochi::bytecode::{BytecodeError, Program, Vm};
+use omochi::cli::{self, Command, Emit, Options};
+use omochi::interpreter::{Interpreter, RuntimeError, Value};
+use omochi::{dump, format, lsp, repl, Backend, Diagnostic, Output};
+#[cfg(feature = "llvm")]
+use omochi::{jit, link, optimize, target};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
 
-use crate::irgenerator::IRGenerator;
-use inkwell::context::Context;
-use lexer::Lexer;
-use parser::Parser;
-use std::fs;
+// インタプリタは omochi の関数呼び出しごとに Rust のスタックを使うので、呼び出しの深さが
+// 上限 (panic::MAX_FRAMES) に達してスタックオーバーフローのパニックになるまであふれない大きさにする
+const INTERPRETER_STACK_SIZE: usize = 1 << 30;
 
 fn main() {
-    let filename = "program.txt";
-    let input = fs::read_to_string(filename).expect("Failed to read file");
-
-    let mut lexer = Lexer::new(&input);
-    let tokens = match lexer.lex() {
-        Ok(t) => t,
+    let options = match cli::parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        // 不正な呼び出しはスクリプトや CI で検出できるように失敗として終了する
         Err(e) => {
-            eprintln!("Lexer error: {:?}", e);
-            return;
+            eprintln!("Error: {}", e);
+            process::exit(2);
+        }
+    };
+
+    if options.command == Command::Repl {
+        if let Err(e) = with_interpreter_stack(|| repl::Repl::new().run()) {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if options.command == Command::Lsp {
+        match lsp::run(io::stdin().lock(), io::stdout().lock()) {
+            Ok(code) => process::exit(code),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if options.command == Command::Fmt {
+        format_file(&options);
+        return;
+    }
+
+    if let Some(emit) = options.emit {
+        emit_frontend(&options, emit);
+        return;
+    }
+
+    // コンパイル済みのバイトコードはそのまま実行・表示する
+    if options.input.extension().is_some_and(|ext| ext == "omoc") {
+        let program = match load_bytecode(&options) {
+            Ok(program) => program,
+            Err(e) => {
+                eprintln!("Error: {}: {}", options.input.display(), e);
+                process::exit(1);
+            }
+        };
+        match options.command {
+            Command::Disasm => print!("{}", program),
+            _ => run_bytecode(&options, &program),
         }
+        return;
+    }
+
+    // エントリファイル (`mod` 宣言はこのファイルからの相対パスで解決される)
+    let source = fs::read_to_string(&options.input).unwrap_or_else(|e| {
+        eprintln!("Error: {}: {}", options.input.display(), e);
+        process::exit(1);
+    });
+    let backend = match options.command {
+        Command::Run if options.vm => Backend::Bytecode,
+        Command::Disasm => Backend::Bytecode,
+        Command::Build if options.bytecode => Backend::Bytecode,
+        _ => Backend::Ast,
     };
+    let artifact = omochi::compile(&source, &options.compile_options(backend)).unwrap_or_else(|diagnostics| {
+        report(&diagnostics);
+        process::exit(1);
+    });
+    report(&artifact.warnings);
 
-    let mut parser = Parser::new(tokens);
-    let statements = match parser.parse_statements() {
-        Ok(statements) => statements,
+    let files = artifact.files;
+    match (options.command, artifact.output) {
+        (Command::Run, Output::Ast(statements)) if options.interpret => interpret(&options, &statements, &files),
+        (Command::Run, Output::Ast(statements)) => run_jit(&options, statements, &files),
+        (Command::Run, Output::Bytecode(program)) => run_bytecode(&options, &program),
+        (Command::Disasm, Output::Bytecode(program)) => print!("{}", program),
+        (Command::Build, Output::Bytecode(program)) => {
+            let path = options.output.with_extension("omoc");
+            // BufWriter は drop 時の書き込みエラーを捨てるので明示的に flush する
+            let result = File::create(&path).and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                program.write_to(&mut writer)?;
+                writer.flush()
+            });
+            if let Err(e) = result {
+                eprintln!("Error: {}: {}", path.display(), e);
+                process::exit(1);
+            }
+        }
+        (Command::Build, Output::Ast(statements)) => build(&options, statements, &files),
+        _ => unreachable!("unexpected compiler output"),
+    }
+}
+
+// 診断を標準エラーに出力する
+fn report(diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+}
+
+// ファイルの診断を表示して終了する
+fn fail(path: &Path, mut diagnostic: Diagnostic) -> ! {
+    diagnostic.path = Some(path.to_path_buf());
+    report(&[diagnostic]);
+    process::exit(1);
+}
+
+// ソースファイルを整形して書き戻す (`--check` では整形済みでなければ失敗する)
+fn format_file(options: &Options) {
+    let path = &options.input;
+    let formatted = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|source| Ok((format::format_source(&source).map_err(|e| e.to_string())?, source)));
+    let (formatted, source) = match formatted {
+        Ok(result) => result,
         Err(e) => {
-            eprintln!("Parser error: {:?}", e);
-            return;
+            eprintln!("Error: {}: {}", path.display(), e);
+            process::exit(1);
         }
     };
-    // LLVMコンテキストの作成とIRジェネレータのインスタンス化
-    let context = Context::create();
-    let mut ir_generator = IRGenerator::new(&context);
-    let i32_type = context.i32_type();
-    let fn_type = i32_type.fn_type(&[], false);
-    let function = ir_generator.module.add_function("main", fn_type, None);
-
-    // エントリブロックの初期化
-    ir_generator.initialize_entry_block(&function);
-
-    // 解析されたプログラム (AST) から LLVM IR を生成
-    for statement in statements {
-        ir_generator.generate_ir_for_statement(&statement, &function).expect("Failed to generate IR");
-    }
-    // 関数の戻り値を設定
-    let return_value = context.i32_type().const_int(0, false);
-    ir_generator.build_return(return_value);
-    // IRをファイルに出力
-    ir_generator.module.print_to_file("output.ll").unwrap();
+    if formatted == source {
+        return;
+    }
+    if options.check {
+        // 最初に異なる行を示す
+        let line = source.lines().zip(formatted.lines()).take_while(|(a, b)| a == b).count() + 1;
+        eprintln!("{}:{}: not formatted; run `omochi fmt {}`", path.display(), line, path.display());
+        process::exit(1);
+    }
+    if let Err(e) = fs::write(path, formatted) {
+        eprintln!("Error: {}: {}", path.display(), e);
+        process::exit(1);
+    }
+}
+
+// エントリファイルのトークン列や構文木を標準出力に書き出す (`mod` で読み込むファイルは含まない)
+fn emit_frontend(options: &Options, emit: Emit) {
+    let path = &options.input;
+    let source = fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("Error: {}: {}", path.display(), e);
+        process::exit(1);
+    });
+    if emit == Emit::TokensJson {
+        let (tokens, spans) = omochi::tokenize(&source).unwrap_or_else(|e| fail(path, e));
+        print!("{}", dump::tokens_json(&tokens, &spans));
+        return;
+    }
+    let statements = omochi::parse(&source).unwrap_or_else(|e| fail(path, e));
+    match emit {
+        Emit::AstJson => print!("{}", dump::ast_json(&statements)),
+        _ => print!("{}", dump::ast_sexp(&statements)),
+    }
+}
+
+fn load_bytecode(options: &Options) -> Result<Program, BytecodeError> {
+    let file = File::open(&options.input)?;
+    Program::read_from(&mut BufReader::new(file))
+}
+
+// バイトコードを VM で実行し、`fn main` の戻り値を終了コードにする
+fn run_bytecode(options: &Options, program: &Program) {
+    let mut vm = Vm::new(program);
+    vm.set_overflow_checks(options.overflow_checks);
+    match vm.run() {
+        Ok(Value::Int(code)) => process::exit(code),
+        Ok(_) => {}
+        Err(e) => runtime_error(options, &program.files, e),
+    }
+}
+
+// AST を直接実行し、`fn main` の戻り値を終了コードにする
+fn interpret(options: &Options, statements: &[Statement], files: &[PathBuf]) {
+    let result = with_interpreter_stack(|| {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_overflow_checks(options.overflow_checks);
+        interpreter.run(statements)
+    });
+    match result {
+        Ok(code) => process::exit(code),
+        Err(e) => runtime_error(options, files, e),
+    }
+}
+
+// インタプリタを大きなスタックのスレッドで実行する
+fn with_interpreter_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(INTERPRETER_STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .expect("Failed to start the interpreter thread")
+            .join()
+            .unwrap_or_else(|e| std::panic::resume_unwind(e))
+    })
+}
+
+// 実行時エラーを `file:line:col: message` の形で表示し、種類ごとの終了コードで終了する
+// ファイルはエラーの位置が指すもの (子モジュールの関数のエラーはそのファイル)
+fn runtime_error(options: &Options, files: &[impl AsRef<Path>], e: RuntimeError) -> ! {
+    let path = e
+        .span
+        .and_then(|span| files.get(span.file))
+        .map_or(options.input.as_path(), AsRef::as_ref);
+    eprintln!("{}:{}", path.display(), e);
+    process::exit(e.exit_code());
+}
+
+#[cfg(not(feature = "llvm"))]
+fn build(_options: &Options, _statements: Vec<Statement>, _files: &[PathBuf]) {
+    eprintln!("Error: omochi was built without LLVM support; use `omochi run --interp`");
+    process::exit(1);
+}
+
+#[cfg(not(feature = "llvm"))]
+fn run_jit(_options: &Options, _statements: Vec<Statement>, _files: &[PathBuf]) {
+    eprintln!("Error: omochi was built without LLVM support; use `omochi run --interp`");
+    process::exit(1);
+}
+
+// JIT コンパイルして実行し、main の戻り値を終了コードにする
+#[cfg(feature = "llvm")]
+fn run_jit(options: &Options, statements: Vec<Statement>, files: &[PathBuf]) {
+    if !options.link_inputs.is_empty() || !options.libraries.is_empty() {
+        eprintln!("Error: `run` cannot link extra inputs or libraries; use `build` instead");
+        process::exit(1);
+    }
+    if options.target.is_some() {
+        eprintln!("Error: `run` executes on this machine; `--target` is only supported by `build`");
+        process::exit(1);
+    }
+    let context = inkwell::context::Context::create();
+    let ir_generator =
+        omochi::generate_ir(&context, statements, files, &options.compile_options(Backend::Ast), None);
+    match jit::run_main(&ir_generator.module, optimize::codegen_level(options.opt_level)) {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(feature = "llvm")]
+fn build(options: &Options, statements: Vec<Statement>, files: &[PathBuf]) {
+    if let Err(e) = build_executable(options, statements, files) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+// ネイティブコードを生成して実行ファイルを作成する (中間ファイルは一時ディレクトリに置く)
+// `--target` を指定した場合は .s と .o を出力先に書き出し、リンクは利用者に任せる
+#[cfg(feature = "llvm")]
+fn build_executable(options: &Options, statements: Vec<Statement>, files: &[PathBuf]) -> Result<(), String> {
+    let machine = target::create_target_machine(options).map_err(|e| format!("Codegen error: {}", e))?;
+    let context = inkwell::context::Context::create();
+    let ir_generator = omochi::generate_ir(
+        &context,
+        statements,
+        files,
+        &options.compile_options(Backend::Ast),
+        Some(&machine),
+    );
+
+    if options.target.is_some() {
+        let dir = options.output.parent().unwrap_or(std::path::Path::new(""));
+        let name = options.output.file_stem().unwrap_or_default().to_string_lossy();
+        let object_path = target::write_native_files(&machine, &ir_generator.module, dir, &name)
+            .map_err(|e| format!("Codegen error: {}", e))?;
+        eprintln!("Wrote {}; link it with a toolchain for the target", object_path.display());
+        return Ok(());
+    }
+
+    // アセンブリとオブジェクトファイルを書き出す
+    let temp_dir = link::TempDir::new(options.keep_temps).map_err(|e| format!("Error: {}", e))?;
+    let object_path = target::write_native_files(&machine, &ir_generator.module, temp_dir.path(), "output")
+        .map_err(|e| format!("Codegen error: {}", e))?;
+
+    // 実行ファイルを作成
+    link::link_executable(&object_path, options).map_err(|e| format!("Link error: {}", e))
 }
@@ -0,0 +1,121 @@
+use crate::cli::OptLevel;
+use inkwell::module::Module;
+use inkwell::passes::PassManager;
+use inkwell::values::FunctionValue;
+use inkwell::OptimizationLevel;
+
+type FunctionPass = (&'static str, fn(&PassManager<FunctionValue>));
+type ModulePass = (&'static str, fn(&PassManager<Module>));
+
+// パイプラインの段階 (関数ごとのパスとモジュール全体のパス)
+enum Stage {
+    Function(Vec<FunctionPass>),
+    Module(Vec<ModulePass>),
+}
+
+// 最適化レベルごとのパスの並び
+fn pipeline(level: OptLevel) -> Vec<Stage> {
+    // create_entry_block_alloca で作った変数をレジスタに昇格してから整理する
+    let mut cleanup: Vec<FunctionPass> = vec![
+        ("mem2reg", |pm| pm.add_promote_memory_to_register_pass()),
+        ("instcombine", |pm| pm.add_instruction_combining_pass()),
+        ("simplifycfg", |pm| pm.add_cfg_simplification_pass()),
+    ];
+    let scalar: Vec<FunctionPass> = vec![
+        ("reassociate", |pm| pm.add_reassociate_pass()),
+        ("sccp", |pm| pm.add_sccp_pass()),
+        ("gvn", |pm| pm.add_gvn_pass()),
+        ("dse", |pm| pm.add_dead_store_elimination_pass()),
+        ("adce", |pm| pm.add_aggressive_dce_pass()),
+        ("instcombine", |pm| pm.add_instruction_combining_pass()),
+        ("simplifycfg", |pm| pm.add_cfg_simplification_pass()),
+    ];
+
+    match level {
+        OptLevel::O0 => Vec::new(),
+        OptLevel::O1 => vec![Stage::Function(cleanup)],
+        // サイズ優先では関数のインライン展開をしない
+        OptLevel::Os => {
+            cleanup.extend(scalar);
+            vec![
+                Stage::Function(cleanup),
+                Stage::Module(vec![("globaldce", |pm| pm.add_global_dce_pass())]),
+            ]
+        }
+        OptLevel::O2 | OptLevel::O3 => {
+            let mut after_inlining = scalar;
+            if level == OptLevel::O3 {
+                after_inlining.extend::<Vec<FunctionPass>>(vec![
+                    ("licm", |pm| pm.add_licm_pass()),
+                    ("tailcallelim", |pm| pm.add_tail_call_elimination_pass()),
+                ]);
+            }
+            vec![
+                Stage::Function(cleanup),
+                Stage::Module(vec![
+                    ("inline", |pm| pm.add_function_inlining_pass()),
+                    ("globaldce", |pm| pm.add_global_dce_pass()),
+                ]),
+                Stage::Function(after_inlining),
+            ]
+        }
+    }
+}
+
+// バックエンドや JIT に渡す最適化レベル
+pub fn codegen_level(level: OptLevel) -> OptimizationLevel {
+    match level {
+        OptLevel::O0 => OptimizationLevel::None,
+        OptLevel::O1 => OptimizationLevel::Less,
+        OptLevel::O2 | OptLevel::Os => OptimizationLevel::Default,
+        OptLevel::O3 => OptimizationLevel::Aggressive,
+    }
+}
+
+// モジュールを最適化する
+// print_after_all が有効なら、パスを1つずつ実行して毎回 IR を標準エラーに表示する
+pub fn optimize_module(module: &Module, level: OptLevel, print_after_all: bool) {
+    for stage in pipeline(level) {
+        match stage {
+            Stage::Function(passes) if print_after_all => {
+                for (name, add) in passes {
+                    run_function_passes(module, &[(name, add)]);
+                    dump(module, name);
+                }
+            }
+            Stage::Function(passes) => run_function_passes(module, &passes),
+            Stage::Module(passes) if print_after_all => {
+                for (name, add) in passes {
+                    run_module_passes(module, &[(name, add)]);
+                    dump(module, name);
+                }
+            }
+            Stage::Module(passes) => run_module_passes(module, &passes),
+        }
+    }
+}
+
+fn run_function_passes(module: &Module, passes: &[FunctionPass]) {
+    let manager = PassManager::create(module);
+    for (_, add) in passes {
+        add(&manager);
+    }
+    manager.initialize();
+    for function in module.get_functions() {
+        manager.run_on(&function);
+    }
+    manager.finalize();
+}
+
+fn run_module_passes(module: &Module, passes: &[ModulePass]) {
+    let manager = PassManager::create(());
+    for (_, add) in passes {
+        add(&manager);
+    }
+    manager.run_on(module);
+}
+
+fn dump(module: &Module, pass: &str) {
+    eprintln!("*** IR Dump After {} ***", pass);
+    eprintln!("{}", module.print_to_string());
+}
@@ -0,0 +1,25 @@
+// 実行時エラー (パニック) の種類
+// どのバックエンドでも `file:line:col: message` を標準エラーに出力し、種類ごとの終了コードで終了する
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PanicKind {
+    Explicit,       // `panic("message")`
+    Assertion,      // `assert(condition)` の失敗
+    Overflow,       // 整数演算のオーバーフロー
+    DivisionByZero, // 0 による割り算
+    StackOverflow,  // 関数呼び出しが MAX_FRAMES を超えた
+}
+
+// 関数呼び出しの深さの上限 (トップレベルの文の分を含む)。どのバックエンドでも同じ深さでパニックする
+pub const MAX_FRAMES: usize = 10_000;
+
+impl PanicKind {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            PanicKind::Explicit => 101,
+            PanicKind::Assertion => 102,
+            PanicKind::Overflow => 103,
+            PanicKind::DivisionByZero => 104,
+            PanicKind::StackOverflow => 105,
+        }
+    }
+}
@@ -1,4 +1,5 @@
-use crate::ast::{Expr, Function, Operator, Statement, Token, Type};
+use crate::ast::{ExternFunction, Expr, Function, Operator, Span, Statement, Token, Type};
+use std::fmt;
 
 // 構文解析器のエラーを表す列挙型
 #[derive(Debug)]
@@ -6,32 +7,78 @@ pub enum ParserError {
     UnexpectedToken { expected: String, found: String },
     UnexpectedEOF,
     InvalidSyntax,
+    VoidType(Span), // 引数などに書かれた `void` の位置
 }
 
+impl ParserError {
+    // エラー自身が位置を持つ場合はその位置 (それ以外は最後に読んだトークンの位置で報告する)
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ParserError::VoidType(span) => Some(*span),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ParserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParserError::UnexpectedToken { expected, found } => {
+                write!(f, "Expected {}, found {}", expected, found)
+            }
+            ParserError::UnexpectedEOF => write!(f, "Unexpected end of input"),
+            ParserError::InvalidSyntax => write!(f, "Invalid syntax"),
+            ParserError::VoidType(_) => {
+                write!(f, "`void` is only allowed as a return type or behind `*`")
+            }
+        }
+    }
+}
+
+// 引数の名前と型、各引数の名前の位置
+type Parameters = (Vec<(String, Type)>, Vec<Span>);
+
 // 構文解析器本体の構造体
 pub struct Parser {
     tokens: Vec<Token>, // 解析するトークンの列
+    spans: Vec<Span>,   // 各トークンのソース上の位置
     current: usize,     // 現在解析中のトークンの位置
 }
 
 impl Parser {
     // 新しい構文解析器インスタンスを作成
     pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, current: 0 }
+        let spans = vec![Span::default(); tokens.len()];
+        Parser::with_spans(tokens, spans)
+    }
+    // トークンの位置情報付きで構文解析器を作成
+    pub fn with_spans(tokens: Vec<Token>, spans: Vec<Span>) -> Self {
+        Parser {
+            tokens,
+            spans,
+            current: 0,
+        }
+    }
+    // 現在のトークンの位置
+    fn current_span(&self) -> Span {
+        self.spans.get(self.current).copied().unwrap_or_default()
+    }
+    // 構文エラーを報告する位置 (最後に読んだトークン)
+    pub fn error_span(&self) -> Span {
+        self.previous_span()
+    }
+    // 直前に消費したトークンの位置
+    fn previous_span(&self) -> Span {
+        self.spans.get(self.current.wrapping_sub(1)).copied().unwrap_or_default()
     }
     // カーソルを進める補助関数
     fn advance(&mut self) {
         self.consume();
     }
 
-    // 直前のトークンを取得する補助関数
-    fn previous(&self) -> &Token {
-        &self.tokens[self.current - 1]
-    }
     // トークン列から次のトークンを取得し、カーソルを進める
     fn consume(&mut self) -> Option<Token> {
         let token = self.tokens.get(self.current)?.clone();
-        println!("Consuming token: {:?}", token);
         self.current += 1;
         Some(token)
     }
@@ -52,11 +99,6 @@ impl Parser {
     }
     // 指定したトークンが現在のトークンであれば、それを消費して true を返す
     fn match_token(&mut self, token: Token) -> bool {
-        println!(
-            "Checking token: {:?}, Current token: {:?}",
-            token,
-            self.peek()
-        );
         if let Some(current_token) = self.peek() {
             match (&token, current_token) {
                 (Token::Else, Token::Identifier(name)) if name == "else" => {
@@ -67,14 +109,7 @@ impl Parser {
                     self.consume();
                     true
                 }
-                _ => {
-                    println!(
-                        "Token not matched: {:?}, Current token: {:?}",
-                        token,
-                        self.peek()
-                    );
-                    false
-                }
+                _ => false,
             }
         } else {
             false
@@ -86,8 +121,8 @@ impl Parser {
         let token = self.consume().ok_or(ParserError::UnexpectedEOF)?;
         if token != expected {
             return Err(ParserError::UnexpectedToken {
-                expected: format!("{:?}", expected),
-                found: format!("{:?}", token),
+                expected: describe(Some(&expected)),
+                found: describe(Some(&token)),
             });
         }
         Ok(())
@@ -105,8 +140,7 @@ impl Parser {
         while let Some(op) = self.next_operator()? {
             match op {
                 Operator::Equals => {
-                    if let Expr::Variable(name) = expr {
-                        println!("Parsing assignment, current token: {:?}", self.peek());
+                    if let Expr::Variable(name, _) = expr {
                         self.consume(); // '=' トークンを消費する
                         let rhs = self.parse_expression()?; // 右辺の式を解析
                         expr = Expr::Assign(name, Box::new(rhs));
@@ -115,8 +149,9 @@ impl Parser {
                     }
                 }
                 _ => {
+                    let span = self.previous_span(); // 演算子の位置
                     let rhs = self.parse_primary()?;
-                    expr = Expr::BinaryOp(Box::new(expr), op, Box::new(rhs));
+                    expr = Expr::BinaryOp(Box::new(expr), op, Box::new(rhs), span);
                 }
             }
         }
@@ -131,29 +166,87 @@ impl Parser {
                 self.consume();
                 Ok(Expr::Integer(value))
             }
+            Some(Token::Str(value)) => {
+                self.consume();
+                Ok(Expr::Str(value))
+            }
+            // `*p` 参照外し
+            Some(Token::Asterisk) => {
+                let span = self.current_span();
+                self.consume();
+                let pointer = self.parse_primary()?;
+                Ok(Expr::Deref(Box::new(pointer), span))
+            }
+            // `&x` アドレス取得 (変数のみ)
+            Some(Token::Ampersand) => {
+                self.consume();
+                let name = self.parse_identifier()?;
+                Ok(Expr::AddressOf(name))
+            }
+            // `new(value)` ヒープ確保
+            Some(Token::New) => {
+                self.consume();
+                self.expect_token(Token::LeftParen)?;
+                let value = self.parse_expression()?;
+                self.expect_token(Token::RightParen)?;
+                Ok(Expr::New(Box::new(value)))
+            }
             Some(Token::LeftParen) => {
                 self.consume(); // 左括弧を消費
                 let expr = self.parse_expression()?; // 括弧内の式を解析
                 self.expect_token(Token::RightParen)?; // 対応する右括弧を期待
                 Ok(expr)
             }
-            Some(Token::Identifier(ref name)) if self.peek_next() == Some(&Token::LeftParen) => {
-                self.consume(); // 関数名を消費
-                self.consume(); // 左括弧を消費
+            Some(Token::Identifier(_))
+                if matches!(
+                    self.peek_next(),
+                    Some(Token::LeftParen) | Some(Token::DoubleColon)
+                ) =>
+            {
+                // `math::square` のような修飾パスは `::` で連結した名前として保持する
+                let span = self.current_span();
+                let name = self.parse_path()?.join("::");
+                self.expect_token(Token::LeftParen)?; // 左括弧を期待
+                let args = self.parse_arguments()?;
                 self.expect_token(Token::RightParen)?; // 対応する右括弧を期待
-                Ok(Expr::FunctionCall(name.clone(), vec![]))
+                Ok(Expr::FunctionCall(name, args, span))
             }
             Some(Token::Identifier(name)) => {
+                let span = self.current_span();
                 self.consume();
-                Ok(Expr::Variable(name))
+                Ok(Expr::Variable(name, span))
             }
             _ => Err(ParserError::UnexpectedToken {
-                expected: String::from("Integer, LeftParen, Identifier, or FunctionCall"),
-                found: format!("{:?}", self.peek()),
+                expected: String::from("an expression"),
+                found: describe(self.peek()),
             }),
         }
     }
 
+    // 関数呼び出しの引数リストの解析
+    fn parse_arguments(&mut self) -> Result<Vec<Expr>, ParserError> {
+        let mut args = Vec::new();
+        if self.check(&Token::RightParen) {
+            return Ok(args);
+        }
+        loop {
+            args.push(self.parse_expression()?);
+            if !self.match_token(Token::Comma) {
+                break;
+            }
+        }
+        Ok(args)
+    }
+
+    // `a::b::c` 形式のパスの解析
+    fn parse_path(&mut self) -> Result<Vec<String>, ParserError> {
+        let mut segments = vec![self.parse_identifier()?];
+        while self.match_token(Token::DoubleColon) {
+            segments.push(self.parse_identifier()?);
+        }
+        Ok(segments)
+    }
+
     // 次の演算子を取得
     fn next_operator(&mut self) -> Result<Option<Operator>, ParserError> {
         let operator = match self.tokens.get(self.current) {
@@ -177,6 +270,10 @@ impl Parser {
                 self.consume();
                 Some(Operator::Multiply)
             }
+            Some(Token::Slash) => {
+                self.consume();
+                Some(Operator::Divide)
+            }
             _ => None,
         };
         Ok(operator)
@@ -185,12 +282,13 @@ impl Parser {
     // 変数宣言の解析
     fn parse_declaration(&mut self) -> Result<Statement, ParserError> {
         self.expect_token(Token::Let)?;
+        let span = self.current_span();
         let name = match self.consume() {
             Some(Token::Identifier(name)) => name,
             Some(found) => {
                 return Err(ParserError::UnexpectedToken {
-                    expected: format!("{:?}", Token::Identifier("".to_string())),
-                    found: format!("{:?}", found),
+                    expected: String::from("a variable name"),
+                    found: describe(Some(&found)),
                 })
             }
             None => return Err(ParserError::UnexpectedEOF),
@@ -200,16 +298,17 @@ impl Parser {
         } else {
             Expr::Integer(0)
         };
-        Ok(Statement::Declaration(name, expr))
+        Ok(Statement::Declaration(name, expr, span))
     }
 
     // 関数定義の解析
     // 関数定義の解析
     fn parse_function(&mut self) -> Result<Function, ParserError> {
         self.expect_token(Token::Fn)?;
+        let span = self.current_span();
         let name = self.parse_identifier()?;
         self.expect_token(Token::LeftParen)?;
-        let params = self.parse_parameters()?;
+        let (params, param_spans) = self.parse_parameters()?;
         self.expect_token(Token::RightParen)?;
         self.expect_token(Token::Arrow)?;
         let return_type = self.parse_type()?;
@@ -218,12 +317,52 @@ impl Parser {
         self.expect_token(Token::RightBrace)?;
         Ok(Function {
             name,
+            span,
+            is_pub: false,
             params,
+            param_spans,
             return_type,
             body,
             return_expr,
         })
     }
+    // `extern fn name(a: int, ...) -> int;` の解析
+    // 戻り値の型を省略した場合は void になる
+    fn parse_extern(&mut self) -> Result<ExternFunction, ParserError> {
+        self.expect_token(Token::Extern)?;
+        self.expect_token(Token::Fn)?;
+        let name = self.parse_identifier()?;
+        self.expect_token(Token::LeftParen)?;
+        let mut params = Vec::new();
+        let mut variadic = false;
+        while !self.check(&Token::RightParen) {
+            if self.match_token(Token::Ellipsis) {
+                // `...` は最後の引数でなければならない
+                variadic = true;
+                break;
+            }
+            let param_name = self.parse_identifier()?;
+            self.expect_token(Token::Colon)?;
+            params.push((param_name, self.parse_value_type()?));
+            if !self.match_token(Token::Comma) {
+                break;
+            }
+        }
+        self.expect_token(Token::RightParen)?;
+        let return_type = if self.match_token(Token::Arrow) {
+            self.parse_type()?
+        } else {
+            Type::Void
+        };
+        self.expect_token(Token::Semicolon)?;
+        Ok(ExternFunction {
+            name,
+            is_pub: false,
+            params,
+            variadic,
+            return_type,
+        })
+    }
     // print文の解析
     fn parse_print_statement(&mut self) -> Result<Statement, ParserError> {
         self.expect_token(Token::Print)?;
@@ -239,11 +378,11 @@ impl Parser {
             self.consume(); // Equals トークンを消費
             let right = self.parse_expression()?; // 右辺の式を解析
             self.expect_token(Token::Semicolon)?; // 代入文の後にセミコロンを期待
-            if let Expr::Variable(name) = left {
-                return Ok(Statement::Assignment(name, right));
-            } else {
-                return Err(ParserError::InvalidSyntax);
-            }
+            return match left {
+                Expr::Variable(name, span) => Ok(Statement::Assignment(name, right, span)),
+                Expr::Deref(pointer, span) => Ok(Statement::DerefAssignment(*pointer, right, span)),
+                _ => Err(ParserError::InvalidSyntax),
+            };
         }
         // Equals トークンがない場合は式として処理
         self.expect_token(Token::Semicolon)?; // 式の後にセミコロンを期待
@@ -258,25 +397,28 @@ impl Parser {
         }
     }
 
-    // パラメータリストの解析
-    fn parse_parameters(&mut self) -> Result<Vec<(String, Type)>, ParserError> {
+    // パラメータリストの解析 (各引数の名前の位置も返す)
+    fn parse_parameters(&mut self) -> Result<Parameters, ParserError> {
         let mut params = Vec::new();
+        let mut spans = Vec::new();
         // パラメータがない場合すぐに終了
         if let Some(Token::RightParen) = self.peek() {
-            return Ok(params);
+            return Ok((params, spans));
         }
 
         loop {
+            let span = self.current_span();
             match self.consume() {
                 Some(Token::Identifier(name)) => {
                     self.expect_token(Token::Colon)?;
-                    let param_type = self.parse_type()?;
+                    let param_type = self.parse_value_type()?;
                     params.push((name, param_type));
+                    spans.push(span);
                 }
                 Some(found) => {
                     return Err(ParserError::UnexpectedToken {
-                        expected: String::from("Identifier"),
-                        found: format!("{:?}", found),
+                        expected: String::from("a parameter name"),
+                        found: describe(Some(&found)),
                     })
                 }
                 None => return Err(ParserError::UnexpectedEOF),
@@ -289,31 +431,45 @@ impl Parser {
                 }
                 Some(found) => {
                     return Err(ParserError::UnexpectedToken {
-                        expected: String::from("RightParen or Comma"),
-                        found: format!("{:?}", found),
+                        expected: String::from("`)` or `,`"),
+                        found: describe(Some(found)),
                     })
                 }
                 None => return Err(ParserError::UnexpectedEOF),
             }
         }
 
-        Ok(params)
+        Ok((params, spans))
+    }
+
+    // 値を持つ型の解析 (`void` は戻り値の型と `*void` にしか書けない)
+    fn parse_value_type(&mut self) -> Result<Type, ParserError> {
+        let span = self.current_span();
+        match self.parse_type()? {
+            Type::Void => Err(ParserError::VoidType(span)),
+            ty => Ok(ty),
+        }
     }
 
     // 型の解析
     fn parse_type(&mut self) -> Result<Type, ParserError> {
         match self.consume() {
+            Some(Token::Asterisk) => Ok(Type::Pointer(Box::new(self.parse_type()?))),
+            Some(Token::Ampersand) => Ok(Type::Reference(Box::new(self.parse_value_type()?))),
             Some(Token::Identifier(type_name)) => match type_name.as_str() {
                 "int" => Ok(Type::Int),
+                "str" => Ok(Type::Str),
+                "string" => Ok(Type::String),
+                "void" => Ok(Type::Void),
                 // 他の型に対してもここで処理
                 _ => Err(ParserError::UnexpectedToken {
-                    expected: String::from("int (or other type)"),
-                    found: type_name,
+                    expected: String::from("a type"),
+                    found: format!("`{}`", type_name),
                 }),
             },
             Some(found) => Err(ParserError::UnexpectedToken {
-                expected: String::from("Identifier"),
-                found: format!("{:?}", found),
+                expected: String::from("a type"),
+                found: describe(Some(&found)),
             }),
             None => Err(ParserError::UnexpectedEOF),
         }
@@ -346,6 +502,7 @@ impl Parser {
     }
     // if文の解析
     fn parse_if_statement(&mut self) -> Result<Statement, ParserError> {
+        let span = self.current_span();
         self.expect_token(Token::If)?;
         let condition = self.parse_expression()?;
         self.expect_token(Token::LeftBrace)?;
@@ -359,7 +516,6 @@ impl Parser {
             self.expect_token(Token::RightBrace)?;
             Some(Box::new(Statement::Block(else_statements)))
         } else {
-            println!("No else branch, current token: {:?}", self.peek());
             None
         };
     
@@ -367,11 +523,11 @@ impl Parser {
             Box::new(condition),
             Box::new(Statement::Block(then_branch)),
             else_branch,
+            span,
         ))
     }
     // 文の解析
     fn parse_statement(&mut self) -> Result<Statement, ParserError> {
-        println!("Parsing statement, current token: {:?}", self.peek());
         let result = match self.peek() {
             Some(Token::Let) => {
                 let stmt = self.parse_declaration()?;
@@ -379,6 +535,31 @@ impl Parser {
                 Ok(stmt)
             }
             Some(Token::Fn) => Ok(Statement::Function(self.parse_function()?)),
+            Some(Token::Pub) if self.peek_next() == Some(&Token::Extern) => {
+                self.consume(); // `pub` を消費
+                let mut function = self.parse_extern()?;
+                function.is_pub = true;
+                Ok(Statement::Extern(function))
+            }
+            Some(Token::Pub) => {
+                self.consume(); // `pub` を消費
+                let mut function = self.parse_function()?;
+                function.is_pub = true;
+                Ok(Statement::Function(function))
+            }
+            Some(Token::Extern) => Ok(Statement::Extern(self.parse_extern()?)),
+            Some(Token::Mod) => {
+                self.consume(); // `mod` を消費
+                let name = self.parse_identifier()?;
+                self.expect_token(Token::Semicolon)?;
+                Ok(Statement::Mod(name))
+            }
+            Some(Token::Import) => {
+                self.consume(); // `import` を消費
+                let path = self.parse_path()?;
+                self.expect_token(Token::Semicolon)?;
+                Ok(Statement::Import(path))
+            }
             Some(Token::If) => {
                 let stmt = self.parse_if_statement()?;
                 Ok(stmt)
@@ -392,6 +573,10 @@ impl Parser {
                 // Identifier トークンの後に Equals が来た場合（代入文）
                 self.parse_assignment_or_expression_statement()
             }
+            Some(Token::Asterisk) => {
+                // `*p = value;` またはポインタを読むだけの式
+                self.parse_assignment_or_expression_statement()
+            }
             Some(Token::Identifier(_)) => {
                 // Identifier トークンの後に Equals 以外が来た場合（式）
                 let expr = self.parse_expression()?;
@@ -403,3 +588,11 @@ impl Parser {
         result
     }
 }
+
+// エラーメッセージに表示するトークン
+fn describe(token: Option<&Token>) -> String {
+    match token {
+        Some(Token::EOF) | None => String::from("end of input"),
+        Some(token) => format!("`{}`", token),
+    }
+}
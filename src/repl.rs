@@ -0,0 +1,232 @@
+use crate::ast::{Statement, Token};
+use crate::fold;
+use crate::interpreter::{Interpreter, Value};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::types::TypeEnvironment;
+use std::io::{self, BufRead, Write};
+
+const PROMPT: &str = "omochi> ";
+const CONTINUATION_PROMPT: &str = "   ...> ";
+
+const HELP: &str = "\
+:type  show the type of the last input
+:ast   show the parsed statements of the last input
+:ir    show the LLVM IR generated for the last input
+:help  show this message
+:quit  exit the REPL";
+
+// 1行ずつ入力を受け付けて実行する対話環境
+pub struct Repl {
+    interpreter: Interpreter,
+    types: TypeEnvironment,
+    history: Vec<Statement>, // これまでに実行した文 (`:ir` で使う)
+    last: Vec<Statement>,    // 直前の入力
+    last_start: usize,       // history の中で直前の入力の文が始まる位置
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Repl {
+            interpreter: Interpreter::new(),
+            types: TypeEnvironment::new(),
+            history: Vec::new(),
+            last: Vec::new(),
+            last_start: 0,
+        }
+    }
+
+    // 標準入力が終わるか `:quit` が入力されるまで繰り返す
+    pub fn run(&mut self) -> io::Result<()> {
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        let mut buffer = String::new();
+        loop {
+            let prompt = if buffer.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            };
+            print!("{}", prompt);
+            io::stdout().flush()?;
+
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => {
+                    println!();
+                    return Ok(());
+                }
+            };
+            if buffer.is_empty() {
+                match line.trim() {
+                    "" => continue,
+                    ":quit" | ":q" => return Ok(()),
+                    command if command.starts_with(':') => {
+                        self.command(command);
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
+            buffer.push_str(&line);
+            buffer.push('\n');
+            // 括弧が閉じるまで次の行を読み続ける
+            if brace_depth(&buffer) > 0 {
+                continue;
+            }
+            let input = std::mem::take(&mut buffer);
+            self.eval(&input);
+        }
+    }
+
+    // 入力を解析して実行し、式の値を表示する
+    // 途中の文でエラーになっても、それまでの文は実行済みなので履歴に残す
+    fn eval(&mut self, input: &str) {
+        let statements = match parse(input) {
+            Ok(statements) => statements,
+            Err(message) => {
+                eprintln!("Error: {}", message);
+                return;
+            }
+        };
+
+        self.last_start = self.history.len();
+        self.last = statements;
+        for statement in &self.last {
+            match self.interpreter.execute(statement) {
+                Ok(Value::Unit) => {}
+                Ok(value) if matches!(statement, Statement::Expression(_)) => {
+                    println!("{}", value)
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Runtime error: {}", e);
+                    return;
+                }
+            }
+            self.types.record(statement);
+            self.history.push(statement.clone());
+        }
+    }
+
+    fn command(&self, command: &str) {
+        match command {
+            ":type" => {
+                for statement in &self.last {
+                    if let Some(description) = self.types.describe(statement) {
+                        println!("{}", description);
+                    }
+                }
+            }
+            ":ast" => {
+                for statement in &self.last {
+                    println!("{:#?}", statement);
+                }
+            }
+            ":ir" => self.print_ir(),
+            ":help" => println!("{}", HELP),
+            _ => eprintln!("Unknown command `{}` (try :help)", command),
+        }
+    }
+
+    // それまでの入力を前置きとして生成し、直前の入力の分だけ IR を表示する
+    // コンパイルと同じ検査を通らない履歴は IR を生成できないので、エラーを表示する
+    #[cfg(feature = "llvm")]
+    fn print_ir(&self) {
+        use crate::diagnostic::Diagnostic;
+        use crate::driver;
+        use crate::irgenerator::IRGenerator;
+        use crate::lint::LintConfig;
+        use inkwell::context::Context;
+        use inkwell::values::AnyValue;
+
+        let errors: Vec<Diagnostic> = driver::check(&self.history, &LintConfig::new())
+            .into_iter()
+            .filter(Diagnostic::is_error)
+            .collect();
+        if !errors.is_empty() {
+            for error in errors {
+                eprintln!("Error: {}", error);
+            }
+            return;
+        }
+
+        let context = Context::create();
+        let mut generator = IRGenerator::new(&context);
+        let fn_type = context.i32_type().fn_type(&[], false);
+        let main = generator.module.add_function("main", fn_type, None);
+        generator.initialize_entry_block(&main);
+
+        let (previous, last) = self.history.split_at(self.last_start);
+        for statement in previous {
+            let _ = generator.generate_ir_for_statement(statement, &main);
+        }
+        let first = generator.begin_block(&main, "input");
+        for statement in last {
+            let _ = generator.generate_ir_for_statement(statement, &main);
+        }
+
+        for statement in last {
+            if let Statement::Function(func) = statement {
+                if let Some(function) = generator.module.get_function(&func.name) {
+                    println!("{}", function.print_to_string());
+                }
+            }
+        }
+        let mut block = Some(first);
+        while let Some(current) = block {
+            println!("{}:", current.get_name().to_string_lossy());
+            let mut instruction = current.get_first_instruction();
+            while let Some(current) = instruction {
+                println!("{}", current.print_to_string());
+                instruction = current.get_next_instruction();
+            }
+            block = current.get_next_basic_block();
+        }
+    }
+
+    #[cfg(not(feature = "llvm"))]
+    fn print_ir(&self) {
+        eprintln!("omochi was built without LLVM support; `:ir` is not available");
+    }
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse(input: &str) -> Result<Vec<Statement>, String> {
+    let (tokens, spans) = Lexer::new(input)
+        .lex_with_spans()
+        .map_err(|e| e.to_string())?;
+    let statements = match Parser::with_spans(tokens.clone(), spans.clone()).parse_statements() {
+        Ok(statements) => statements,
+        // 式だけの入力はセミコロンを省略できる
+        Err(e) => {
+            let (mut tokens, mut spans) = (tokens, spans);
+            let eof = tokens.len() - 1;
+            tokens.insert(eof, Token::Semicolon);
+            spans.insert(eof, spans[eof]);
+            Parser::with_spans(tokens, spans)
+                .parse_statements()
+                .map_err(|_| e.to_string())?
+        }
+    };
+    fold::fold_program(statements).map_err(|e| e.to_string())
+}
+
+// 閉じていない `{` の数
+fn brace_depth(input: &str) -> i32 {
+    match Lexer::new(input).lex() {
+        Ok(tokens) => tokens.iter().fold(0, |depth, token| match token {
+            Token::LeftBrace => depth + 1,
+            Token::RightBrace => depth - 1,
+            _ => depth,
+        }),
+        // 字句解析のエラーは実行時に報告する
+        Err(_) => 0,
+    }
+}
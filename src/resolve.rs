@@ -0,0 +1,273 @@
+use crate::ast::{Expr, Function, Span, Statement};
+use crate::builtin;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+// 名前解決で見つかったエラー
+#[derive(Debug, PartialEq, Clone)]
+pub enum ResolveError {
+    UndefinedVariable(String, Span),     // 変数名, 使った位置
+    UndefinedFunction(String, Span),     // 関数名, 呼び出した位置
+    Uninitialized(String, String, Span), // トップレベルの変数名, 呼び出した関数名, 呼び出した位置
+}
+
+impl ResolveError {
+    pub fn span(&self) -> Span {
+        match self {
+            ResolveError::UndefinedVariable(_, span)
+            | ResolveError::UndefinedFunction(_, span)
+            | ResolveError::Uninitialized(_, _, span) => *span,
+        }
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::UndefinedVariable(name, span) => {
+                write!(f, "{}: Undefined variable `{}`", span, name)
+            }
+            ResolveError::UndefinedFunction(name, span) => {
+                write!(f, "{}: Undefined function `{}`", span, name)
+            }
+            ResolveError::Uninitialized(name, function, span) => write!(
+                f,
+                "{}: `{}` uses the top-level variable `{}` before it is initialized",
+                span, function, name
+            ),
+        }
+    }
+}
+
+// 未定義の変数と関数の使用をすべて集める
+// 関数はどこで定義しても呼べる。関数の中では引数とローカル変数の次にトップレベルの変数が見え、
+// トップレベルの文からはそれより前に宣言した変数だけが見える (ブロックの中の変数はブロックの外から見えない)
+// トップレベルの文から呼び出した関数が、まだ初期化されていないトップレベルの変数を使う場合もエラーにする
+pub fn check(statements: &[Statement]) -> Vec<ResolveError> {
+    let mut resolver = Resolver {
+        functions: HashSet::new(),
+        globals: HashSet::new(),
+        scopes: vec![HashSet::new()],
+        in_function: false,
+        errors: Vec::new(),
+        span: Span::default(),
+        current: None,
+        uses: HashMap::new(),
+        top_level_calls: Vec::new(),
+    };
+    resolver.declare_functions(statements);
+    for statement in statements {
+        if let Statement::Declaration(name, _, _) = statement {
+            resolver.globals.insert(name.clone());
+        }
+    }
+    resolver.statements(statements);
+    resolver.check_initialization();
+    resolver.errors
+}
+
+// 関数の本体が直接使うトップレベルの変数と、呼び出す関数
+#[derive(Default)]
+struct Uses {
+    globals: BTreeSet<String>,
+    calls: BTreeSet<String>,
+}
+
+// トップレベルの文からの関数呼び出し
+struct TopLevelCall {
+    function: String,
+    span: Span,
+    initialized: HashSet<String>, // 呼び出した時点で初期化済みのトップレベルの変数
+}
+
+struct Resolver {
+    functions: HashSet<String>,   // ユーザー定義の関数と C関数
+    globals: HashSet<String>,     // トップレベルの変数 (関数の中からは宣言の順序によらず見える)
+    scopes: Vec<HashSet<String>>, // 内側ほど後ろのスコープで宣言した変数
+    in_function: bool,            // 関数の本体を解決している
+    errors: Vec<ResolveError>,
+    span: Span,              // 解決中の位置 (位置を持たない式のエラーに使う)
+    current: Option<String>, // 解決中の関数
+    uses: HashMap<String, Uses>,
+    top_level_calls: Vec<TopLevelCall>,
+}
+
+impl Resolver {
+    // 定義より前の呼び出しも解決できるように、入れ子の関数も含めて先に登録する
+    fn declare_functions(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Function(func) => {
+                    self.functions.insert(func.name.clone());
+                    self.declare_functions(&func.body);
+                }
+                Statement::Extern(func) => {
+                    self.functions.insert(func.name.clone());
+                }
+                Statement::Block(body) => self.declare_functions(body),
+                Statement::If(_, then_branch, else_branch, _) => {
+                    self.declare_functions(std::slice::from_ref(then_branch));
+                    if let Some(else_branch) = else_branch {
+                        self.declare_functions(std::slice::from_ref(else_branch));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(expr) | Statement::Print(expr) => self.expr(expr),
+            Statement::Declaration(name, expr, span) => {
+                // `let x = x + 1;` の右辺は外側の x を参照する
+                self.expr(expr);
+                self.span = *span;
+                self.scopes.last_mut().unwrap().insert(name.clone());
+            }
+            Statement::Assignment(name, expr, span) => {
+                self.expr(expr);
+                self.span = *span;
+                self.variable(name);
+            }
+            Statement::DerefAssignment(pointer, expr, _) => {
+                self.expr(expr);
+                self.expr(pointer);
+            }
+            Statement::Block(statements) => {
+                self.scopes.push(HashSet::new());
+                self.statements(statements);
+                self.scopes.pop();
+            }
+            Statement::If(condition, then_branch, else_branch, span) => {
+                self.span = *span;
+                self.expr(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Statement::Function(func) => self.function(func),
+            Statement::Extern(_) | Statement::Mod(_) | Statement::Import(_) => {}
+        }
+    }
+
+    // 関数の本体からは引数とトップレベルの変数だけが見える
+    fn function(&mut self, func: &Function) {
+        let params = func.params.iter().map(|(name, _)| name.clone()).collect();
+        let saved_scopes = std::mem::replace(&mut self.scopes, vec![params]);
+        let saved_in_function = std::mem::replace(&mut self.in_function, true);
+        let saved_current = self.current.replace(func.name.clone());
+        self.uses.entry(func.name.clone()).or_default();
+        self.span = func.span;
+        self.statements(&func.body);
+        self.expr(&func.return_expr);
+        self.scopes = saved_scopes;
+        self.in_function = saved_in_function;
+        self.current = saved_current;
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Integer(_) | Expr::Str(_) => {}
+            Expr::Variable(name, span) => {
+                self.span = *span;
+                self.variable(name);
+            }
+            Expr::Assign(name, value) => {
+                self.expr(value);
+                self.variable(name);
+            }
+            Expr::BinaryOp(left, _, right, span) => {
+                self.expr(left);
+                self.expr(right);
+                self.span = *span;
+            }
+            Expr::If(condition, then_branch, else_branch) => {
+                self.expr(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Expr::AddressOf(name) => self.variable(name),
+            Expr::Deref(pointer, _) => self.expr(pointer),
+            Expr::New(value) => self.expr(value),
+            Expr::FunctionCall(name, args, span) => {
+                for arg in args {
+                    self.expr(arg);
+                }
+                self.span = *span;
+                if !self.functions.contains(name) && builtin::lookup(name).is_none() {
+                    self.errors
+                        .push(ResolveError::UndefinedFunction(name.clone(), *span));
+                }
+                match &self.current {
+                    Some(current) => {
+                        let uses = self.uses.get_mut(current).unwrap();
+                        uses.calls.insert(name.clone());
+                    }
+                    None => self.top_level_calls.push(TopLevelCall {
+                        function: name.clone(),
+                        span: *span,
+                        initialized: self.scopes[0].clone(),
+                    }),
+                }
+            }
+        }
+    }
+
+    fn variable(&mut self, name: &str) {
+        if self.scopes.iter().any(|scope| scope.contains(name)) {
+            return;
+        }
+        if self.in_function && self.globals.contains(name) {
+            if let Some(current) = &self.current {
+                let uses = self.uses.get_mut(current).unwrap();
+                uses.globals.insert(name.to_string());
+            }
+            return;
+        }
+        self.errors
+            .push(ResolveError::UndefinedVariable(name.to_string(), self.span));
+    }
+
+    // トップレベルの文から呼び出した関数 (とそこから呼び出す関数) が使う変数は、呼び出す前に初期化されていなければならない
+    // どのバックエンドでも宣言より前のトップレベルの変数は読めない (インタプリタと VM の未定義の値や LLVM の 0 にしない)
+    fn check_initialization(&mut self) {
+        for call in std::mem::take(&mut self.top_level_calls) {
+            for name in self.globals_used_by(&call.function) {
+                if !call.initialized.contains(&name) {
+                    self.errors.push(ResolveError::Uninitialized(
+                        name,
+                        call.function.clone(),
+                        call.span,
+                    ));
+                }
+            }
+        }
+    }
+
+    // 関数が直接または呼び出す関数を通して使うトップレベルの変数
+    fn globals_used_by(&self, function: &str) -> BTreeSet<String> {
+        let mut globals = BTreeSet::new();
+        let mut visited = HashSet::new();
+        let mut pending = vec![function.to_string()];
+        while let Some(function) = pending.pop() {
+            if !visited.insert(function.clone()) {
+                continue;
+            }
+            if let Some(uses) = self.uses.get(&function) {
+                globals.extend(uses.globals.iter().cloned());
+                pending.extend(uses.calls.iter().cloned());
+            }
+        }
+        globals
+    }
+}
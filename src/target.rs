@@ -0,0 +1,91 @@
+use crate::cli::Options;
+use crate::optimize;
+use inkwell::module::Module;
+use inkwell::targets::{
+    CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple,
+};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// ネイティブコード生成のエラーを表す列挙型
+#[derive(Debug)]
+pub enum TargetError {
+    Initialization(String),
+    UnknownTarget(String, String),
+    MachineCreation(String),
+    Emit(PathBuf, String),
+}
+
+impl fmt::Display for TargetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TargetError::Initialization(e) => write!(f, "Failed to initialize LLVM targets: {}", e),
+            TargetError::UnknownTarget(triple, e) => {
+                write!(f, "Unknown target `{}`: {}", triple, e)
+            }
+            TargetError::MachineCreation(triple) => {
+                write!(f, "Failed to create a target machine for `{}`", triple)
+            }
+            TargetError::Emit(path, e) => write!(f, "Failed to write {}: {}", path.display(), e),
+        }
+    }
+}
+
+// `--target` / `--cpu` / `--features` に従って TargetMachine を作成する
+// ターゲットを省略した場合はこのマシンの CPU と機能を使う
+pub fn create_target_machine(options: &Options) -> Result<TargetMachine, TargetError> {
+    let (triple, default_cpu, default_features) = match &options.target {
+        Some(triple) => {
+            Target::initialize_all(&InitializationConfig::default());
+            (
+                TargetTriple::create(triple),
+                String::from("generic"),
+                String::new(),
+            )
+        }
+        None => {
+            Target::initialize_native(&InitializationConfig::default())
+                .map_err(TargetError::Initialization)?;
+            (
+                TargetMachine::get_default_triple(),
+                TargetMachine::get_host_cpu_name().to_string(),
+                TargetMachine::get_host_cpu_features().to_string(),
+            )
+        }
+    };
+    let triple_name = triple.as_str().to_string_lossy().into_owned();
+    let target = Target::from_triple(&triple)
+        .map_err(|e| TargetError::UnknownTarget(triple_name.clone(), e.to_string()))?;
+    let cpu = options.cpu.clone().unwrap_or(default_cpu);
+    let features = options.features.clone().unwrap_or(default_features);
+    target
+        .create_target_machine(
+            &triple,
+            &cpu,
+            &features,
+            optimize::codegen_level(options.opt_level),
+            RelocMode::PIC, // cc は PIE を作るので位置独立コードにする
+            CodeModel::Default,
+        )
+        .ok_or(TargetError::MachineCreation(triple_name))
+}
+
+// モジュールをアセンブリ (.s) とオブジェクトファイル (.o) として書き出し、.o のパスを返す
+pub fn write_native_files(
+    machine: &TargetMachine,
+    module: &Module,
+    dir: &Path,
+    name: &str,
+) -> Result<PathBuf, TargetError> {
+    let assembly_path = dir.join(format!("{}.s", name));
+    let object_path = dir.join(format!("{}.o", name));
+    for (file_type, path) in [
+        (FileType::Assembly, &assembly_path),
+        (FileType::Object, &object_path),
+    ] {
+        machine
+            .write_to_file(module, file_type, path)
+            .map_err(|e| TargetError::Emit(path.clone(), e.to_string()))?;
+    }
+    Ok(object_path)
+}
@@ -0,0 +1,496 @@
+use crate::ast::{Expr, Function, Operator, Span, Statement, Type};
+use crate::builtin;
+use std::collections::HashMap;
+use std::fmt;
+
+// 関数呼び出し、参照外し、値の格納の型検査で見つかったエラー
+#[derive(Debug, PartialEq, Clone)]
+pub enum TypeError {
+    NotAPointer(Type, Span), // 参照外しした値の型, `*` の位置
+    ArgumentCount(String, usize, bool, usize, Span), // 関数名, 引数の数, 可変長かどうか, 渡した数
+    ArgumentType(String, usize, Type, Type, Span), // 関数名, 何番目の引数か (1始まり), 引数の型, 渡した値の型
+    UnknownArgument(String, usize, Type, Span),    // 関数名, 何番目の引数か (1始まり), 引数の型
+    AssignmentType(String, Type, Type, Span),      // 変数名, 変数の型, 代入した値の型
+    PointeeType(Type, Type, Span),                 // 参照先の型, 書き込んだ値の型
+    ReturnType(String, Type, Type, Span),          // 関数名, 戻り値の型, 最後の式の型
+    BorrowedValue(Type, Span),                     // `*` で読み出した値の型, `*` の位置
+    OperandType(Operator, Type, Type, Span),       // 演算子, 左辺の型, 右辺の型, 演算子の位置
+    ConditionType(Type, Span),                     // if の条件の型
+}
+
+impl TypeError {
+    pub fn span(&self) -> Span {
+        match self {
+            TypeError::NotAPointer(_, span)
+            | TypeError::ArgumentCount(.., span)
+            | TypeError::ArgumentType(.., span)
+            | TypeError::UnknownArgument(.., span)
+            | TypeError::AssignmentType(.., span)
+            | TypeError::PointeeType(.., span)
+            | TypeError::ReturnType(.., span)
+            | TypeError::BorrowedValue(_, span)
+            | TypeError::OperandType(.., span)
+            | TypeError::ConditionType(_, span) => *span,
+        }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeError::NotAPointer(ty, span) => {
+                write!(f, "{}: Cannot dereference a value of type `{}`", span, ty)
+            }
+            TypeError::ArgumentCount(name, expected, variadic, found, span) => {
+                let at_least = if *variadic { "at least " } else { "" };
+                write!(
+                    f,
+                    "{}: `{}` expects {}{} argument(s), found {}",
+                    span, name, at_least, expected, found
+                )
+            }
+            TypeError::ArgumentType(name, index, expected, found, span) => write!(
+                f,
+                "{}: Argument {} of `{}` expects `{}`, found `{}`",
+                span, index, name, expected, found
+            ),
+            TypeError::UnknownArgument(name, index, expected, span) => write!(
+                f,
+                "{}: Cannot infer the type of argument {} of `{}`, which expects `{}`",
+                span, index, name, expected
+            ),
+            TypeError::AssignmentType(name, expected, found, span) => write!(
+                f,
+                "{}: Cannot assign a value of type `{}` to `{}` of type `{}`",
+                span, found, name, expected
+            ),
+            TypeError::PointeeType(expected, found, span) => write!(
+                f,
+                "{}: Cannot store a value of type `{}` through a pointer to `{}`",
+                span, found, expected
+            ),
+            TypeError::ReturnType(name, expected, found, span) => write!(
+                f,
+                "{}: `{}` returns `{}`, but its last expression has type `{}`",
+                span, name, expected, found
+            ),
+            TypeError::BorrowedValue(ty, span) => write!(
+                f,
+                "{}: Cannot store a `{}` read through `*`; keep the reference itself instead",
+                span, ty
+            ),
+            TypeError::OperandType(op, left, right, span) => write!(
+                f,
+                "{}: Cannot apply `{}` to `{}` and `{}`",
+                span, op, left, right
+            ),
+            TypeError::ConditionType(ty, span) => {
+                write!(f, "{}: Condition must be `int`, found `{}`", span, ty)
+            }
+        }
+    }
+}
+
+// 変数と関数の型 (REPL の `:type` や LSP のホバーで使う)
+pub struct TypeEnvironment {
+    scopes: Vec<HashMap<String, Type>>, // 変数のスコープ (最後が一番内側)
+    functions: HashMap<String, (Vec<Type>, bool, Type)>, // 引数, 可変長かどうか, 戻り値
+}
+
+impl TypeEnvironment {
+    pub fn new() -> Self {
+        TypeEnvironment {
+            scopes: vec![HashMap::new()],
+            functions: HashMap::new(),
+        }
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    pub fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    // 一番内側のスコープに変数を追加する
+    pub fn declare(&mut self, name: &str, ty: Type) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), ty);
+        }
+    }
+
+    pub fn variable(&self, name: &str) -> Option<&Type> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    // 関数は定義より前からも呼べるので、全ての関数のシグネチャを先に記録する
+    pub fn declare_functions(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Function(func) => {
+                    self.record(statement);
+                    self.declare_functions(&func.body);
+                }
+                Statement::Extern(_) => self.record(statement),
+                Statement::Block(body) => self.declare_functions(body),
+                Statement::If(_, then_branch, else_branch, _) => {
+                    self.declare_functions(std::slice::from_ref(then_branch));
+                    if let Some(else_branch) = else_branch {
+                        self.declare_functions(std::slice::from_ref(else_branch));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // 宣言された変数と関数の型を記録する
+    pub fn record(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Declaration(name, expr, _) => {
+                if let Some(ty) = self.infer(expr) {
+                    self.declare(name, ty);
+                }
+            }
+            Statement::Function(func) => {
+                let params = func.params.iter().map(|(_, ty)| ty.clone()).collect();
+                self.functions
+                    .insert(func.name.clone(), (params, false, func.return_type.clone()));
+            }
+            Statement::Extern(func) => {
+                let params = func.params.iter().map(|(_, ty)| ty.clone()).collect();
+                self.functions.insert(
+                    func.name.clone(),
+                    (params, func.variadic, func.return_type.clone()),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    // `:type` で表示する文字列
+    pub fn describe(&self, statement: &Statement) -> Option<String> {
+        let show = |ty: Option<Type>| ty.map_or(String::from("<unknown>"), |ty| ty.to_string());
+        match statement {
+            Statement::Expression(expr) => Some(show(self.infer(expr))),
+            Statement::Declaration(name, _, _) => {
+                Some(format!("{}: {}", name, show(self.variable(name).cloned())))
+            }
+            Statement::Function(func) => {
+                Some(format!("{}: {}", func.name, self.signature(&func.name)?))
+            }
+            Statement::Extern(func) => {
+                Some(format!("{}: {}", func.name, self.signature(&func.name)?))
+            }
+            _ => None,
+        }
+    }
+
+    // `fn(int, str) -> int` の形式の関数の型
+    pub fn signature(&self, name: &str) -> Option<String> {
+        let (params, variadic, return_type) = self.functions.get(name)?;
+        let mut params: Vec<String> = params.iter().map(Type::to_string).collect();
+        if *variadic {
+            params.push(String::from("..."));
+        }
+        Some(format!("fn({}) -> {}", params.join(", "), return_type))
+    }
+
+    pub fn infer(&self, expr: &Expr) -> Option<Type> {
+        match expr {
+            Expr::Integer(_) => Some(Type::Int),
+            Expr::Str(_) => Some(Type::Str),
+            Expr::Variable(name, _) => self.variable(name).cloned(),
+            Expr::Assign(_, value) => self.infer(value),
+            Expr::BinaryOp(left, Operator::Plus, _, _) => match self.infer(left)? {
+                Type::Str | Type::String => Some(Type::String),
+                _ => Some(Type::Int),
+            },
+            Expr::BinaryOp(..) | Expr::If(..) => Some(Type::Int),
+            Expr::FunctionCall(name, _, _) => match self.functions.get(name) {
+                Some((_, _, ty)) => Some(ty.clone()),
+                None => builtin::lookup(name).map(|builtin| builtin.return_type()),
+            },
+            Expr::AddressOf(name) => {
+                let ty = self.variable(name)?;
+                Some(Type::Pointer(Box::new(ty.clone())))
+            }
+            Expr::Deref(pointer, _) => match self.infer(pointer)? {
+                Type::Pointer(inner) | Type::Reference(inner) => Some(*inner),
+                _ => None,
+            },
+            Expr::New(value) => Some(Type::Reference(Box::new(self.infer(value)?))),
+        }
+    }
+}
+
+impl Default for TypeEnvironment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 関数呼び出しの引数の数と型をシグネチャと照合し、参照外しの対象がポインタであることを確かめる
+// 代入・ポインタへの書き込み・戻り値は格納先と同じ型でなければならない
+// (参照カウントを持つ `string` と `&T` の格納先に、リテラルや `&x` の値が入らないようにする)
+// 型を推論できない式と、解決できない関数の呼び出しは検査しない
+pub fn check(statements: &[Statement]) -> Vec<TypeError> {
+    let mut checker = Checker {
+        types: TypeEnvironment::new(),
+        errors: Vec::new(),
+        span: Span::default(),
+    };
+    checker.types.declare_functions(statements);
+    checker.statements(statements);
+    checker.errors
+}
+
+struct Checker {
+    types: TypeEnvironment,
+    errors: Vec<TypeError>,
+    span: Span, // 検査中の文の位置 (位置を持たない式のエラーに使う)
+}
+
+impl Checker {
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        if let Some(span) = statement.span() {
+            self.span = span;
+        }
+        match statement {
+            Statement::Expression(expr) | Statement::Print(expr) => self.expr(expr),
+            Statement::Assignment(name, expr, span) => {
+                self.expr(expr);
+                self.assign(name, expr, *span);
+            }
+            Statement::Declaration(_, expr, _) => {
+                self.expr(expr);
+                self.store(expr);
+                self.types.record(statement);
+            }
+            Statement::DerefAssignment(pointer, value, span) => {
+                self.expr(pointer);
+                self.expr(value);
+                self.deref(pointer, *span);
+                self.store_through(pointer, value, *span);
+            }
+            Statement::Block(body) => {
+                self.types.push_scope();
+                self.statements(body);
+                self.types.pop_scope();
+            }
+            Statement::If(condition, then_branch, else_branch, span) => {
+                self.expr(condition);
+                self.condition(condition, *span);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Statement::Function(func) => self.function(func),
+            Statement::Mod(_) | Statement::Import(_) | Statement::Extern(_) => {}
+        }
+    }
+
+    // 関数の中からはトップレベルの変数と引数だけが見える
+    fn function(&mut self, func: &Function) {
+        let outer = self.types.scopes.split_off(1);
+        self.types.push_scope();
+        for (name, ty) in &func.params {
+            self.types.declare(name, ty.clone());
+        }
+        self.statements(&func.body);
+        self.span = func.span;
+        self.expr(&func.return_expr);
+        self.returns(func);
+        self.types.scopes.truncate(1);
+        self.types.scopes.extend(outer);
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Integer(_) | Expr::Str(_) | Expr::Variable(..) | Expr::AddressOf(_) => {}
+            Expr::BinaryOp(left, op, right, span) => {
+                self.expr(left);
+                self.expr(right);
+                self.operands(left, op, right, *span);
+            }
+            Expr::Assign(name, value) => {
+                self.expr(value);
+                self.assign(name, value, self.at(value));
+            }
+            Expr::New(value) => self.expr(value),
+            Expr::Deref(pointer, span) => {
+                self.expr(pointer);
+                self.deref(pointer, *span);
+            }
+            Expr::If(condition, then_branch, else_branch) => {
+                self.expr(condition);
+                self.condition(condition, self.at(condition));
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Expr::FunctionCall(name, args, span) => {
+                args.iter().for_each(|arg| self.expr(arg));
+                self.call(name, args, *span);
+            }
+        }
+    }
+
+    fn at(&self, expr: &Expr) -> Span {
+        expr.span().unwrap_or(self.span)
+    }
+
+    // 変数には宣言したときと同じ型の値だけを代入できる
+    fn assign(&mut self, name: &str, value: &Expr, span: Span) {
+        self.store(value);
+        let (Some(expected), Some(found)) = (self.types.variable(name), self.types.infer(value))
+        else {
+            return;
+        };
+        if *expected != found {
+            let error = TypeError::AssignmentType(name.to_string(), expected.clone(), found, span);
+            self.errors.push(error);
+        }
+    }
+
+    // ポインタの参照先には参照先と同じ型の値だけを書き込める
+    fn store_through(&mut self, pointer: &Expr, value: &Expr, span: Span) {
+        self.store(value);
+        let Some(Type::Pointer(expected) | Type::Reference(expected)) = self.types.infer(pointer)
+        else {
+            return;
+        };
+        if let Some(found) = self.types.infer(value) {
+            if found != *expected {
+                self.errors
+                    .push(TypeError::PointeeType(*expected, found, span));
+            }
+        }
+    }
+
+    // 最後の式は戻り値の型と同じ型でなければならない (void を返す関数の最後の式は捨てる)
+    fn returns(&mut self, func: &Function) {
+        if func.return_type == Type::Void {
+            return;
+        }
+        self.store(&func.return_expr);
+        match self.types.infer(&func.return_expr) {
+            Some(found) if found != func.return_type => {
+                let span = self.at(&func.return_expr);
+                self.errors.push(TypeError::ReturnType(
+                    func.name.clone(),
+                    func.return_type.clone(),
+                    found,
+                    span,
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    // `*` で読み出した参照カウントを持つ値は、参照を確保せずに変数などに格納できない
+    fn store(&mut self, value: &Expr) {
+        let Expr::Deref(_, span) = value else {
+            return;
+        };
+        match self.types.infer(value) {
+            Some(ty) if ty.is_managed() => self.errors.push(TypeError::BorrowedValue(ty, *span)),
+            _ => {}
+        }
+    }
+
+    // 演算できるのは int 同士と、`+` での文字列同士だけ
+    fn operands(&mut self, left: &Expr, op: &Operator, right: &Expr, span: Span) {
+        let (Some(left), Some(right)) = (self.types.infer(left), self.types.infer(right)) else {
+            return;
+        };
+        let is_string = |ty: &Type| matches!(ty, Type::Str | Type::String);
+        let valid = match (&left, &right) {
+            (Type::Int, Type::Int) => true,
+            (l, r) => *op == Operator::Plus && is_string(l) && is_string(r),
+        };
+        if !valid {
+            let error = TypeError::OperandType(op.clone(), left, right, span);
+            self.errors.push(error);
+        }
+    }
+
+    // if の条件は int (0 なら偽)
+    fn condition(&mut self, condition: &Expr, span: Span) {
+        match self.types.infer(condition) {
+            Some(Type::Int) | None => {}
+            Some(ty) => self.errors.push(TypeError::ConditionType(ty, span)),
+        }
+    }
+
+    // `*` を付けられるのはポインタと参照だけ
+    fn deref(&mut self, pointer: &Expr, span: Span) {
+        match self.types.infer(pointer) {
+            Some(Type::Pointer(_) | Type::Reference(_)) | None => {}
+            Some(ty) => self.errors.push(TypeError::NotAPointer(ty, span)),
+        }
+    }
+
+    // 同じ名前の関数がなければ組み込み関数のシグネチャと照合する
+    fn call(&mut self, name: &str, args: &[Expr], span: Span) {
+        let (params, variadic) = match self.types.functions.get(name) {
+            Some((params, variadic, _)) => (params.clone(), *variadic),
+            None => match builtin::lookup(name) {
+                Some(builtin) => (builtin.params(), false),
+                None => return,
+            },
+        };
+        let count_matches = if variadic {
+            args.len() >= params.len()
+        } else {
+            args.len() == params.len()
+        };
+        if !count_matches {
+            self.errors.push(TypeError::ArgumentCount(
+                name.to_string(),
+                params.len(),
+                variadic,
+                args.len(),
+                span,
+            ));
+            return;
+        }
+        for (index, (param, arg)) in params.iter().zip(args).enumerate() {
+            // 参照カウントを持つ引数は関数の中で retain するので、型が分からない値は渡せない
+            let Some(found) = self.types.infer(arg) else {
+                if param.is_managed() {
+                    self.errors.push(TypeError::UnknownArgument(
+                        name.to_string(),
+                        index + 1,
+                        param.clone(),
+                        span,
+                    ));
+                }
+                continue;
+            };
+            if !accepts(param, &found) {
+                self.errors.push(TypeError::ArgumentType(
+                    name.to_string(),
+                    index + 1,
+                    param.clone(),
+                    found,
+                    span,
+                ));
+            }
+        }
+    }
+}
+
+// 引数の型に値を渡せるかどうか (ヒープ文字列は C の文字列としても渡せる)
+fn accepts(param: &Type, found: &Type) -> bool {
+    param == found || (*param == Type::Str && *found == Type::String)
+}
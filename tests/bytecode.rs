@@ -0,0 +1,65 @@
+// 壊れた `.omoc` ファイルを読み込み・実行してもパニックせずにエラーになることを確かめる
+use omochi::ast::{Operator, Span};
+use omochi::bytecode::{BytecodeError, Chunk, Constant, Instruction, Program, Vm, FORMAT_VERSION};
+use std::io;
+
+// ヘッダーと関数の数までを書いたファイルの先頭
+fn header(chunks: u32) -> Vec<u8> {
+    let mut bytes = b"OMOC".to_vec();
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&chunks.to_le_bytes());
+    bytes
+}
+
+fn program(code: Vec<Instruction>) -> Program {
+    let spans = vec![Span::new(1, 1); code.len()];
+    Program {
+        chunks: vec![Chunk {
+            name: String::from("<main>"),
+            code,
+            spans,
+            ..Chunk::default()
+        }],
+        files: vec![String::from("main.omo")],
+    }
+}
+
+#[test]
+fn string_length_is_not_trusted() {
+    // 関数名の長さだけ巨大で、中身がないファイル
+    let mut bytes = header(1);
+    bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+    bytes.extend_from_slice(b"main");
+    match Program::read_from(&mut bytes.as_slice()) {
+        Err(BytecodeError::Corrupt(reason)) => assert_eq!(reason, "unexpected end of file"),
+        other => panic!("expected a corrupt file error, got {:?}", other),
+    }
+}
+
+#[test]
+fn round_trip() {
+    let mut program = program(vec![Instruction::Const(0), Instruction::Return]);
+    program.chunks[0]
+        .constants
+        .push(Constant::Str(String::from("omochi")));
+    let mut bytes = Vec::new();
+    program.write_to(&mut bytes).unwrap();
+    assert_eq!(Program::read_from(&mut bytes.as_slice()).unwrap(), program);
+}
+
+#[test]
+fn stack_underflow_is_a_runtime_error() {
+    let programs = [
+        vec![Instruction::Pop],
+        vec![Instruction::Dup],
+        vec![Instruction::Return],
+        vec![Instruction::Binary(Operator::Plus)],
+    ];
+    for code in programs {
+        let program = program(code.clone());
+        let error = Vm::with_output(&program, Box::new(io::sink()))
+            .run()
+            .expect_err("underflow must fail");
+        assert_eq!(error.message, "Bytecode stack underflow", "{:?}", code);
+    }
+}
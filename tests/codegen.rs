@@ -0,0 +1,43 @@
+// 構文解析器が作らない AST (if 式の枝の宣言や print など) からも検証を通る IR を生成できることを確かめる
+#![cfg(feature = "llvm")]
+
+use inkwell::context::Context;
+use omochi::ast::{Expr, Operator, Span, Statement};
+use omochi::{generate_ir, Backend, CompileOptions};
+
+#[test]
+fn if_expressions_lower_every_statement() {
+    let variable = |name: &str, line| Expr::Variable(name.to_string(), Span::new(line, 5));
+    let statements = vec![
+        Statement::Declaration("x".to_string(), Expr::Integer(1), Span::new(1, 5)),
+        Statement::Expression(Expr::If(
+            Box::new(Expr::BinaryOp(
+                Box::new(variable("x", 2)),
+                Operator::LessThan,
+                Box::new(Expr::Integer(2)),
+                Span::new(2, 7),
+            )),
+            Box::new(Statement::Block(vec![
+                Statement::Declaration("y".to_string(), Expr::Integer(2), Span::new(3, 9)),
+                Statement::Print(variable("y", 4)),
+            ])),
+            Some(Box::new(Statement::Block(vec![
+                Statement::Assignment("x".to_string(), Expr::Integer(3), Span::new(6, 5)),
+                Statement::Import(vec!["nothing".to_string()]),
+            ]))),
+        )),
+        Statement::Print(variable("x", 8)),
+    ];
+    let options = CompileOptions::new("codegen.omo", Backend::LlvmIr);
+    let context = Context::create();
+    let generator = generate_ir(
+        &context,
+        statements,
+        &[options.path.clone()],
+        &options,
+        None,
+    );
+    if let Err(e) = generator.get_module().verify() {
+        panic!("invalid module: {}", e.to_string());
+    }
+}
@@ -0,0 +1,67 @@
+// `-g` で生成した IR にデバッグ情報が揃っていて、モジュールが検証を通ることを確かめる
+#![cfg(feature = "llvm")]
+
+use inkwell::context::Context;
+use omochi::{generate_ir, parse, Backend, CompileOptions};
+
+const SOURCE: &str = "\
+fn add(a: int, b: int) -> int {
+    let sum = a + b;
+    sum;
+}
+let x = add(1, 2);
+print x;
+";
+
+#[test]
+fn debug_info_is_complete() {
+    let mut options = CompileOptions::new("debug.omo", Backend::LlvmIr);
+    options.debug_info = true;
+    let statements = parse(SOURCE).unwrap();
+    let context = Context::create();
+    let generator = generate_ir(
+        &context,
+        statements,
+        &[options.path.clone()],
+        &options,
+        None,
+    );
+    let module = generator.get_module();
+    if let Err(e) = module.verify() {
+        panic!("invalid module: {}", e.to_string());
+    }
+    let ir = module.print_to_string().to_string();
+
+    assert!(ir.contains("!DICompileUnit("), "no compile unit:\n{}", ir);
+    for function in ["add", "omochi_init"] {
+        let subprogram = format!("DISubprogram(name: \"{}\"", function);
+        assert!(
+            ir.contains(&subprogram),
+            "no subprogram for `{}`:\n{}",
+            function,
+            ir
+        );
+    }
+    for variable in ["a", "b", "sum"] {
+        let local = format!("DILocalVariable(name: \"{}\"", variable);
+        assert!(
+            ir.contains(&local),
+            "no debug variable `{}`:\n{}",
+            variable,
+            ir
+        );
+    }
+    assert!(
+        ir.contains("call void @llvm.dbg.declare("),
+        "no dbg.declare:\n{}",
+        ir
+    );
+    let calls: Vec<&str> = ir
+        .lines()
+        .filter(|line| line.contains("call i32 @add("))
+        .collect();
+    assert!(!calls.is_empty(), "no call to `add`:\n{}", ir);
+    for call in calls {
+        assert!(call.contains("!dbg"), "call without a location: {}", call);
+    }
+}
@@ -0,0 +1,86 @@
+// `--emit=tokens-json` / `ast-json` / `ast-sexp` の出力の形を固定する
+use omochi::driver;
+use omochi::dump;
+
+#[test]
+fn tokens_json() {
+    let (tokens, spans) = driver::tokenize("let s = \"hi\";\n").unwrap();
+    assert_eq!(
+        dump::tokens_json(&tokens, &spans),
+        r#"[
+  {"kind": "Let", "text": "let", "span": {"line": 1, "column": 1}},
+  {"kind": "Identifier", "value": "s", "text": "s", "span": {"line": 1, "column": 5}},
+  {"kind": "Equals", "text": "=", "span": {"line": 1, "column": 7}},
+  {"kind": "Str", "value": "hi", "text": "\"hi\"", "span": {"line": 1, "column": 9}},
+  {"kind": "Semicolon", "text": ";", "span": {"line": 1, "column": 13}},
+  {"kind": "EOF", "text": "", "span": {"line": 2, "column": 1}}
+]
+"#
+    );
+}
+
+#[test]
+fn ast_json() {
+    // リテラル・`&x`・print は AST に位置がないので span を出力しない
+    let statements = driver::parse("let x = 1 + 2;\nprint *&x;\n").unwrap();
+    assert_eq!(
+        dump::ast_json(&statements),
+        r#"[
+  {
+    "kind": "Declaration",
+    "name": "x",
+    "value": {
+      "kind": "BinaryOp",
+      "op": "+",
+      "left": {"kind": "Integer", "value": 1},
+      "right": {"kind": "Integer", "value": 2},
+      "span": {"line": 1, "column": 11}
+    },
+    "span": {"line": 1, "column": 5}
+  },
+  {
+    "kind": "Print",
+    "value": {
+      "kind": "Deref",
+      "pointer": {"kind": "AddressOf", "name": "x"},
+      "span": {"line": 2, "column": 7}
+    }
+  }
+]
+"#
+    );
+}
+
+#[test]
+fn ast_sexp() {
+    let source = "\
+fn twice(n: int) -> int {
+    n * 2;
+}
+let x = twice(21);
+if x > 1 {
+    print \"big\";
+    x;
+}
+";
+    let statements = driver::parse(source).unwrap();
+    assert_eq!(
+        dump::ast_sexp(&statements),
+        r#"(program
+  (function
+    twice
+    false
+    ((param n int))
+    int
+    ()
+    (binary_op * (variable n @2:5) (integer 2) @2:7)
+    @1:4)
+  (declaration x (function_call twice ((integer 21)) @4:9) @4:5)
+  (if
+    (binary_op > (variable x @5:4) (integer 1) @5:6)
+    (block ((print (str "big"))))
+    nil
+    @5:1))
+"#
+    );
+}
@@ -0,0 +1,125 @@
+// 整形結果が元と同じトークン列になり、もう一度整形しても変わらないことを確かめる
+use omochi::format::{format_source, FormatError};
+use omochi::lexer::Lexer;
+
+fn assert_stable(source: &str) -> String {
+    let formatted = format_source(source).expect("source must format");
+    let tokens = Lexer::new(source).lex().unwrap();
+    let formatted_tokens = Lexer::new(&formatted).lex().unwrap();
+    assert_eq!(formatted_tokens, tokens, "tokens changed:\n{}", formatted);
+    let twice = format_source(&formatted).expect("formatted source must format");
+    assert_eq!(twice, formatted, "formatting is not idempotent");
+    formatted
+}
+
+#[test]
+fn comments_are_kept() {
+    let source = "\
+// 先頭のコメント
+let x=1;   // 行末のコメント
+
+
+// 空行は1行にまとめる
+print x;
+";
+    assert_eq!(
+        assert_stable(source),
+        "\
+// 先頭のコメント
+let x = 1;  // 行末のコメント
+
+// 空行は1行にまとめる
+print x;
+"
+    );
+}
+
+#[test]
+fn trailing_comments_in_argument_lists_stay_on_their_line() {
+    let source = "\
+fn add(a: int, // 1つ目
+    b: int) -> int {
+    a + b;
+}
+print add(1, // 左
+    2);
+";
+    assert_eq!(
+        assert_stable(source),
+        "\
+fn add(a: int,  // 1つ目
+    b: int) -> int {
+    a + b;
+}
+
+print add(1,  // 左
+    2);
+"
+    );
+}
+
+#[test]
+fn else_stays_on_the_closing_brace_line() {
+    let source = "\
+let x = 5;
+if x > 3 {
+print \"big\";
+x;
+}
+else
+{
+print \"small\";
+x;
+}
+";
+    assert_eq!(
+        assert_stable(source),
+        "\
+let x = 5;
+if x > 3 {
+    print \"big\";
+    x;
+} else {
+    print \"small\";
+    x;
+}
+"
+    );
+}
+
+#[test]
+fn integer_literals_keep_their_spelling() {
+    let source = "let mask = 0xFF;\nlet big = -1_000;\nprint mask+big;\n";
+    assert_eq!(
+        assert_stable(source),
+        "let mask = 0xFF;\nlet big = -1_000;\nprint mask + big;\n"
+    );
+}
+
+#[test]
+fn long_calls_are_wrapped() {
+    let args: Vec<String> = (0..12).map(|i| format!("argument_number_{}", i)).collect();
+    let params: Vec<String> = args.iter().map(|a| format!("{}: int", a)).collect();
+    let source = format!(
+        "fn wide({}) -> int {{\n{};\n}}\nprint wide({});\n",
+        params.join(", "),
+        args[0],
+        args.join(", ")
+    );
+    let formatted = assert_stable(&source);
+    assert!(
+        formatted.lines().all(|line| line.len() <= 100),
+        "lines are too long:\n{}",
+        formatted
+    );
+}
+
+#[test]
+fn syntax_errors_are_displayed() {
+    match format_source("let = 1;") {
+        Err(e @ FormatError::Parser(_)) => {
+            assert_eq!(e.to_string(), "Expected a variable name, found `=`")
+        }
+        other => panic!("expected a parser error, got {:?}", other),
+    }
+}
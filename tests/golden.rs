@@ -0,0 +1,159 @@
+// tests/programs/*.omo をコンパイルして実行し、終了コードと標準出力・標準エラーを .expected と比べる
+// 複数ファイルのプログラムは tests/programs/<name>/main.omo に置く (同じディレクトリのファイルを `mod` で読み込む)
+// コンパイルに失敗するプログラムは診断メッセージ (標準エラー) と終了コードを比べる
+// どのプログラムもこのビルドで使えるすべてのバックエンドで実行し、どれも同じ .expected に一致しなければならない
+// `BLESS=1 cargo test --test golden` で .expected を実際の結果 (BACKENDS の最初のもの) で書き換える
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const PROGRAMS_DIR: &str = "tests/programs";
+const MULTI_FILE_ENTRY: &str = "main.omo";
+
+// バックエンドの名前と `omochi run` に付ける引数 (LLVM なしでビルドした場合は JIT を使えない)
+#[cfg(feature = "llvm")]
+const BACKENDS: &[(&str, &[&str])] = &[("jit", &[]), ("interp", &["--interp"]), ("vm", &["--vm"])];
+#[cfg(not(feature = "llvm"))]
+const BACKENDS: &[(&str, &[&str])] = &[("interp", &["--interp"]), ("vm", &["--vm"])];
+
+#[test]
+fn golden() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(PROGRAMS_DIR);
+    let bless = env::var_os("BLESS").is_some();
+    let mut programs: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("Failed to read the test programs")
+        .map(|entry| entry.expect("Failed to read the test programs").path())
+        .filter_map(|path| match path.extension() {
+            Some(ext) if ext == "omo" => Some(path),
+            _ if path.join(MULTI_FILE_ENTRY).is_file() => Some(path.join(MULTI_FILE_ENTRY)),
+            _ => None,
+        })
+        .collect();
+    programs.sort();
+    assert!(!programs.is_empty(), "No programs in {}", dir.display());
+
+    let mut failures = Vec::new();
+    for path in &programs {
+        let name = path.strip_prefix(&dir).unwrap().to_string_lossy();
+        let source = fs::read_to_string(path).expect("Failed to read a test program");
+        let args = extra_args(&source);
+        let results: Vec<(&str, String)> = BACKENDS
+            .iter()
+            .map(|(backend, flags)| (*backend, run_program(&dir, &name, flags, &args)))
+            .collect();
+        let expected_path = path.with_extension("expected");
+        if bless {
+            fs::write(&expected_path, &results[0].1).expect("Failed to write the expectation");
+        }
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) => {
+                for (backend, actual) in &results {
+                    if expected != *actual {
+                        failures.push(format!(
+                            "{} [{}]:\n{}",
+                            name,
+                            backend,
+                            diff(&expected, actual)
+                        ));
+                    }
+                }
+            }
+            Err(_) => failures.push(format!(
+                "{}: missing {} (run with BLESS=1 to create it)\n",
+                name,
+                expected_path.display()
+            )),
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} runs did not match their expectations:\n\n{}",
+        failures.len(),
+        programs.len() * BACKENDS.len(),
+        failures.join("\n")
+    );
+}
+
+// 先頭の `// args: -D warnings` のようなコメントでコマンドライン引数を追加する
+fn extra_args(source: &str) -> Vec<String> {
+    source
+        .lines()
+        .take_while(|line| line.starts_with("//"))
+        .filter_map(|line| line.strip_prefix("// args:"))
+        .flat_map(|args| args.split_whitespace().map(String::from))
+        .collect()
+}
+
+// プログラムのあるディレクトリで `omochi run` を実行する (メッセージのパスはファイル名だけになる)
+fn run_program(dir: &Path, name: &str, backend: &[&str], args: &[String]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_omochi"))
+        .current_dir(dir)
+        .arg("run")
+        .args(backend)
+        .args(args)
+        .arg(name)
+        .output()
+        .expect("Failed to run omochi");
+    // 読み込みエラーは絶対パスを含むので、環境によらないようにディレクトリ部分を取り除く
+    let dir = dir.canonicalize().expect("Failed to resolve the test directory");
+    let prefix = format!("{}/", dir.display());
+    render(
+        output.status.code(),
+        &String::from_utf8_lossy(&output.stdout),
+        &String::from_utf8_lossy(&output.stderr).replace(&prefix, ""),
+    )
+}
+
+// .expected の形式 (空の出力の節は省く)
+fn render(code: Option<i32>, stdout: &str, stderr: &str) -> String {
+    let mut text = match code {
+        Some(code) => format!("exit: {}\n", code),
+        None => String::from("exit: signal\n"),
+    };
+    for (section, output) in [("stdout", stdout), ("stderr", stderr)] {
+        if output.is_empty() {
+            continue;
+        }
+        text.push_str(&format!("--- {}\n{}", section, output));
+        if !output.ends_with('\n') {
+            text.push('\n');
+        }
+    }
+    text
+}
+
+// 行単位の差分 (`-` が期待値、`+` が実際の結果)
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    // 最長共通部分列の長さの表 (lengths[i][j] は expected[i..] と actual[j..] のもの)
+    let mut lengths = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lengths[i][j] = if expected[i] == actual[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut text = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            text.push_str(&format!("  {}\n", expected[i]));
+            i += 1;
+            j += 1;
+        } else if j < actual.len() && (i == expected.len() || lengths[i][j + 1] >= lengths[i + 1][j])
+        {
+            text.push_str(&format!("+ {}\n", actual[j]));
+            j += 1;
+        } else {
+            text.push_str(&format!("- {}\n", expected[i]));
+            i += 1;
+        }
+    }
+    text
+}
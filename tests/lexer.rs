@@ -0,0 +1,79 @@
+// 整数リテラルの表記 (接頭辞・桁区切り・負の数) と、範囲外や不正な数字の位置付きエラーを確かめる
+use omochi::ast::{Span, Token};
+use omochi::lexer::{Lexer, LexerError};
+
+fn integers(source: &str) -> Vec<i64> {
+    Lexer::new(source)
+        .lex()
+        .expect("source must lex")
+        .into_iter()
+        .filter_map(|token| match token {
+            Token::Integer(value) => Some(value),
+            _ => None,
+        })
+        .collect()
+}
+
+fn error(source: &str) -> LexerError {
+    Lexer::new(source).lex().expect_err("source must not lex")
+}
+
+#[test]
+fn radix_prefixes() {
+    assert_eq!(
+        integers("0xFF 0Xff 0o17 0O17 0b101 0B101 0"),
+        vec![255, 255, 15, 15, 5, 5, 0]
+    );
+}
+
+#[test]
+fn digit_separators() {
+    assert_eq!(
+        integers("1_000_000 0xFF_FF 0b1010_1010"),
+        vec![1_000_000, 0xFFFF, 0b1010_1010]
+    );
+}
+
+#[test]
+fn negative_literals_reach_the_minimum() {
+    assert_eq!(
+        integers("let min = -2147483648; f(-0x10, -1);"),
+        vec![i32::MIN as i64, -16, -1]
+    );
+    // 値の直後の `-` は減算のまま
+    let tokens = Lexer::new("x -1").lex().unwrap();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Identifier(String::from("x")),
+            Token::Minus,
+            Token::Integer(1),
+            Token::EOF
+        ]
+    );
+}
+
+#[test]
+fn bad_digits_after_a_prefix() {
+    for source in ["0x", "0xG1", "0b102", "0o8", "1__", "1_"] {
+        match error(source) {
+            LexerError::InvalidNumber(_, span) => assert_eq!(span, Span::new(1, 1)),
+            other => panic!("{}: expected an invalid number, got {:?}", source, other),
+        }
+    }
+    assert_eq!(error("let x = 0b12;").to_string(), "1:9: Invalid number: 0b12");
+}
+
+#[test]
+fn overflow_is_located() {
+    let e = error("let ok = 2147483647;\nlet big = 2147483648;");
+    assert!(matches!(e, LexerError::IntegerOverflow(_, Span { line: 2, column: 11, .. })));
+    assert_eq!(
+        e.to_string(),
+        "2:11: Integer literal `2147483648` does not fit in `int` (max 2147483647)"
+    );
+    assert_eq!(
+        error("let x = -0x8000_0001;").to_string(),
+        "1:9: Integer literal `-0x8000_0001` does not fit in `int` (min -2147483648)"
+    );
+}
@@ -0,0 +1,194 @@
+// リントの報告を確かめる (特に分岐を考慮した unused_assignments)
+use omochi::driver;
+use omochi::lint::{self, Level, LintConfig};
+
+// 報告を `行:列: ... [リント名]` の形式の文字列で返す
+fn lints(source: &str) -> Vec<String> {
+    lints_with(source, &LintConfig::new())
+}
+
+fn lints_with(source: &str, config: &LintConfig) -> Vec<String> {
+    let statements = driver::parse(source).expect("test source must parse");
+    lint::check(&statements, config)
+        .iter()
+        .map(ToString::to_string)
+        .collect()
+}
+
+#[test]
+fn overwrite_before_read_is_reported() {
+    let source = "\
+let x = 1;
+x = 2;
+print x;
+";
+    assert_eq!(
+        lints(source),
+        ["1:5: warning: value assigned to `x` is never read [unused_assignments]"]
+    );
+}
+
+#[test]
+fn overwrite_in_one_branch_keeps_earlier_value_alive() {
+    // else のない if では元の値がそのまま読まれることがある
+    let source = "\
+let c = 1;
+let x = 1;
+if c {
+    x = 2;
+    c;
+}
+print x;
+";
+    assert_eq!(lints(source), Vec::<String>::new());
+}
+
+#[test]
+fn overwrite_in_both_branches_is_not_reported() {
+    // 経路ごとの解析はしないので、両方の分岐で上書きしても元の代入は報告しない
+    let source = "\
+let c = 1;
+let x = 1;
+if c {
+    x = 2;
+    c;
+} else {
+    x = 3;
+    c;
+}
+print x;
+";
+    assert_eq!(lints(source), Vec::<String>::new());
+}
+
+#[test]
+fn overwrite_within_a_branch_is_reported() {
+    let source = "\
+let c = 1;
+let x = 1;
+if c {
+    x = 2;
+    x = 3;
+    c;
+}
+print x;
+";
+    assert_eq!(
+        lints(source),
+        ["4:5: warning: value assigned to `x` is never read [unused_assignments]"]
+    );
+}
+
+#[test]
+fn branch_write_overwritten_after_the_if_is_reported() {
+    let source = "\
+let c = 1;
+let x = 1;
+if c {
+    x = 2;
+    c;
+}
+x = 3;
+print x;
+";
+    assert_eq!(
+        lints(source),
+        ["4:5: warning: value assigned to `x` is never read [unused_assignments]"]
+    );
+}
+
+#[test]
+fn escaped_variables_are_not_reported() {
+    // アドレスを取った変数はポインタ経由で読まれるかもしれない
+    let source = "\
+let x = 1;
+let p = &x;
+x = 2;
+x = 3;
+print *p;
+";
+    assert_eq!(lints(source), Vec::<String>::new());
+}
+
+#[test]
+fn globals_written_from_functions_are_not_reported() {
+    // 関数がいつ呼ばれるか分からないので、関数から書き込むトップレベルの変数は報告しない
+    let source = "\
+let counter = 0;
+fn bump() -> int {
+    counter = counter + 1;
+    counter = 10;
+    counter;
+}
+counter = 5;
+bump();
+print counter;
+";
+    assert_eq!(lints(source), Vec::<String>::new());
+}
+
+#[test]
+fn shadowing_is_reported_unless_underscored() {
+    let source = "\
+let x = 1;
+if x {
+    let x = 2;
+    print x;
+    x;
+}
+let _y = 1;
+let _y = 2;
+";
+    assert_eq!(
+        lints(source),
+        ["3:9: warning: `x` shadows an earlier binding [shadowing]"]
+    );
+}
+
+#[test]
+fn uncalled_functions_are_dead_code() {
+    let source = "\
+fn used(x: int) -> int {
+    x;
+}
+fn unused(x: int) -> int {
+    x;
+}
+pub fn exported(x: int) -> int {
+    x;
+}
+print used(1);
+";
+    assert_eq!(
+        lints(source),
+        ["4:4: warning: function `unused` is never called [dead_code]"]
+    );
+}
+
+#[test]
+fn levels_follow_the_config() {
+    let source = "let unused = 1;\n";
+    let mut config = LintConfig::new();
+    config.set(lint::UNUSED_VARIABLES, Level::Deny).unwrap();
+    assert_eq!(
+        lints_with(source, &config),
+        ["1:5: error: unused variable `unused` [unused_variables]"]
+    );
+    config.set("warnings", Level::Allow).unwrap();
+    assert_eq!(lints_with(source, &config), Vec::<String>::new());
+    assert!(config.set("no_such_lint", Level::Warn).is_err());
+}
+
+#[test]
+fn unused_parameter_points_at_the_parameter() {
+    let source = "\
+fn first(used: int, unused: int) -> int {
+    used;
+}
+print first(1, 2);
+";
+    assert_eq!(
+        lints(source),
+        ["1:21: warning: unused parameter `unused` [unused_parameters]"]
+    );
+}
@@ -0,0 +1,243 @@
+// Content-Length で区切ったメッセージを lsp::run に流し、応答を確かめる
+use omochi::json::Json;
+use omochi::lsp;
+use std::io::Cursor;
+
+const URI: &str = "file:///test.omo";
+const SOURCE: &str = "fn double(n: int) -> int {\n    n * 2;\n}\nlet value = double(21);\nprint value;\nlet unused = double(\"a\");\n";
+
+fn frame(message: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{}", message.len(), message)
+}
+
+fn request(id: i64, method: &str, params: Json) -> String {
+    let message = Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id.into()),
+        ("method", method.into()),
+        ("params", params),
+    ]);
+    frame(&message.to_string())
+}
+
+fn notification(method: &str, params: Json) -> String {
+    let message = Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", method.into()),
+        ("params", params),
+    ]);
+    frame(&message.to_string())
+}
+
+fn did_open() -> String {
+    notification(
+        "textDocument/didOpen",
+        Json::object([(
+            "textDocument",
+            Json::object([
+                ("uri", URI.into()),
+                ("languageId", "omochi".into()),
+                ("version", 1i64.into()),
+                ("text", SOURCE.into()),
+            ]),
+        )]),
+    )
+}
+
+// 0始まりの位置を指すリクエストのパラメータ
+fn at(line: i64, character: i64) -> Json {
+    Json::object([
+        ("textDocument", Json::object([("uri", URI.into())])),
+        (
+            "position",
+            Json::object([("line", line.into()), ("character", character.into())]),
+        ),
+    ])
+}
+
+// サーバを最後まで動かし、終了コードと送られたメッセージを返す
+fn session(messages: &[String]) -> (i32, Vec<Json>) {
+    let input = Cursor::new(messages.concat().into_bytes());
+    let mut output = Vec::new();
+    let code = lsp::run(input, &mut output).expect("server failed");
+    let mut output = String::from_utf8(output).unwrap();
+    let mut responses = Vec::new();
+    while let Some(rest) = output.strip_prefix("Content-Length: ") {
+        let (length, rest) = rest.split_once("\r\n\r\n").expect("missing header end");
+        let length: usize = length.parse().unwrap();
+        responses.push(Json::parse(&rest[..length]).unwrap());
+        output = rest[length..].to_string();
+    }
+    assert!(output.is_empty(), "unframed output: {:?}", output);
+    (code, responses)
+}
+
+// id を持つ応答の result
+fn result(responses: &[Json], id: i64) -> &Json {
+    responses
+        .iter()
+        .find(|response| response.get("id").and_then(Json::as_i64) == Some(id))
+        .and_then(|response| response.get("result"))
+        .unwrap_or_else(|| panic!("no result for request {}", id))
+}
+
+fn document() -> Json {
+    Json::object([("textDocument", Json::object([("uri", URI.into())]))])
+}
+
+#[test]
+fn lifecycle() {
+    let (code, responses) = session(&[
+        request(1, "initialize", Json::object([])),
+        request(2, "shutdown", Json::Null),
+        notification("exit", Json::Null),
+    ]);
+    assert_eq!(code, 0);
+    let capabilities = result(&responses, 1).get("capabilities").unwrap();
+    assert_eq!(capabilities.get("hoverProvider"), Some(&Json::Bool(true)));
+    assert_eq!(
+        capabilities.get("textDocumentSync").and_then(Json::as_i64),
+        Some(1)
+    );
+    assert_eq!(result(&responses, 2), &Json::Null);
+
+    // shutdown の前の exit は異常終了
+    let (code, _) = session(&[notification("exit", Json::Null)]);
+    assert_eq!(code, 1);
+}
+
+#[test]
+fn did_open_publishes_diagnostics() {
+    let (_, responses) = session(&[did_open()]);
+    let [notification] = responses.as_slice() else {
+        panic!("expected one notification, got {:?}", responses);
+    };
+    assert_eq!(
+        notification.get("method").and_then(Json::as_str),
+        Some("textDocument/publishDiagnostics")
+    );
+    let params = notification.get("params").unwrap();
+    assert_eq!(params.get("uri").and_then(Json::as_str), Some(URI));
+    assert_eq!(
+        params.get("diagnostics").unwrap().to_string(),
+        concat!(
+            r#"[{"range":{"start":{"line":5,"character":13},"end":{"line":5,"character":19}},"#,
+            r#""severity":1,"source":"omochi","message":"Argument 1 of `double` expects `int`, found `str`"},"#,
+            r#"{"range":{"start":{"line":5,"character":4},"end":{"line":5,"character":10}},"#,
+            r#""severity":2,"source":"omochi","message":"unused variable `unused`","code":"unused_variables"}]"#,
+        )
+    );
+}
+
+// `mod` は URI のファイルからの相対パスで読み込み、他のファイルの診断は送らない
+#[test]
+fn modules_are_resolved_relative_to_the_document() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/programs");
+    let open = |uri: &str, text: &str| {
+        let (_, responses) = session(&[notification(
+            "textDocument/didOpen",
+            Json::object([(
+                "textDocument",
+                Json::object([
+                    ("uri", uri.into()),
+                    ("languageId", "omochi".into()),
+                    ("version", 1i64.into()),
+                    ("text", text.into()),
+                ]),
+            )]),
+        )]);
+        responses[0]
+            .get("params")
+            .and_then(|params| params.get("diagnostics"))
+            .unwrap()
+            .to_string()
+    };
+
+    let uri = format!("file://{}/module_panic/main.omo", dir);
+    assert_eq!(
+        open(&uri, "mod util;\nprint util::ratio(10, missing);\n"),
+        concat!(
+            r#"[{"range":{"start":{"line":1,"character":22},"end":{"line":1,"character":29}},"#,
+            r#""severity":1,"source":"omochi","message":"Undefined variable `missing`"}]"#,
+        )
+    );
+    let uri = format!("file://{}/fail_module_type_error/main.omo", dir);
+    assert_eq!(open(&uri, "mod util;\nprint util::g(1);\n"), "[]");
+}
+
+#[test]
+fn navigation() {
+    let (_, responses) = session(&[
+        did_open(),
+        request(1, "textDocument/hover", at(4, 6)),
+        request(2, "textDocument/definition", at(4, 6)),
+        request(3, "textDocument/references", at(0, 3)),
+        request(4, "textDocument/documentSymbol", document()),
+    ]);
+    assert_eq!(
+        result(&responses, 1).to_string(),
+        r#"{"contents":{"kind":"markdown","value":"```omochi\nlet value: int\n```"}}"#
+    );
+    assert_eq!(
+        result(&responses, 2).to_string(),
+        format!(
+            r#"{{"uri":"{}","range":{{"start":{{"line":3,"character":4}},"end":{{"line":3,"character":9}}}}}}"#,
+            URI
+        )
+    );
+    let lines: Vec<i64> = result(&responses, 3)
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|location| {
+            let start = location.get("range").unwrap().get("start").unwrap();
+            start.get("line").and_then(Json::as_i64).unwrap()
+        })
+        .collect();
+    assert_eq!(lines, [0, 3, 5]);
+    assert_eq!(
+        result(&responses, 4).to_string(),
+        concat!(
+            r#"[{"name":"double","detail":"fn double(n: int) -> int","kind":12,"#,
+            r#""range":{"start":{"line":0,"character":3},"end":{"line":2,"character":1}},"#,
+            r#""selectionRange":{"start":{"line":0,"character":3},"end":{"line":0,"character":9}}}]"#,
+        )
+    );
+}
+
+#[test]
+fn completion_lists_symbols_and_keywords() {
+    let (_, responses) = session(&[did_open(), request(1, "textDocument/completion", at(4, 0))]);
+    let labels: Vec<&str> = result(&responses, 1)
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|item| item.get("label").and_then(Json::as_str))
+        .collect();
+    assert_eq!(&labels[..3], ["double", "value", "let"]);
+    assert!(labels.contains(&"extern"));
+}
+
+#[test]
+fn negative_positions_are_invalid_params() {
+    let (_, responses) = session(&[
+        did_open(),
+        request(1, "textDocument/hover", at(-1, 0)),
+        request(2, "textDocument/definition", at(0, -5)),
+    ]);
+    for id in [1, 2] {
+        let response = responses
+            .iter()
+            .find(|response| response.get("id").and_then(Json::as_i64) == Some(id))
+            .unwrap();
+        let error = response.get("error").expect("expected an error");
+        assert_eq!(error.get("code").and_then(Json::as_i64), Some(-32602));
+    }
+}
+
+#[test]
+fn oversized_content_length_is_rejected() {
+    let input = Cursor::new(b"Content-Length: 18446744073709551615\r\n\r\n{}".to_vec());
+    let error = lsp::run(input, Vec::new()).expect_err("must reject the message");
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+}